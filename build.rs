@@ -0,0 +1,67 @@
+//! Packs a Geolonia-format dataset directory into a single JSON snapshot for
+//! the `embedded-dataset` feature.
+//!
+//! Set `EMBEDDED_DATASET_DIR` to a directory containing `ja.json` and
+//! `ja/{prefecture}/{city}.json`. Without it an empty snapshot is embedded,
+//! which the startup completeness check rejects.
+
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::path::Path;
+
+fn main() {
+    println!("cargo:rerun-if-env-changed=EMBEDDED_DATASET_DIR");
+
+    if std::env::var_os("CARGO_FEATURE_EMBEDDED_DATASET").is_none() {
+        return;
+    }
+
+    let out_dir = std::env::var("OUT_DIR").expect("OUT_DIR is set by cargo");
+    let snapshot = match std::env::var("EMBEDDED_DATASET_DIR") {
+        Ok(dir) => {
+            println!("cargo:rerun-if-changed={}", dir);
+            pack(Path::new(&dir))
+        }
+        Err(_) => {
+            println!("cargo:warning=EMBEDDED_DATASET_DIR is not set; embedding an empty dataset");
+            json!([])
+        }
+    };
+
+    std::fs::write(
+        Path::new(&out_dir).join("embedded_dataset.json"),
+        serde_json::to_vec(&snapshot).expect("snapshot serializes"),
+    )
+    .expect("failed to write embedded dataset");
+}
+
+fn pack(dir: &Path) -> Value {
+    let index: HashMap<String, Vec<String>> = read_json(&dir.join("ja.json"));
+
+    let prefectures: Vec<Value> = index
+        .into_iter()
+        .map(|(prefecture, cities)| {
+            let cities: Vec<Value> = cities
+                .into_iter()
+                .map(|city| {
+                    let path = dir
+                        .join("ja")
+                        .join(&prefecture)
+                        .join(format!("{}.json", city));
+                    let towns: Value = read_json(&path);
+                    json!({ "name": city, "towns": towns })
+                })
+                .collect();
+            json!({ "name": prefecture, "cities": cities })
+        })
+        .collect();
+
+    Value::Array(prefectures)
+}
+
+fn read_json<T: serde::de::DeserializeOwned>(path: &Path) -> T {
+    let bytes =
+        std::fs::read(path).unwrap_or_else(|e| panic!("failed to read {}: {}", path.display(), e));
+    serde_json::from_slice(&bytes)
+        .unwrap_or_else(|e| panic!("failed to parse {}: {}", path.display(), e))
+}
@@ -0,0 +1,392 @@
+//! Rendering parse results as address strings.
+//!
+//! The Japanese rendering writes components back in their usual order with a
+//! configurable style for 丁目 numerals and block numbers, so stored addresses
+//! print consistently:
+//!
+//! ```text
+//! 東京都渋谷区神宮前一丁目1番1号 神宮前ビル3階305号室
+//! ```
+//!
+//! The English rendering follows the Western order international carriers
+//! expect, smallest unit first:
+//!
+//! ```text
+//! Room 305, 神宮前ビル
+//! 1-1-1 Jingumae
+//! Shibuya-ku
+//! Tokyo 150-0001
+//! JAPAN
+//! ```
+//!
+//! City and town names are romanized from the postal code dataset readings
+//! and fall back to the Japanese name when no reading is available. Building
+//! names are kept as written.
+
+use crate::normalize::number_to_kanji;
+use crate::postal_code::{self, PostalCodeIndex};
+use crate::readings::{self, capitalize, RomajiStyle};
+use crate::rest_components::RestComponents;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AddressFormat {
+    En,
+}
+
+impl FromStr for AddressFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "en" => Ok(Self::En),
+            other => Err(format!("Invalid format '{}' (expected en)", other)),
+        }
+    }
+}
+
+/// How the number in front of 丁目 is written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChomeNumerals {
+    /// `一丁目`, as town names appear in the master data.
+    #[default]
+    Kanji,
+    /// `1丁目`
+    Arabic,
+}
+
+/// How 番地 and 号 are written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BlockStyle {
+    /// `1-1`
+    #[default]
+    Hyphen,
+    /// `1番1号`, or `1番地` without a 号.
+    Label,
+}
+
+/// Conventions for the Japanese rendering.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub struct JapaneseStyle {
+    #[serde(default)]
+    pub chome_numerals: ChomeNumerals,
+    #[serde(default)]
+    pub block_style: BlockStyle,
+    /// Defaults to true.
+    pub include_prefecture: Option<bool>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FormattedAddress {
+    pub single_line: String,
+    pub lines: Vec<String>,
+}
+
+impl FormattedAddress {
+    fn from_lines(lines: Vec<String>) -> Self {
+        Self {
+            single_line: lines.join(", "),
+            lines,
+        }
+    }
+}
+
+/// The address components a rendering is built from.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Components<'a> {
+    pub prefecture: Option<&'a str>,
+    pub city: Option<&'a str>,
+    pub town: Option<&'a str>,
+    /// Unsplit remainder, used when `rest_components` is not available.
+    pub rest: Option<&'a str>,
+    pub rest_components: Option<&'a RestComponents>,
+    /// Postal code to print; looked up from the postal code dataset if unset.
+    pub postal_code: Option<&'a str>,
+}
+
+/// Renders the components as a single Japanese address string, prefixed with
+/// `〒` and the postal code when one is given.
+pub fn japanese(components: Components, style: JapaneseStyle) -> String {
+    let empty = RestComponents::default();
+    let rest = components.rest_components.unwrap_or(&empty);
+    let (town, town_chome) = match components.town {
+        Some(town) => {
+            let (base, chome) = readings::split_chome(town);
+            (Some(base), chome)
+        }
+        None => (None, None),
+    };
+
+    let mut address = String::new();
+    if let Some(code) = components
+        .postal_code
+        .and_then(postal_code::canonical_postal_code)
+    {
+        address.push_str(&format!("〒{}-{} ", &code[..3], &code[3..]));
+    }
+    if style.include_prefecture.unwrap_or(true) {
+        address.extend(components.prefecture);
+    }
+    address.extend(components.city);
+    address.extend(town);
+
+    let chome = town_chome.or_else(|| rest.chome.as_ref().and_then(|chome| chome.parse().ok()));
+    if let Some(chome) = chome {
+        match style.chome_numerals {
+            ChomeNumerals::Kanji => address.push_str(&number_to_kanji(chome)),
+            ChomeNumerals::Arabic => address.push_str(&chome.to_string()),
+        }
+        address.push_str("丁目");
+    }
+
+    match (style.block_style, &rest.banchi, &rest.go) {
+        (BlockStyle::Hyphen, Some(banchi), Some(go)) => {
+            address.push_str(&format!("{}-{}", banchi, go))
+        }
+        (BlockStyle::Label, Some(banchi), Some(go)) => {
+            address.push_str(&format!("{}番{}号", banchi, go))
+        }
+        (BlockStyle::Hyphen, Some(banchi), None) => address.push_str(banchi),
+        (BlockStyle::Label, Some(banchi), None) => address.push_str(&format!("{}番地", banchi)),
+        (_, None, Some(go)) => address.push_str(&format!("{}号", go)),
+        (_, None, None) => {}
+    }
+    if components.rest_components.is_none() {
+        address.extend(components.rest);
+    }
+
+    let floor = rest
+        .floor
+        .as_ref()
+        .map(|floor| match floor.strip_prefix('B') {
+            Some(basement) => format!("地下{}階", basement),
+            None => format!("{}階", floor),
+        });
+    let room = rest.room.as_ref().map(|room| format!("{}号室", room));
+    let building: String = [rest.building.clone(), floor, room]
+        .into_iter()
+        .flatten()
+        .collect();
+    if !building.is_empty() {
+        address.push(' ');
+        address.push_str(&building);
+    }
+    address.trim().to_string()
+}
+
+/// Renders the components in Western order for international shipping,
+/// with readings and postal codes from `postal_codes` when it is loaded.
+pub fn english(
+    components: Components,
+    style: RomajiStyle,
+    postal_codes: Option<&PostalCodeIndex>,
+) -> FormattedAddress {
+    let empty = RestComponents::default();
+    let rest = components.rest_components.unwrap_or(&empty);
+    let (town, town_chome) = match components.town {
+        Some(town) => {
+            let (base, chome) = readings::split_chome(town);
+            (Some(base), chome)
+        }
+        None => (None, None),
+    };
+    let readings = readings::readings(
+        postal_codes,
+        components.prefecture,
+        components.city,
+        town,
+        style,
+    );
+
+    let mut lines = Vec::new();
+
+    let building: Vec<String> = [
+        rest.room.as_ref().map(|room| format!("Room {}", room)),
+        rest.floor.as_ref().map(|floor| format!("{}F", floor)),
+        rest.building.clone(),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+    lines.push(building.join(", "));
+
+    let block: Vec<String> = [
+        town_chome.map(|chome| chome.to_string()),
+        rest.chome.clone(),
+        rest.banchi.clone(),
+        rest.go.clone(),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+    let unsplit_rest = components
+        .rest
+        .filter(|_| components.rest_components.is_none());
+    let town_name = readings.town_romaji.as_deref().or(town);
+    lines.push(
+        [Some(block.join("-").as_str()), unsplit_rest, town_name]
+            .into_iter()
+            .flatten()
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>()
+            .join(" "),
+    );
+
+    lines.push(
+        readings
+            .city_romaji
+            .as_deref()
+            .or(components.city)
+            .unwrap_or_default()
+            .to_string(),
+    );
+
+    let prefecture = components.prefecture.map(|prefecture| {
+        jisx0401::Prefecture::try_from(prefecture)
+            .map(|p| capitalize(p.name_en()))
+            .unwrap_or_else(|_| prefecture.to_string())
+    });
+    let postal_code = postal_code_for(components, town, postal_codes);
+    lines.push(
+        [prefecture, postal_code]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>()
+            .join(" "),
+    );
+
+    lines.push("JAPAN".to_string());
+    lines.retain(|line| !line.is_empty());
+    FormattedAddress::from_lines(lines)
+}
+
+/// The given postal code as `NNN-NNNN`, or the one the postal code dataset
+/// lists for the town (or the whole city).
+fn postal_code_for(
+    components: Components,
+    town: Option<&str>,
+    postal_codes: Option<&PostalCodeIndex>,
+) -> Option<String> {
+    let code = match components.postal_code {
+        Some(code) => postal_code::canonical_postal_code(code)?,
+        None => {
+            let (prefecture, city) = (components.prefecture?, components.city?);
+            postal_codes?
+                .find_postal_code(prefecture, city, town)?
+                .to_string()
+        }
+    };
+    Some(format!("{}-{}", &code[..3], &code[3..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rest_components::split_rest;
+
+    #[test]
+    fn test_japanese_styles() {
+        let rest = split_rest("1-1 神宮前ビル 3F 305号室", true);
+        let components = Components {
+            prefecture: Some("東京都"),
+            city: Some("渋谷区"),
+            town: Some("神宮前一丁目"),
+            rest_components: Some(&rest),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            japanese(components, JapaneseStyle::default()),
+            "東京都渋谷区神宮前一丁目1-1 神宮前ビル3階305号室"
+        );
+        let style = JapaneseStyle {
+            chome_numerals: ChomeNumerals::Arabic,
+            block_style: BlockStyle::Label,
+            include_prefecture: Some(false),
+        };
+        assert_eq!(
+            japanese(components, style),
+            "渋谷区神宮前1丁目1番1号 神宮前ビル3階305号室"
+        );
+
+        // 丁目 given as a rest component, and 番地 without 号.
+        let rest = RestComponents {
+            chome: Some("2".to_string()),
+            banchi: Some("10".to_string()),
+            floor: Some("B1".to_string()),
+            ..Default::default()
+        };
+        let components = Components {
+            prefecture: Some("東京都"),
+            city: Some("千代田区"),
+            town: Some("丸の内"),
+            rest_components: Some(&rest),
+            postal_code: Some("1000005"),
+            ..Default::default()
+        };
+        let style = JapaneseStyle {
+            block_style: BlockStyle::Label,
+            ..Default::default()
+        };
+        assert_eq!(
+            japanese(components, style),
+            "〒100-0005 東京都千代田区丸の内二丁目10番地 地下1階"
+        );
+    }
+
+    #[test]
+    fn test_english_with_readings() {
+        let index = postal_code::test_index();
+
+        let rest = split_rest("1-1 神宮前ビル 3F 305号室", true);
+        let formatted = english(
+            Components {
+                prefecture: Some("東京都"),
+                city: Some("渋谷区"),
+                town: Some("神宮前一丁目"),
+                rest: Some("1-1 神宮前ビル 3F 305号室"),
+                rest_components: Some(&rest),
+                postal_code: None,
+            },
+            RomajiStyle::Hepburn,
+            Some(&index),
+        );
+        assert_eq!(
+            formatted.lines,
+            vec![
+                "Room 305, 3F, 神宮前ビル",
+                "1-1-1 Jingumae",
+                "Shibuya-ku",
+                "Tokyo 150-0001",
+                "JAPAN"
+            ]
+        );
+        assert_eq!(
+            formatted.single_line,
+            "Room 305, 3F, 神宮前ビル, 1-1-1 Jingumae, Shibuya-ku, Tokyo 150-0001, JAPAN"
+        );
+    }
+
+    #[test]
+    fn test_english_without_readings() {
+        let formatted = english(
+            Components {
+                prefecture: Some("大阪府"),
+                city: Some("大阪市北区"),
+                rest: Some("梅田3-1-1"),
+                postal_code: Some("〒530-0001"),
+                ..Default::default()
+            },
+            RomajiStyle::Hepburn,
+            None,
+        );
+        assert_eq!(
+            formatted.lines,
+            vec!["梅田3-1-1", "大阪市北区", "Osaka 530-0001", "JAPAN"]
+        );
+    }
+}
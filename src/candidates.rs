@@ -0,0 +1,338 @@
+//! Alternative interpretations of addresses with omitted components.
+//!
+//! The parser needs the prefecture to resolve anything and the city to
+//! resolve a town, so `渋谷区神宮前1-1-1` or `神宮前1-1-1` fail or stop early
+//! even though the place is findable. These inputs are matched against the
+//! city and town names of the offline dataset and the postal code dataset,
+//! and every place they could belong to becomes an interpretation with the
+//! missing components filled in.
+//!
+//! Without an offline dataset, city and town names come from the prefecture
+//! and city masters fetched through [`MasterDataClient`] instead; see
+//! [`fetch_masters`].
+
+use crate::dataset::{self, City, Dataset, Prefecture, Town};
+use crate::master_data::MasterDataClient;
+use crate::postal_code::PostalCodeIndex;
+use crate::readings::split_chome;
+use futures::stream::{self, StreamExt};
+use japanese_address_parser::http::client::ApiClient;
+use std::collections::HashSet;
+
+/// Masters fetched at once by [`fetch_masters`].
+const FETCH_CONCURRENCY: usize = 8;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Interpretation {
+    /// The input with the inferred components prepended.
+    pub address: String,
+    /// Number of components that were filled in.
+    pub inferred: usize,
+    /// Why this interpretation was considered.
+    pub reason: String,
+}
+
+/// Places an address may be in, from whichever datasets are loaded.
+#[derive(Clone, Copy, Default)]
+pub struct Places<'a> {
+    pub dataset: Option<&'a Dataset>,
+    pub postal_codes: Option<&'a PostalCodeIndex>,
+    /// Masters fetched by [`fetch_masters`] when no dataset is loaded.
+    pub masters: &'a [Prefecture],
+}
+
+/// Finds interpretations for `address`, given what the parser resolved from
+/// it as written. The most plausible come first: those inferring fewer
+/// components, then those matching a longer name.
+pub fn interpretations(
+    places: Places,
+    address: &str,
+    prefecture: Option<&str>,
+    city: Option<&str>,
+) -> Vec<Interpretation> {
+    let mut found = match (prefecture, city) {
+        (None, _) => {
+            let by_city = by_city(places, address);
+            if by_city.is_empty() {
+                by_town(places, address, None)
+            } else {
+                by_city
+            }
+        }
+        (Some(prefecture), None) => {
+            let rest = address.strip_prefix(prefecture).unwrap_or(address);
+            by_town(places, rest, Some(prefecture))
+        }
+        (Some(_), Some(_)) => Vec::new(),
+    };
+    found.sort_by_key(|(matched, interpretation)| {
+        (interpretation.inferred, std::cmp::Reverse(*matched))
+    });
+    found
+        .into_iter()
+        .map(|(_, interpretation)| interpretation)
+        .collect()
+}
+
+/// Fetches the masters [`interpretations`] needs when no offline dataset is
+/// loaded: the city lists of every prefecture, or, once the prefecture is
+/// known, the town lists of its cities. Towns are therefore only found
+/// remotely when the address names its prefecture. Masters that cannot be
+/// fetched are skipped.
+pub async fn fetch_masters(client: &MasterDataClient, prefecture: Option<&str>) -> Vec<Prefecture> {
+    let Some(prefecture) = prefecture else {
+        let names: Vec<String> = jisx0401::Prefecture::values()
+            .map(|prefecture| prefecture.name_ja().to_string())
+            .collect();
+        return stream::iter(names)
+            .map(|name| fetch_cities(client, name))
+            .buffer_unordered(FETCH_CONCURRENCY)
+            .filter_map(|prefecture| async move { prefecture })
+            .collect()
+            .await;
+    };
+
+    let Some(master) = fetch_cities(client, prefecture.to_string()).await else {
+        return Vec::new();
+    };
+    let cities = stream::iter(master.cities)
+        .map(|city| fetch_towns(client, prefecture, city.name))
+        .buffered(FETCH_CONCURRENCY)
+        .filter_map(|city| async move { city })
+        .collect()
+        .await;
+    vec![Prefecture {
+        name: master.name,
+        cities,
+    }]
+}
+
+/// A prefecture with its cities, which have no towns yet.
+async fn fetch_cities(client: &MasterDataClient, prefecture: String) -> Option<Prefecture> {
+    let master: dataset::PrefectureMaster = client
+        .fetch(&dataset::prefecture_master_url(&prefecture))
+        .await
+        .ok()?;
+    let cities = master
+        .cities
+        .into_iter()
+        .map(|name| City {
+            name,
+            towns: Vec::new(),
+        })
+        .collect();
+    Some(Prefecture {
+        name: prefecture,
+        cities,
+    })
+}
+
+async fn fetch_towns(client: &MasterDataClient, prefecture: &str, city: String) -> Option<City> {
+    let towns: Vec<Town> = client
+        .fetch(&dataset::city_master_url(prefecture, &city))
+        .await
+        .ok()?;
+    Some(City { name: city, towns })
+}
+
+/// Prefectures of every city the address starts with, each with the length
+/// of the matched name.
+fn by_city(places: Places, address: &str) -> Vec<(usize, Interpretation)> {
+    let mut seen = HashSet::new();
+    let mut interpretations = Vec::new();
+    for (prefecture, city, _) in places.iter() {
+        if address.starts_with(city) && seen.insert((prefecture, city)) {
+            let interpretation = Interpretation {
+                address: format!("{}{}", prefecture, address),
+                inferred: 1,
+                reason: format!("prefecture {} inferred from city {}", prefecture, city),
+            };
+            interpretations.push((city.chars().count(), interpretation));
+        }
+    }
+    interpretations
+}
+
+/// Cities (and prefectures, unless given) of every town the address starts
+/// with, each with the length of the matched name.
+fn by_town(
+    places: Places,
+    address: &str,
+    prefecture: Option<&str>,
+) -> Vec<(usize, Interpretation)> {
+    let mut seen = HashSet::new();
+    let mut interpretations = Vec::new();
+    for (place_prefecture, city, town) in places.iter() {
+        let Some(town) = town else {
+            continue;
+        };
+        if prefecture.is_some_and(|prefecture| prefecture != place_prefecture)
+            || !address.starts_with(town)
+            || !seen.insert((place_prefecture, city))
+        {
+            continue;
+        }
+        let (inferred, reason) = match prefecture {
+            Some(_) => (1, format!("city {} inferred from town {}", city, town)),
+            None => (
+                2,
+                format!(
+                    "prefecture {} and city {} inferred from town {}",
+                    place_prefecture, city, town
+                ),
+            ),
+        };
+        let interpretation = Interpretation {
+            address: format!("{}{}{}", place_prefecture, city, address),
+            inferred,
+            reason,
+        };
+        interpretations.push((town.chars().count(), interpretation));
+    }
+    interpretations
+}
+
+impl<'a> Places<'a> {
+    /// Every known (prefecture, city, town), with 丁目 dropped from town
+    /// names so `神宮前1-1-1` matches `神宮前一丁目`. Cities are also listed
+    /// on their own with no town.
+    fn iter(self) -> impl Iterator<Item = (&'a str, &'a str, Option<&'a str>)> {
+        let prefectures = self
+            .dataset
+            .map(Dataset::prefectures)
+            .unwrap_or_default()
+            .iter()
+            .chain(self.masters);
+        let from_masters = prefectures.flat_map(|prefecture| {
+            prefecture.cities.iter().flat_map(move |city| {
+                let towns = city
+                    .towns
+                    .iter()
+                    .map(|town| Some(split_chome(&town.town).0));
+                std::iter::once(None)
+                    .chain(towns)
+                    .map(move |town| (prefecture.name.as_str(), city.name.as_str(), town))
+            })
+        });
+        let from_postal_codes = self
+            .postal_codes
+            .into_iter()
+            .flat_map(|index| index.entries())
+            .flat_map(|entry| {
+                let (prefecture, city) = (entry.prefecture.as_str(), entry.city.as_str());
+                [
+                    (prefecture, city, None),
+                    (prefecture, city, entry.town.as_deref()),
+                ]
+            });
+        from_masters.chain(from_postal_codes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dataset;
+
+    #[test]
+    fn test_interpretations() {
+        let dataset = dataset::test_dataset();
+        let places = Places {
+            dataset: Some(&dataset),
+            ..Default::default()
+        };
+
+        let found = interpretations(places, "渋谷区神宮前1-1-1", None, None);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].address, "東京都渋谷区神宮前1-1-1");
+        assert_eq!(
+            found[0].reason,
+            "prefecture 東京都 inferred from city 渋谷区"
+        );
+
+        let found = interpretations(places, "東京都丸の内1-9-1", Some("東京都"), None);
+        assert_eq!(found[0].address, "東京都千代田区丸の内1-9-1");
+        assert_eq!(found[0].inferred, 1);
+
+        let found = interpretations(places, "神宮前1-1-1", None, None);
+        assert_eq!(found[0].address, "東京都渋谷区神宮前1-1-1");
+        assert_eq!(found[0].inferred, 2);
+
+        assert!(interpretations(
+            places,
+            "東京都渋谷区神宮前1-1-1",
+            Some("東京都"),
+            Some("渋谷区")
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn test_interpretations_from_masters_most_plausible_first() {
+        let city = |name: &str, towns: &[&str]| City {
+            name: name.to_string(),
+            towns: towns
+                .iter()
+                .map(|town| Town {
+                    town: town.to_string(),
+                    koaza: String::new(),
+                    lat: None,
+                    lng: None,
+                })
+                .collect(),
+        };
+        let masters = [
+            Prefecture {
+                name: "北海道".to_string(),
+                cities: vec![city("伊達市", &[])],
+            },
+            Prefecture {
+                name: "福島県".to_string(),
+                cities: vec![city("伊達市", &[]), city("伊達郡川俣町", &[])],
+            },
+            Prefecture {
+                name: "東京都".to_string(),
+                cities: vec![city("港区", &["神宮"]), city("渋谷区", &["神宮前一丁目"])],
+            },
+        ];
+        let places = Places {
+            masters: &masters,
+            ..Default::default()
+        };
+
+        let found = interpretations(places, "伊達郡川俣町五百田1", None, None);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].address, "福島県伊達郡川俣町五百田1");
+
+        let found: Vec<String> = interpretations(places, "伊達市梁川町1", None, None)
+            .into_iter()
+            .map(|interpretation| interpretation.address)
+            .collect();
+        assert_eq!(found, ["北海道伊達市梁川町1", "福島県伊達市梁川町1"]);
+
+        // The longer town name comes first, whatever the master order.
+        let found = interpretations(places, "東京都神宮前1-1-1", Some("東京都"), None);
+        assert_eq!(found[0].address, "東京都渋谷区神宮前1-1-1");
+        assert_eq!(found[1].address, "東京都港区神宮前1-1-1");
+    }
+
+    #[tokio::test]
+    async fn test_fetch_masters() {
+        let client = MasterDataClient::with_dataset(Some(dataset::test_dataset()));
+
+        let masters = fetch_masters(&client, None).await;
+        assert!(masters.iter().any(|prefecture| prefecture.name == "東京都"));
+        assert!(masters
+            .iter()
+            .flat_map(|prefecture| &prefecture.cities)
+            .all(|city| city.towns.is_empty()));
+
+        let masters = fetch_masters(&client, Some("東京都")).await;
+        let places = Places {
+            masters: &masters,
+            ..Default::default()
+        };
+        let found = interpretations(places, "東京都神宮前1-1-1", Some("東京都"), None);
+        assert_eq!(found[0].address, "東京都渋谷区神宮前1-1-1");
+    }
+}
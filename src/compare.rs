@@ -0,0 +1,208 @@
+//! Deciding whether two parsed addresses refer to the same place.
+//!
+//! Both sides are compared level by level after normalization: 丁目 counts
+//! as part of the town whether the parser put it in the town name
+//! (`神宮前一丁目`) or left it in the block numbers (`1-1-1`), and building
+//! names are compared ignoring width, case and whitespace.
+
+use crate::address_format::Components;
+use crate::normalize::normalize_characters;
+use crate::readings::split_chome;
+use serde::Serialize;
+
+/// Weight of each level in the similarity score, in `Levels` order.
+const WEIGHTS: [f64; 6] = [0.2, 0.2, 0.25, 0.2, 0.1, 0.05];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LevelMatch {
+    /// Both sides agree, including when neither has the level.
+    Match,
+    /// Both sides have the level and it differs.
+    Mismatch,
+    /// Only one side has the level.
+    Missing,
+}
+
+impl LevelMatch {
+    fn of(a: Option<String>, b: Option<String>) -> Self {
+        match (a, b) {
+            (a, b) if a == b => Self::Match,
+            (Some(_), Some(_)) => Self::Mismatch,
+            _ => Self::Missing,
+        }
+    }
+
+    fn score(&self) -> f64 {
+        match self {
+            Self::Match => 1.0,
+            Self::Missing => 0.5,
+            Self::Mismatch => 0.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Levels {
+    pub prefecture: LevelMatch,
+    pub city: LevelMatch,
+    pub town: LevelMatch,
+    /// 番地 and 号.
+    pub block: LevelMatch,
+    /// Building name and floor.
+    pub building: LevelMatch,
+    pub room: LevelMatch,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Comparison {
+    /// Same prefecture, city, town and block, with no conflicting building or
+    /// room. A building or room given on one side only does not count against
+    /// it.
+    pub equivalent: bool,
+    /// Weighted agreement across levels, from 0 to 1.
+    pub score: f64,
+    pub levels: Levels,
+}
+
+pub fn compare(a: Components, b: Components) -> Comparison {
+    let levels = Levels {
+        prefecture: LevelMatch::of(
+            a.prefecture.map(str::to_string),
+            b.prefecture.map(str::to_string),
+        ),
+        city: LevelMatch::of(a.city.map(str::to_string), b.city.map(str::to_string)),
+        town: LevelMatch::of(town_key(a), town_key(b)),
+        block: LevelMatch::of(block_key(a), block_key(b)),
+        building: LevelMatch::of(building_key(a), building_key(b)),
+        room: LevelMatch::of(
+            a.rest_components.and_then(|rest| rest.room.clone()),
+            b.rest_components.and_then(|rest| rest.room.clone()),
+        ),
+    };
+
+    let all = [
+        levels.prefecture,
+        levels.city,
+        levels.town,
+        levels.block,
+        levels.building,
+        levels.room,
+    ];
+    let score: f64 = all
+        .iter()
+        .zip(WEIGHTS)
+        .map(|(level, weight)| level.score() * weight)
+        .sum();
+    let equivalent = a.prefecture.is_some()
+        && all[..4].iter().all(|level| *level == LevelMatch::Match)
+        && all[4..].iter().all(|level| *level != LevelMatch::Mismatch);
+
+    Comparison {
+        equivalent,
+        score: (score * 1000.0).round() / 1000.0,
+        levels,
+    }
+}
+
+/// Town name with its 丁目 in Arabic digits (`神宮前1丁目`). When the town was
+/// not resolved, the unparsed remainder stands in so that two different
+/// unresolved addresses do not compare equal.
+fn town_key(side: Components) -> Option<String> {
+    let Some(town) = side.town else {
+        return side.rest.map(compact);
+    };
+    let (base, chome) = split_chome(town);
+    let chome = chome.or_else(|| side.rest_components?.chome.as_ref()?.parse().ok());
+    Some(match chome {
+        Some(chome) => format!("{}{}丁目", base, chome),
+        None => base.to_string(),
+    })
+}
+
+fn block_key(side: Components) -> Option<String> {
+    let rest = side.rest_components?;
+    match (&rest.banchi, &rest.go) {
+        (None, None) => None,
+        (banchi, go) => Some(format!(
+            "{}-{}",
+            banchi.as_deref().unwrap_or_default(),
+            go.as_deref().unwrap_or_default()
+        )),
+    }
+}
+
+fn building_key(side: Components) -> Option<String> {
+    let rest = side.rest_components?;
+    match (&rest.building, &rest.floor) {
+        (None, None) => None,
+        (building, floor) => Some(format!(
+            "{}|{}",
+            building.as_deref().map(compact).unwrap_or_default(),
+            floor.as_deref().unwrap_or_default()
+        )),
+    }
+}
+
+/// Width-normalized, lowercased text without whitespace.
+fn compact(text: &str) -> String {
+    normalize_characters(text)
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rest_components::{split_rest, RestComponents};
+
+    fn side<'a>(town: &'a str, rest: &'a RestComponents) -> Components<'a> {
+        Components {
+            prefecture: Some("東京都"),
+            city: Some("渋谷区"),
+            town: Some(town),
+            rest_components: Some(rest),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_compare_chome_in_town_or_rest() {
+        let a_rest = split_rest("1番1号", true);
+        let b_rest = split_rest("1-1-1", false);
+        let comparison = compare(side("神宮前一丁目", &a_rest), side("神宮前", &b_rest));
+        assert!(comparison.equivalent);
+        assert_eq!(comparison.score, 1.0);
+    }
+
+    #[test]
+    fn test_compare_levels() {
+        let a_rest = split_rest("1-1 神宮前ビル 305号室", true);
+        let b_rest = split_rest("1-1 ｼﾞﾝｸﾞｳﾏｴ ﾋﾞﾙ", true);
+        let comparison = compare(side("神宮前一丁目", &a_rest), side("神宮前一丁目", &b_rest));
+        assert_eq!(comparison.levels.block, LevelMatch::Match);
+        assert_eq!(comparison.levels.building, LevelMatch::Mismatch);
+        assert_eq!(comparison.levels.room, LevelMatch::Missing);
+        assert!(!comparison.equivalent);
+        assert_eq!(comparison.score, 0.875);
+
+        let b_rest = split_rest("1-2", true);
+        let comparison = compare(side("神宮前一丁目", &a_rest), side("神宮前二丁目", &b_rest));
+        assert_eq!(comparison.levels.town, LevelMatch::Mismatch);
+        assert_eq!(comparison.levels.block, LevelMatch::Mismatch);
+    }
+
+    #[test]
+    fn test_compare_unresolved_addresses() {
+        let unresolved = |rest| Components {
+            rest: Some(rest),
+            ..Default::default()
+        };
+        let comparison = compare(unresolved("foo"), unresolved("foo"));
+        assert!(!comparison.equivalent);
+        let comparison = compare(unresolved("foo"), unresolved("bar"));
+        assert_eq!(comparison.levels.town, LevelMatch::Mismatch);
+    }
+}
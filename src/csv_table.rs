@@ -0,0 +1,232 @@
+//! Reading and writing the CSV files of `/parse/csv`.
+//!
+//! Spreadsheet exports come in UTF-8, often with a byte order mark, or in
+//! Shift_JIS from Japanese Excel. The output is written back in the encoding,
+//! byte order mark and line endings of the input so it opens the same way.
+
+use std::str::FromStr;
+
+/// Headers recognized as the address column when none is given.
+const ADDRESS_HEADERS: [&str; 2] = ["address", "住所"];
+
+const BOM: &str = "\u{feff}";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Utf8,
+    ShiftJis,
+}
+
+impl Encoding {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Utf8 => "utf-8",
+            Self::ShiftJis => "shift_jis",
+        }
+    }
+}
+
+impl FromStr for Encoding {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "utf-8" | "utf8" => Ok(Self::Utf8),
+            "shift_jis" | "sjis" | "cp932" => Ok(Self::ShiftJis),
+            other => Err(format!(
+                "Invalid encoding '{}' (expected utf-8 or shift_jis)",
+                other
+            )),
+        }
+    }
+}
+
+/// Which column holds the address.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Column {
+    /// A header name.
+    Name(String),
+    /// A 0-based position.
+    Index(usize),
+}
+
+impl FromStr for Column {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        Ok(match value.parse() {
+            Ok(index) => Self::Index(index),
+            Err(_) => Self::Name(value.to_string()),
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct Table {
+    pub encoding: Encoding,
+    bom: bool,
+    crlf: bool,
+    pub header: Option<csv::StringRecord>,
+    pub rows: Vec<csv::StringRecord>,
+}
+
+impl Table {
+    /// Decodes and reads a CSV file. Without an explicit encoding, UTF-8 is
+    /// assumed when the bytes are valid UTF-8 and Shift_JIS otherwise.
+    pub fn read(
+        bytes: &[u8],
+        encoding: Option<Encoding>,
+        has_headers: bool,
+    ) -> Result<Self, String> {
+        let encoding = encoding.unwrap_or(match std::str::from_utf8(bytes) {
+            Ok(_) => Encoding::Utf8,
+            Err(_) => Encoding::ShiftJis,
+        });
+        let text = match encoding {
+            Encoding::Utf8 => std::str::from_utf8(bytes)
+                .map(std::borrow::Cow::Borrowed)
+                .map_err(|_| "CSV is not valid UTF-8".to_string())?,
+            Encoding::ShiftJis => encoding_rs::SHIFT_JIS.decode(bytes).0,
+        };
+        let (bom, text) = match text.strip_prefix(BOM) {
+            Some(text) => (true, text),
+            None => (false, text.as_ref()),
+        };
+
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_reader(text.as_bytes());
+        let mut rows = reader
+            .records()
+            .enumerate()
+            .map(|(line, record)| record.map_err(|e| format!("CSV line {}: {}", line + 1, e)))
+            .collect::<Result<Vec<_>, _>>()?;
+        let header = (has_headers && !rows.is_empty()).then(|| rows.remove(0));
+        Ok(Self {
+            encoding,
+            bom,
+            crlf: text.contains("\r\n"),
+            header,
+            rows,
+        })
+    }
+
+    /// Resolves the address column: the given one, or else the first header
+    /// named `address` or `住所`, or else the first column.
+    pub fn address_column(&self, column: Option<&Column>) -> Result<usize, String> {
+        let find = |name: &str| {
+            self.header
+                .as_ref()
+                .and_then(|header| header.iter().position(|field| field.trim() == name))
+        };
+        match column {
+            Some(Column::Index(index)) => Ok(*index),
+            Some(Column::Name(name)) => {
+                find(name).ok_or_else(|| format!("No column named '{}' in the CSV header", name))
+            }
+            None => Ok(ADDRESS_HEADERS
+                .iter()
+                .find_map(|name| find(name))
+                .unwrap_or(0)),
+        }
+    }
+
+    /// Writes the table back with `appended` fields added to the header and
+    /// each row, in the input's encoding. Short rows are padded to the width
+    /// of the header (or of the widest row) so appended fields line up.
+    pub fn write(&self, appended_header: &[&str], appended: Vec<Vec<String>>) -> Vec<u8> {
+        let terminator = if self.crlf {
+            csv::Terminator::CRLF
+        } else {
+            csv::Terminator::Any(b'\n')
+        };
+        let mut writer = csv::WriterBuilder::new()
+            .flexible(true)
+            .terminator(terminator)
+            .from_writer(Vec::new());
+        if let Some(header) = &self.header {
+            let _ = writer.write_record(header.iter().chain(appended_header.iter().copied()));
+        }
+        let width = match &self.header {
+            Some(header) => header.len(),
+            None => self
+                .rows
+                .iter()
+                .map(csv::StringRecord::len)
+                .max()
+                .unwrap_or(0),
+        };
+        for (row, fields) in self.rows.iter().zip(appended) {
+            let padding = std::iter::repeat_n("", width.saturating_sub(row.len()));
+            let _ = writer.write_record(
+                row.iter()
+                    .chain(padding)
+                    .chain(fields.iter().map(String::as_str)),
+            );
+        }
+        let text = String::from_utf8(writer.into_inner().unwrap_or_default()).unwrap_or_default();
+        let text = if self.bom {
+            format!("{}{}", BOM, text)
+        } else {
+            text
+        };
+        match self.encoding {
+            Encoding::Utf8 => text.into_bytes(),
+            Encoding::ShiftJis => encoding_rs::SHIFT_JIS.encode(&text).0.into_owned(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip_shift_jis() {
+        let input = "id,住所,memo\r\n1,東京都渋谷区神宮前1-1-1,\"a, b\"\r\n";
+        let (encoded, _, _) = encoding_rs::SHIFT_JIS.encode(input);
+        let table = Table::read(&encoded, None, true).unwrap();
+        assert_eq!(table.encoding, Encoding::ShiftJis);
+        assert_eq!(table.address_column(None), Ok(1));
+        assert_eq!(&table.rows[0][1], "東京都渋谷区神宮前1-1-1");
+
+        let output = table.write(&["prefecture"], vec![vec!["東京都".to_string()]]);
+        let (decoded, _, _) = encoding_rs::SHIFT_JIS.decode(&output);
+        assert_eq!(
+            decoded,
+            "id,住所,memo,prefecture\r\n1,東京都渋谷区神宮前1-1-1,\"a, b\",東京都\r\n"
+        );
+    }
+
+    #[test]
+    fn test_columns_and_bom() {
+        let table = Table::read("\u{feff}name,addr\nx,y\n".as_bytes(), None, true).unwrap();
+        assert_eq!(table.address_column(None), Ok(0));
+        assert_eq!(table.address_column(Some(&"addr".parse().unwrap())), Ok(1));
+        assert!(table
+            .address_column(Some(&"住所".parse().unwrap()))
+            .is_err());
+
+        let output = table.write(&["city"], vec![vec![String::new()]]);
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "\u{feff}name,addr,city\nx,y,\n"
+        );
+
+        // Ragged rows are padded so appended fields stay under their headers.
+        let table = Table::read(b"id,address,memo\n1,x\n2,y,z\n", None, true).unwrap();
+        let output = table.write(
+            &["city"],
+            vec![vec!["a".to_string()], vec!["b".to_string()]],
+        );
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "id,address,memo,city\n1,x,,a\n2,y,z,b\n"
+        );
+
+        let table = Table::read(b"a\nb\n", Some(Encoding::Utf8), false).unwrap();
+        assert!(table.header.is_none());
+        assert_eq!(table.rows.len(), 2);
+    }
+}
@@ -0,0 +1,369 @@
+//! Local address dataset for offline operation.
+//!
+//! The dataset uses the layout of the Geolonia `japanese-addresses` API:
+//! `ja.json` maps every prefecture to its cities, and `ja/{prefecture}/{city}.json`
+//! lists the towns of each city. When a dataset is loaded, every master data
+//! request the parsers make is answered from memory instead of the network.
+
+use japanese_address_parser::http::error::ApiClientError;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use std::path::Path;
+#[cfg(test)]
+use std::sync::Arc;
+
+/// Base URL the upstream parser fetches prefecture masters from.
+const PREFECTURE_MASTER_URL: &str =
+    "https://yuukitoriyama.github.io/geolonia-japanese-addresses-accompanist/";
+/// Base URL the upstream parser fetches city (town list) masters from.
+const CITY_MASTER_URL: &str = "https://geolonia.github.io/japanese-addresses/api/ja/";
+
+/// URL of the city list the upstream parser fetches for a prefecture.
+pub fn prefecture_master_url(prefecture: &str) -> String {
+    format!("{}{}/master.json", PREFECTURE_MASTER_URL, prefecture)
+}
+
+/// URL of the town list the upstream parser fetches for a city.
+pub fn city_master_url(prefecture: &str, city: &str) -> String {
+    format!("{}{}/{}.json", CITY_MASTER_URL, prefecture, city)
+}
+
+/// Maximum number of problems listed in a validation error.
+const MAX_REPORTED_PROBLEMS: usize = 5;
+
+#[cfg(feature = "embedded-dataset")]
+static EMBEDDED_DATASET: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/embedded_dataset.json"));
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Town {
+    pub town: String,
+    #[serde(default)]
+    pub koaza: String,
+    pub lat: Option<f64>,
+    pub lng: Option<f64>,
+}
+
+/// City list of a prefecture, as served at [`prefecture_master_url`].
+#[derive(Debug, Clone, Deserialize)]
+pub struct PrefectureMaster {
+    pub cities: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct City {
+    pub name: String,
+    pub towns: Vec<Town>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Prefecture {
+    pub name: String,
+    pub cities: Vec<City>,
+}
+
+/// Where a dataset was loaded from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DatasetSource {
+    Local,
+    #[cfg(feature = "embedded-dataset")]
+    Embedded,
+}
+
+impl DatasetSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Local => "local",
+            #[cfg(feature = "embedded-dataset")]
+            Self::Embedded => "embedded",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Dataset {
+    source: DatasetSource,
+    prefectures: Vec<Prefecture>,
+}
+
+impl Dataset {
+    fn new(source: DatasetSource, mut prefectures: Vec<Prefecture>) -> Self {
+        // Keep prefectures in JIS X 0401 order regardless of how they were stored.
+        prefectures.sort_by_key(|prefecture| {
+            jisx0401::Prefecture::try_from(prefecture.name.as_str())
+                .map(|p| p.code())
+                .unwrap_or("99")
+        });
+        Self {
+            source,
+            prefectures,
+        }
+    }
+
+    /// Loads a dataset from a directory laid out like the Geolonia API.
+    pub fn load_dir(dir: &Path) -> Result<Self, String> {
+        let index_path = dir.join("ja.json");
+        let index = std::fs::read(&index_path)
+            .map_err(|e| format!("Failed to read {}: {}", index_path.display(), e))?;
+        let index: HashMap<String, Vec<String>> = serde_json::from_slice(&index)
+            .map_err(|e| format!("Failed to parse {}: {}", index_path.display(), e))?;
+
+        let mut problems = Vec::new();
+        let mut prefectures = Vec::with_capacity(index.len());
+        for (prefecture_name, city_names) in index {
+            let mut cities = Vec::with_capacity(city_names.len());
+            for city_name in city_names {
+                let path = dir
+                    .join("ja")
+                    .join(&prefecture_name)
+                    .join(format!("{}.json", city_name));
+                let towns = std::fs::read(&path)
+                    .map_err(|e| e.to_string())
+                    .and_then(|bytes| {
+                        serde_json::from_slice::<Vec<Town>>(&bytes).map_err(|e| e.to_string())
+                    });
+                match towns {
+                    Ok(towns) => cities.push(City {
+                        name: city_name,
+                        towns,
+                    }),
+                    Err(e) => problems.push(format!("{}: {}", path.display(), e)),
+                }
+            }
+            prefectures.push(Prefecture {
+                name: prefecture_name,
+                cities,
+            });
+        }
+
+        if !problems.is_empty() {
+            return Err(describe_problems("unreadable town files", &problems));
+        }
+
+        Ok(Self::new(DatasetSource::Local, prefectures))
+    }
+
+    /// Loads the snapshot compiled in with the `embedded-dataset` feature.
+    #[cfg(feature = "embedded-dataset")]
+    pub fn load_embedded() -> Result<Self, String> {
+        let prefectures: Vec<Prefecture> = serde_json::from_slice(EMBEDDED_DATASET)
+            .map_err(|e| format!("Failed to parse embedded dataset: {}", e))?;
+        Ok(Self::new(DatasetSource::Embedded, prefectures))
+    }
+
+    /// Checks that every prefecture is present and has cities with towns.
+    pub fn validate(&self) -> Result<(), String> {
+        let mut problems: Vec<String> = jisx0401::Prefecture::values()
+            .filter(|prefecture| self.prefecture(prefecture.name_ja()).is_none())
+            .map(|prefecture| format!("missing prefecture {}", prefecture.name_ja()))
+            .collect();
+
+        for prefecture in &self.prefectures {
+            if prefecture.cities.is_empty() {
+                problems.push(format!("{} has no cities", prefecture.name));
+            }
+            for city in &prefecture.cities {
+                if city.towns.is_empty() {
+                    problems.push(format!("{}{} has no towns", prefecture.name, city.name));
+                }
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(describe_problems("incomplete dataset", &problems))
+        }
+    }
+
+    pub fn source(&self) -> DatasetSource {
+        self.source
+    }
+
+    pub fn prefectures(&self) -> &[Prefecture] {
+        &self.prefectures
+    }
+
+    pub fn prefecture(&self, name: &str) -> Option<&Prefecture> {
+        self.prefectures.iter().find(|p| p.name == name)
+    }
+
+    pub fn city(&self, prefecture_name: &str, city_name: &str) -> Option<&City> {
+        self.prefecture(prefecture_name)?
+            .cities
+            .iter()
+            .find(|c| c.name == city_name)
+    }
+
+    pub fn town_count(&self) -> usize {
+        self.prefectures
+            .iter()
+            .flat_map(|p| &p.cities)
+            .map(|c| c.towns.len())
+            .sum()
+    }
+
+    /// Answers a master data request the upstream parser would send to `url`.
+    pub fn lookup(&self, url: &str) -> Result<serde_json::Value, ApiClientError> {
+        let not_found = |message: &str| ApiClientError::Request {
+            url: url.to_string(),
+            message: message.to_string(),
+        };
+
+        if let Some(prefecture_name) = url
+            .strip_prefix(PREFECTURE_MASTER_URL)
+            .and_then(|path| path.strip_suffix("/master.json"))
+        {
+            let prefecture = self
+                .prefecture(prefecture_name)
+                .ok_or_else(|| not_found("prefecture not found in local dataset"))?;
+            let cities: Vec<&str> = prefecture.cities.iter().map(|c| c.name.as_str()).collect();
+            return Ok(json!({ "name": prefecture.name, "cities": cities }));
+        }
+
+        if let Some((prefecture_name, city_name)) = url
+            .strip_prefix(CITY_MASTER_URL)
+            .and_then(|path| path.strip_suffix(".json"))
+            .and_then(|path| path.split_once('/'))
+        {
+            let city = self
+                .city(prefecture_name, city_name)
+                .ok_or_else(|| not_found("city not found in local dataset"))?;
+            return serde_json::to_value(&city.towns).map_err(|e| ApiClientError::Deserialize {
+                url: url.to_string(),
+                message: e.to_string(),
+            });
+        }
+
+        Err(not_found("resource is not available in offline mode"))
+    }
+}
+
+fn describe_problems(kind: &str, problems: &[String]) -> String {
+    let shown: Vec<&str> = problems
+        .iter()
+        .take(MAX_REPORTED_PROBLEMS)
+        .map(String::as_str)
+        .collect();
+    let more = problems.len().saturating_sub(MAX_REPORTED_PROBLEMS);
+    if more > 0 {
+        format!("{}: {} (and {} more)", kind, shown.join("; "), more)
+    } else {
+        format!("{}: {}", kind, shown.join("; "))
+    }
+}
+
+/// Loads the dataset selected by `DATASET_MODE`.
+///
+/// Returns `Ok(None)` in the default `remote` mode. Any other mode fails if the
+/// dataset cannot be loaded or does not cover every prefecture.
+pub fn init_from_env() -> Result<Option<Dataset>, String> {
+    let mode = std::env::var("DATASET_MODE").unwrap_or_else(|_| "remote".to_string());
+    let dataset = match mode.as_str() {
+        "remote" => return Ok(None),
+        "local" => {
+            let dir = std::env::var("DATASET_DIR")
+                .map_err(|_| "DATASET_DIR must be set when DATASET_MODE=local".to_string())?;
+            Dataset::load_dir(Path::new(&dir))?
+        }
+        #[cfg(feature = "embedded-dataset")]
+        "embedded" => Dataset::load_embedded()?,
+        #[cfg(not(feature = "embedded-dataset"))]
+        "embedded" => {
+            return Err(
+                "DATASET_MODE=embedded requires building with the embedded-dataset feature"
+                    .to_string(),
+            )
+        }
+        other => {
+            return Err(format!(
+                "Invalid DATASET_MODE '{}' (expected remote, local or embedded)",
+                other
+            ))
+        }
+    };
+
+    dataset.validate()?;
+    Ok(Some(dataset))
+}
+
+/// A small Tokyo fixture so tests can parse without network access.
+#[cfg(test)]
+pub fn test_dataset() -> Arc<Dataset> {
+    let prefectures: Vec<Prefecture> = serde_json::from_value(json!([
+        {
+            "name": "東京都",
+            "cities": [
+                {
+                    "name": "渋谷区",
+                    "towns": [
+                        {"town": "神宮前一丁目", "koaza": "", "lat": 35.670168, "lng": 139.706404},
+                        {"town": "神宮前二丁目", "koaza": "", "lat": 35.673227, "lng": 139.710052},
+                        {"town": "渋谷一丁目", "koaza": "", "lat": 35.660618, "lng": 139.704894}
+                    ]
+                },
+                {
+                    "name": "千代田区",
+                    "towns": [
+                        {"town": "丸の内一丁目", "koaza": "", "lat": 35.681688, "lng": 139.765396}
+                    ]
+                }
+            ]
+        }
+    ]))
+    .unwrap();
+    Arc::new(Dataset::new(DatasetSource::Local, prefectures))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup_serves_upstream_urls() {
+        let dataset = test_dataset();
+
+        let master = dataset
+            .lookup(&format!("{}東京都/master.json", PREFECTURE_MASTER_URL))
+            .unwrap();
+        assert_eq!(master["name"], "東京都");
+        assert_eq!(master["cities"], json!(["渋谷区", "千代田区"]));
+
+        let towns = dataset
+            .lookup(&format!("{}東京都/渋谷区.json", CITY_MASTER_URL))
+            .unwrap();
+        assert_eq!(towns[0]["town"], "神宮前一丁目");
+
+        assert!(dataset
+            .lookup(&format!("{}東京都/港区.json", CITY_MASTER_URL))
+            .is_err());
+        assert!(dataset
+            .lookup("https://tokyo.chimei-ruiju.org/master.json")
+            .unwrap_err()
+            .is_request());
+    }
+
+    #[test]
+    fn test_load_dir_and_validate() {
+        let dir = std::env::temp_dir().join(format!("dataset-test-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("ja/東京都")).unwrap();
+        std::fs::write(dir.join("ja.json"), r#"{"東京都": ["渋谷区"]}"#).unwrap();
+        std::fs::write(
+            dir.join("ja/東京都/渋谷区.json"),
+            r#"[{"town": "神宮前一丁目", "koaza": "", "lat": 35.67, "lng": 139.70}]"#,
+        )
+        .unwrap();
+
+        let dataset = Dataset::load_dir(&dir).unwrap();
+        assert_eq!(dataset.town_count(), 1);
+        let error = dataset.validate().unwrap_err();
+        assert!(error.contains("missing prefecture 北海道"));
+        assert!(error.contains("(and 41 more)"));
+
+        std::fs::write(dir.join("ja.json"), r#"{"東京都": ["渋谷区", "港区"]}"#).unwrap();
+        let error = Dataset::load_dir(&dir).unwrap_err();
+        assert!(error.contains("港区.json"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,284 @@
+//! Finding addresses in free text.
+//!
+//! A span starts at a prefecture name, or at a city name when the prefecture
+//! is omitted, and runs until punctuation, a line break or the point where
+//! prose resumes. Known city names are taken whole, so hiragana cities
+//! (`さいたま市`, `あきる野市`) stay in the span, as does a town written in
+//! hiragana when block numbers follow it (`みなとみらい2-2-1`). Past that,
+//! prose is recognized by hiragana: place names rarely contain any beyond
+//! `の` and `が` (`丸の内`, `霞が関`), so a run of two or more, or any other
+//! hiragana character, ends the span:
+//!
+//! ```text
+//! お届け先は東京都渋谷区神宮前1-1-1 神宮前ビル3階までお願いします。
+//!           ^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^
+//! ```
+//!
+//! City names come from the prefecture masters through [`MasterDataClient`],
+//! so they are served from the offline dataset or the master data cache.
+
+use crate::dataset;
+use crate::master_data::MasterDataClient;
+use futures::stream::{self, StreamExt};
+use japanese_address_parser::http::client::ApiClient;
+use std::collections::{HashMap, HashSet};
+
+/// Prefecture masters fetched at once while collecting city names.
+const FETCH_CONCURRENCY: usize = 8;
+/// Longest span considered, in characters.
+const MAX_SPAN_LENGTH: usize = 200;
+/// Longest town name looked for between a city and its block numbers.
+const MAX_TOWN_LENGTH: usize = 20;
+
+const DELIMITERS: &str = "。、，,．「」『』（）()【】［］[]〈〉《》<>＜＞:：;；!！?？\"“”'‘’";
+
+/// Names a span can start at.
+pub struct Anchors {
+    prefectures: HashSet<&'static str>,
+    /// City name to the prefectures that have a city of that name.
+    cities: HashMap<String, Vec<String>>,
+    longest: usize,
+}
+
+impl Anchors {
+    /// Collects city names from every prefecture master that can be fetched.
+    pub async fn load(client: &MasterDataClient) -> Self {
+        let prefectures: Vec<&'static str> = jisx0401::Prefecture::values()
+            .map(|prefecture| prefecture.name_ja())
+            .collect();
+        let names: Vec<String> = prefectures.iter().map(|name| name.to_string()).collect();
+        let masters: Vec<(String, dataset::PrefectureMaster)> = stream::iter(names)
+            .map(|prefecture| fetch_master(client, prefecture))
+            .buffer_unordered(FETCH_CONCURRENCY)
+            .filter_map(|master| async move { master })
+            .collect()
+            .await;
+
+        let mut cities: HashMap<String, Vec<String>> = HashMap::new();
+        for (prefecture, master) in masters {
+            for city in master.cities {
+                cities.entry(city).or_default().push(prefecture.clone());
+            }
+        }
+        for city_prefectures in cities.values_mut() {
+            city_prefectures.sort();
+        }
+        Self::new(prefectures, cities)
+    }
+
+    fn new(prefectures: Vec<&'static str>, cities: HashMap<String, Vec<String>>) -> Self {
+        let longest = prefectures
+            .iter()
+            .map(|name| name.chars().count())
+            .chain(cities.keys().map(|name| name.chars().count()))
+            .max()
+            .unwrap_or(0);
+        Self {
+            prefectures: prefectures.into_iter().collect(),
+            cities,
+            longest,
+        }
+    }
+
+    /// The longest name starting at `chars[start]`, with the prefectures it
+    /// implies: none for a prefecture name, the city's for a city name.
+    fn match_at(&self, chars: &[char], start: usize) -> Option<(usize, &[String])> {
+        let longest = self.longest.min(chars.len() - start);
+        (2..=longest).rev().find_map(|length| {
+            let name: String = chars[start..start + length].iter().collect();
+            if self.prefectures.contains(name.as_str()) {
+                return Some((length, &[][..]));
+            }
+            self.cities
+                .get(&name)
+                .map(|prefectures| (length, prefectures.as_slice()))
+        })
+    }
+}
+
+async fn fetch_master(
+    client: &MasterDataClient,
+    prefecture: String,
+) -> Option<(String, dataset::PrefectureMaster)> {
+    let master = client
+        .fetch(&dataset::prefecture_master_url(&prefecture))
+        .await
+        .ok()?;
+    Some((prefecture, master))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Span {
+    /// Offset of the first character, counted in Unicode characters.
+    pub start: usize,
+    /// Offset just past the last character.
+    pub end: usize,
+    pub text: String,
+    /// Prefectures that have the city the span starts with; empty when the
+    /// span starts with its prefecture.
+    pub prefectures: Vec<String>,
+}
+
+/// Finds address-like spans in `text`, in order of appearance.
+pub fn find_spans(text: &str, anchors: &Anchors) -> Vec<Span> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut spans = Vec::new();
+    let mut start = 0;
+    while start < chars.len() {
+        let Some((length, prefectures)) = anchors.match_at(&chars, start) else {
+            start += 1;
+            continue;
+        };
+        let mut anchor_end = start + length;
+        // A prefecture is usually followed by its city, which may be written
+        // in hiragana.
+        if prefectures.is_empty() {
+            let prefecture: String = chars[start..anchor_end].iter().collect();
+            if let Some((city_length, _)) = anchors
+                .match_at(&chars, anchor_end)
+                .filter(|(_, city_prefectures)| city_prefectures.contains(&prefecture))
+            {
+                anchor_end += city_length;
+            }
+        }
+        let end = span_end(&chars, start, anchor_end);
+        spans.push(Span {
+            start,
+            end,
+            text: chars[start..end].iter().collect(),
+            prefectures: prefectures.to_vec(),
+        });
+        start = end;
+    }
+    spans
+}
+
+/// Extends a span from the end of its anchor name.
+fn span_end(chars: &[char], start: usize, from: usize) -> usize {
+    let limit = chars.len().min(start + MAX_SPAN_LENGTH);
+    let mut end = town_end(chars, from, limit).unwrap_or(from);
+    while end < limit {
+        let c = chars[end];
+        if DELIMITERS.contains(c) || c == '\n' || c == '\r' || c == '\t' {
+            break;
+        }
+        if is_hiragana(c) {
+            let run = chars[end..].iter().take_while(|c| is_hiragana(**c)).count();
+            // `1番地の2` is an address, `1-1が` is not.
+            let after_number = chars[end - 1].is_numeric() || "番地号階室".contains(chars[end - 1]);
+            let part_of_name = c == 'の' || (c == 'が' && !after_number);
+            if run > 1 || !part_of_name {
+                break;
+            }
+        }
+        end += 1;
+    }
+    while end > from && chars[end - 1].is_whitespace() {
+        end -= 1;
+    }
+    end
+}
+
+/// Where the block numbers start when the text after a city reads as a
+/// town name followed by them, so hiragana in the town does not end the span.
+fn town_end(chars: &[char], from: usize, limit: usize) -> Option<usize> {
+    let limit = limit.min(from + MAX_TOWN_LENGTH);
+    let length = chars[from..limit]
+        .iter()
+        .take_while(|c| !c.is_ascii_digit() && !is_fullwidth_digit(**c))
+        .take_while(|c| !DELIMITERS.contains(**c) && !c.is_whitespace())
+        .count();
+    let end = from + length;
+    let digit_follows = chars
+        .get(end)
+        .is_some_and(|c| c.is_ascii_digit() || is_fullwidth_digit(*c));
+    (length > 0 && end < limit && digit_follows).then_some(end)
+}
+
+fn is_fullwidth_digit(c: char) -> bool {
+    matches!(c, '０'..='９')
+}
+
+fn is_hiragana(c: char) -> bool {
+    matches!(c, 'ぁ'..='ゖ')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn anchors() -> Anchors {
+        let cities = HashMap::from([
+            ("渋谷区".to_string(), vec!["東京都".to_string()]),
+            ("千代田区".to_string(), vec!["東京都".to_string()]),
+            ("あきる野市".to_string(), vec!["東京都".to_string()]),
+            (
+                "府中市".to_string(),
+                vec!["広島県".to_string(), "東京都".to_string()],
+            ),
+            ("さいたま市大宮区".to_string(), vec!["埼玉県".to_string()]),
+            ("横浜市西区".to_string(), vec!["神奈川県".to_string()]),
+            ("つくば市".to_string(), vec!["茨城県".to_string()]),
+        ]);
+        Anchors::new(vec!["東京都", "大阪府", "埼玉県", "神奈川県"], cities)
+    }
+
+    #[test]
+    fn test_find_spans() {
+        let text = "お届け先は東京都渋谷区神宮前1-1-1 神宮前ビル3階までお願いします。\n\
+                    請求書は千代田区丸の内1-9-1、府中市宮西町2-24へ。";
+        let spans = find_spans(text, &anchors());
+        let texts: Vec<&str> = spans.iter().map(|span| span.text.as_str()).collect();
+        assert_eq!(
+            texts,
+            vec![
+                "東京都渋谷区神宮前1-1-1 神宮前ビル3階",
+                "千代田区丸の内1-9-1",
+                "府中市宮西町2-24"
+            ]
+        );
+
+        assert_eq!(spans[0].start, 5);
+        assert_eq!(spans[0].end, 5 + spans[0].text.chars().count());
+        let chars: Vec<char> = text.chars().collect();
+        let span: String = chars[spans[1].start..spans[1].end].iter().collect();
+        assert_eq!(span, spans[1].text);
+
+        assert!(spans[0].prefectures.is_empty());
+        assert_eq!(spans[1].prefectures, vec!["東京都"]);
+        assert_eq!(spans[2].prefectures, vec!["広島県", "東京都"]);
+    }
+
+    #[test]
+    fn test_spans_end_where_prose_resumes() {
+        let spans = find_spans("東京都渋谷区に住んでいます", &anchors());
+        assert_eq!(spans[0].text, "東京都渋谷区");
+        let spans = find_spans("大阪府大阪市北区梅田3丁目1番地の2", &anchors());
+        assert_eq!(spans[0].text, "大阪府大阪市北区梅田3丁目1番地の2");
+        assert!(find_spans("特に住所はありません", &anchors()).is_empty());
+    }
+
+    #[test]
+    fn test_hiragana_city_and_town_names() {
+        let spans = find_spans(
+            "埼玉県さいたま市大宮区高鼻町4丁目までお願いします",
+            &anchors(),
+        );
+        let texts: Vec<&str> = spans.iter().map(|span| span.text.as_str()).collect();
+        assert_eq!(texts, vec!["埼玉県さいたま市大宮区高鼻町4丁目"]);
+        assert!(spans[0].prefectures.is_empty());
+
+        let spans = find_spans("会場は横浜市西区みなとみらい2-2-1にあります", &anchors());
+        assert_eq!(spans[0].text, "横浜市西区みなとみらい2-2-1");
+        assert_eq!(spans[0].prefectures, vec!["神奈川県"]);
+
+        let spans = find_spans("東京都あきる野市秋川1-1-1", &anchors());
+        assert_eq!(spans[0].text, "東京都あきる野市秋川1-1-1");
+
+        // Prose after a city is not taken for a town.
+        let spans = find_spans("実家はつくば市にあります", &anchors());
+        assert_eq!(spans[0].text, "つくば市");
+        let spans = find_spans("東京都あきる野市に住んでいます。電話は042", &anchors());
+        assert_eq!(spans[0].text, "東京都あきる野市");
+    }
+}
@@ -0,0 +1,268 @@
+//! Typo-tolerant matching of prefecture, city and town names.
+//!
+//! When the parser stops short of the town level, the text after the last
+//! resolved component is compared against the names one level down. Variant
+//! spellings are folded before comparing (`ヶ`/`ケ`/`が`, `檜`/`桧`,
+//! `澤`/`沢`, ...) so they match exactly, and anything else is scored by
+//! normalized Levenshtein similarity. The best name at or above the threshold
+//! replaces what was written; prefecture names must match exactly after
+//! folding.
+
+use crate::dataset;
+use crate::master_data::MasterDataClient;
+use crate::readings::split_chome;
+use japanese_address_parser::http::client::ApiClient;
+use rapidfuzz::distance::levenshtein;
+use serde::Serialize;
+
+/// Spellings that refer to the same place, folded to the form on the right.
+const VARIANTS: [(char, char); 34] = [
+    ('ヶ', 'ケ'),
+    ('ヵ', 'ケ'),
+    ('が', 'ケ'),
+    ('ガ', 'ケ'),
+    ('ノ', 'の'),
+    ('之', 'の'),
+    ('檜', '桧'),
+    ('澤', '沢'),
+    ('濱', '浜'),
+    ('邊', '辺'),
+    ('邉', '辺'),
+    ('齋', '斎'),
+    ('齊', '斉'),
+    ('廣', '広'),
+    ('國', '国'),
+    ('關', '関'),
+    ('瀧', '滝'),
+    ('龍', '竜'),
+    ('嶋', '島'),
+    ('嶌', '島'),
+    ('槇', '槙'),
+    ('舘', '館'),
+    ('淺', '浅'),
+    ('櫻', '桜'),
+    ('藏', '蔵'),
+    ('壽', '寿'),
+    ('條', '条'),
+    ('眞', '真'),
+    ('莊', '荘'),
+    ('黑', '黒'),
+    ('德', '徳'),
+    ('髙', '高'),
+    ('﨑', '崎'),
+    ('籠', '篭'),
+];
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Correction {
+    /// `prefecture`, `city` or `town`.
+    pub field: &'static str,
+    /// The text as written.
+    pub input: String,
+    /// The name it was read as.
+    pub corrected: String,
+    /// Similarity of the two after folding variants, from 0 to 1.
+    pub similarity: f64,
+}
+
+/// Corrects the first component the parser could not resolve, given what it
+/// did resolve. Returns the corrected address along with the correction.
+pub async fn correct(
+    client: &MasterDataClient,
+    address: &str,
+    prefecture: Option<&str>,
+    city: Option<&str>,
+    threshold: f64,
+) -> Option<(String, Correction)> {
+    let (field, resolved, names): (_, String, Vec<String>) = match (prefecture, city) {
+        (None, _) => (
+            "prefecture",
+            String::new(),
+            jisx0401::Prefecture::values()
+                .map(|prefecture| prefecture.name_ja().to_string())
+                .collect(),
+        ),
+        (Some(prefecture), None) => {
+            let master = client
+                .fetch::<dataset::PrefectureMaster>(&dataset::prefecture_master_url(prefecture))
+                .await
+                .ok()?;
+            ("city", prefecture.to_string(), master.cities.clone())
+        }
+        (Some(prefecture), Some(city)) => {
+            let towns: Vec<dataset::Town> = client
+                .fetch(&dataset::city_master_url(prefecture, city))
+                .await
+                .ok()?;
+            // 丁目 is matched by the parser once the town name is right.
+            let mut names: Vec<String> = towns
+                .iter()
+                .map(|town| split_chome(&town.town).0.to_string())
+                .collect();
+            names.sort();
+            names.dedup();
+            ("town", format!("{}{}", prefecture, city), names)
+        }
+    };
+
+    // An address that omits the prefecture often starts with a city that
+    // resembles one (`京都市` → `京都府`), so prefectures only match exactly.
+    let threshold = if field == "prefecture" {
+        1.0
+    } else {
+        threshold
+    };
+    let rest = address.strip_prefix(resolved.as_str())?;
+    let (name, consumed, similarity) =
+        best_match(rest, names.iter().map(String::as_str), threshold)?;
+    let correction = Correction {
+        field,
+        input: rest[..consumed].to_string(),
+        corrected: name.to_string(),
+        similarity: (similarity * 1000.0).round() / 1000.0,
+    };
+    Some((
+        format!("{}{}{}", resolved, name, &rest[consumed..]),
+        correction,
+    ))
+}
+
+/// Finds the name most similar to a prefix of `text`. Prefixes one character
+/// shorter or longer than each name are tried too, so a dropped or extra
+/// character still lines up. Returns the name, the length in bytes of the
+/// prefix it replaces and the similarity.
+fn best_match<'a>(
+    text: &str,
+    names: impl Iterator<Item = &'a str>,
+    threshold: f64,
+) -> Option<(&'a str, usize, f64)> {
+    let folded_text: Vec<char> = fold(text).chars().collect();
+    let boundaries: Vec<usize> = text
+        .char_indices()
+        .map(|(index, _)| index)
+        .skip(1)
+        .chain(std::iter::once(text.len()))
+        .collect();
+
+    let mut best: Option<(&str, usize, f64)> = None;
+    for name in names {
+        let folded_name: Vec<char> = fold(name).chars().collect();
+        let length = folded_name.len();
+        // The name's own length first, so it wins ties.
+        for prefix_length in [length, length.saturating_sub(1), length + 1] {
+            let Some(&end) = prefix_length
+                .checked_sub(1)
+                .and_then(|last| boundaries.get(last))
+            else {
+                continue;
+            };
+            let similarity = levenshtein::normalized_similarity(
+                folded_text[..prefix_length].iter(),
+                folded_name.iter(),
+            );
+            // Ties go to the longer name, which explains more of the input.
+            let better = best.is_none_or(|(best_name, _, best_similarity)| {
+                similarity > best_similarity
+                    || (similarity == best_similarity && name.len() > best_name.len())
+            });
+            if similarity >= threshold && better {
+                best = Some((name, end, similarity));
+            }
+        }
+    }
+    best
+}
+
+/// Replaces variant spellings with a single form.
+pub fn fold(text: &str) -> String {
+    text.chars()
+        .map(|c| {
+            VARIANTS
+                .iter()
+                .find(|(variant, _)| *variant == c)
+                .map_or(c, |(_, folded)| *folded)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fold_variants() {
+        assert_eq!(fold("霞ヶ関"), fold("霞が関"));
+        assert_eq!(fold("檜原村"), "桧原村");
+        assert_eq!(fold("丸ノ内"), "丸の内");
+    }
+
+    #[test]
+    fn test_best_match() {
+        let names = ["神宮前", "渋谷", "千駄ヶ谷"];
+        assert_eq!(
+            best_match("千駄が谷1-1", names.into_iter(), 0.65),
+            Some(("千駄ヶ谷", "千駄が谷".len(), 1.0))
+        );
+
+        let (name, consumed, similarity) =
+            best_match("神宮全1-1-1", names.into_iter(), 0.65).unwrap();
+        assert_eq!((name, consumed), ("神宮前", "神宮全".len()));
+        assert!((similarity - 2.0 / 3.0).abs() < 1e-9);
+
+        // A dropped character.
+        let (name, consumed, _) = best_match("千駄谷1-1", names.into_iter(), 0.65).unwrap();
+        assert_eq!((name, consumed), ("千駄ヶ谷", "千駄谷".len()));
+
+        assert_eq!(best_match("原宿1-1", names.into_iter(), 0.65), None);
+    }
+
+    #[tokio::test]
+    async fn test_correct_prefecture_only_on_variant_spelling() {
+        let client = MasterDataClient::with_dataset(None);
+        let (address, correction) =
+            correct(&client, "鹿児嶋県鹿児島市山下町11-1", None, None, 0.65)
+                .await
+                .unwrap();
+        assert_eq!(address, "鹿児島県鹿児島市山下町11-1");
+        assert_eq!(correction.field, "prefecture");
+        assert_eq!(correction.similarity, 1.0);
+
+        // A city that resembles a prefecture is not rewritten into one.
+        assert_eq!(
+            correct(&client, "京都市中京区寺町通御池上る", None, None, 0.65).await,
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_correct_town() {
+        let client = MasterDataClient::with_dataset(Some(dataset::test_dataset()));
+
+        let (address, correction) = correct(
+            &client,
+            "東京都渋谷区神宮全1-1-1",
+            Some("東京都"),
+            Some("渋谷区"),
+            0.65,
+        )
+        .await
+        .unwrap();
+        assert_eq!(address, "東京都渋谷区神宮前1-1-1");
+        assert_eq!(correction.field, "town");
+        assert_eq!(correction.input, "神宮全");
+        assert_eq!(correction.corrected, "神宮前");
+        assert_eq!(correction.similarity, 0.667);
+
+        let (address, correction) = correct(
+            &client,
+            "東京都千代田区丸ノ内1-9-1",
+            Some("東京都"),
+            Some("千代田区"),
+            0.65,
+        )
+        .await
+        .unwrap();
+        assert_eq!(address, "東京都千代田区丸の内1-9-1");
+        assert_eq!(correction.similarity, 1.0);
+    }
+}
@@ -0,0 +1,97 @@
+//! Representative coordinates for parse results.
+//!
+//! The Geolonia town lists the parser resolves against carry a point for every
+//! town. A resolved town gets its own point; an address resolved only down to
+//! the city gets the centroid of the city's towns.
+//!
+//! Parses against `chimei_ruiju` already report a point in their metadata,
+//! which is used as is; this module is the fallback for everything else.
+
+use crate::dataset::{self, Town};
+use crate::master_data::MasterDataClient;
+use japanese_address_parser::http::client::ApiClient;
+use serde::Serialize;
+
+/// How specific a coordinate is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GeocodePrecision {
+    City,
+    Town,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Geocode {
+    pub lat: f64,
+    pub lng: f64,
+    pub precision: GeocodePrecision,
+}
+
+/// Looks up coordinates for a resolved city and, if known, town.
+///
+/// The town list comes through [`MasterDataClient`], so it is usually served
+/// from the offline dataset or the master data cache the parse just filled.
+pub async fn geocode(
+    client: &MasterDataClient,
+    prefecture: &str,
+    city: &str,
+    town: Option<&str>,
+) -> Option<Geocode> {
+    let towns: Vec<Town> = client
+        .fetch(&dataset::city_master_url(prefecture, city))
+        .await
+        .ok()?;
+    locate(&towns, town)
+}
+
+fn locate(towns: &[Town], town: Option<&str>) -> Option<Geocode> {
+    let point = |town: &Town| Some((town.lat?, town.lng?));
+
+    if let Some(name) = town {
+        if let Some((lat, lng)) = towns.iter().filter(|t| t.town == name).find_map(point) {
+            return Some(Geocode {
+                lat,
+                lng,
+                precision: GeocodePrecision::Town,
+            });
+        }
+    }
+
+    let points: Vec<(f64, f64)> = towns.iter().filter_map(point).collect();
+    if points.is_empty() {
+        return None;
+    }
+    let count = points.len() as f64;
+    Some(Geocode {
+        lat: points.iter().map(|(lat, _)| lat).sum::<f64>() / count,
+        lng: points.iter().map(|(_, lng)| lng).sum::<f64>() / count,
+        precision: GeocodePrecision::City,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_geocode_town_and_city_centroid() {
+        let client = MasterDataClient::with_dataset(Some(dataset::test_dataset()));
+
+        let town = geocode(&client, "東京都", "千代田区", Some("丸の内一丁目"))
+            .await
+            .unwrap();
+        assert_eq!(town.precision, GeocodePrecision::Town);
+        assert_eq!((town.lat, town.lng), (35.681688, 139.765396));
+
+        let city = geocode(&client, "東京都", "渋谷区", None).await.unwrap();
+        assert_eq!(city.precision, GeocodePrecision::City);
+        assert!((city.lat - 35.668004).abs() < 1e-6);
+
+        let unknown_town = geocode(&client, "東京都", "渋谷区", Some("恵比寿一丁目"))
+            .await
+            .unwrap();
+        assert_eq!(unknown_town.precision, GeocodePrecision::City);
+
+        assert!(geocode(&client, "東京都", "港区", None).await.is_none());
+    }
+}
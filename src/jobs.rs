@@ -0,0 +1,572 @@
+//! Asynchronous batch jobs.
+//!
+//! A job is a `/parse/batch` request run in the background and polled for
+//! progress. Jobs are only available when `JOBS_DIR` is set; each one is
+//! persisted there as three files so unfinished jobs resume after a restart:
+//!
+//! - `{id}.json`: status and progress
+//! - `{id}.request.json`: the submitted request
+//! - `{id}.results.ndjson`: one result per line, in input order
+//!
+//! Results are appended as they complete, so the number of lines in the
+//! results file is where a resumed job picks up. Finished jobs are deleted
+//! `JOB_RETENTION_SECS` after their last update.
+
+use crate::webhook::{CallbackStatus, DeliveryAttempt};
+use axum::body::Bytes;
+use chrono::{DateTime, Utc};
+use futures::stream::{self, BoxStream, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tracing::warn;
+
+const DEFAULT_JOB_CONCURRENCY: usize = 2;
+const DEFAULT_JOB_RETENTION_SECS: u64 = 24 * 60 * 60;
+const DOWNLOAD_CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    /// Waiting for one of the `JOB_CONCURRENCY` slots.
+    Queued,
+    Running,
+    Completed,
+    Cancelled,
+    /// Stopped by an error reading the request or writing results.
+    Failed,
+}
+
+impl JobStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Queued => "queued",
+            Self::Running => "running",
+            Self::Completed => "completed",
+            Self::Cancelled => "cancelled",
+            Self::Failed => "failed",
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        matches!(self, Self::Completed | Self::Cancelled | Self::Failed)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobInfo {
+    pub id: String,
+    pub status: JobStatus,
+    /// Number of addresses submitted.
+    pub total: usize,
+    /// Addresses processed so far, including failed ones.
+    pub done: usize,
+    /// Addresses that could not be parsed.
+    pub failed: usize,
+    /// Why the job failed, for the `failed` status.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Where the summary is posted once the job finishes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub callback_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub callback_status: Option<CallbackStatus>,
+    /// Every attempt to post the summary, oldest first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deliveries: Vec<DeliveryAttempt>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+pub struct Job {
+    info: Mutex<JobInfo>,
+    cancelled: AtomicBool,
+}
+
+impl Job {
+    fn new(info: JobInfo) -> Self {
+        Self {
+            info: Mutex::new(info),
+            cancelled: AtomicBool::new(false),
+        }
+    }
+
+    pub fn info(&self) -> JobInfo {
+        self.info.lock().unwrap().clone()
+    }
+
+    pub fn id(&self) -> String {
+        self.info.lock().unwrap().id.clone()
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+pub struct JobStore {
+    /// Where jobs are persisted.
+    dir: PathBuf,
+    jobs: Mutex<HashMap<String, Arc<Job>>>,
+    /// Limits how many jobs run at once.
+    permits: Arc<Semaphore>,
+    /// How long a finished job is kept after its last update.
+    retention: Duration,
+}
+
+impl JobStore {
+    /// Opens the store, loading the jobs already in `dir`. Jobs
+    /// that were running are queued again, with progress counted from their
+    /// results file.
+    pub fn open(dir: &Path, concurrency: usize, retention: Duration) -> Result<Self, String> {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        let mut store = Self {
+            dir: dir.to_path_buf(),
+            jobs: Mutex::new(HashMap::new()),
+            permits: Arc::new(Semaphore::new(concurrency.max(1))),
+            retention,
+        };
+
+        let entries = std::fs::read_dir(dir)
+            .map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?;
+        let mut jobs = HashMap::new();
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let is_info = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.ends_with(".json") && name.matches('.').count() == 1);
+            if !is_info {
+                continue;
+            }
+            match store.load(&path) {
+                Ok(job) => {
+                    jobs.insert(job.id(), Arc::new(job));
+                }
+                Err(e) => warn!(
+                    event = "job_load_failed",
+                    path = %path.display(),
+                    error = %e,
+                    "Skipping unreadable job"
+                ),
+            }
+        }
+        store.jobs = Mutex::new(jobs);
+        store.expire();
+        Ok(store)
+    }
+
+    fn load(&self, path: &Path) -> io::Result<Job> {
+        let mut info: JobInfo = serde_json::from_slice(&std::fs::read(path)?)?;
+        if !info.status.is_finished() {
+            let (done, failed) = self.recover_results(&info.id)?;
+            info.status = JobStatus::Queued;
+            info.done = done;
+            info.failed = failed;
+        }
+        Ok(Job::new(info))
+    }
+
+    /// Counts the complete lines of a results file, dropping a line cut off
+    /// by a crash.
+    fn recover_results(&self, id: &str) -> io::Result<(usize, usize)> {
+        let path = self.path(id, "results.ndjson");
+        let mut contents = Vec::new();
+        match std::fs::File::open(&path) {
+            Ok(mut file) => {
+                file.read_to_end(&mut contents)?;
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok((0, 0)),
+            Err(e) => return Err(e),
+        }
+        let complete = contents
+            .iter()
+            .rposition(|byte| *byte == b'\n')
+            .map_or(0, |last| last + 1);
+        if complete < contents.len() {
+            std::fs::OpenOptions::new()
+                .write(true)
+                .open(&path)?
+                .set_len(complete as u64)?;
+        }
+        let mut done = 0;
+        let mut failed = 0;
+        for line in contents[..complete].split(|byte| *byte == b'\n') {
+            if line.is_empty() {
+                continue;
+            }
+            done += 1;
+            let result: serde_json::Value = serde_json::from_slice(line).unwrap_or_default();
+            if result["success"] != true {
+                failed += 1;
+            }
+        }
+        Ok((done, failed))
+    }
+
+    /// Creates a queued job for `request`, which has `total` addresses.
+    pub fn create(
+        &self,
+        request: serde_json::Value,
+        total: usize,
+        callback_url: Option<String>,
+    ) -> io::Result<Arc<Job>> {
+        self.expire();
+        let now = Utc::now();
+        let info = JobInfo {
+            // Random so that knowing one job's ID does not reveal another's.
+            id: format!("{:032x}", rand::random::<u128>()),
+            status: JobStatus::Queued,
+            total,
+            done: 0,
+            failed: 0,
+            error: None,
+            callback_status: callback_url.as_ref().map(|_| CallbackStatus::Pending),
+            callback_url,
+            deliveries: Vec::new(),
+            created_at: now,
+            updated_at: now,
+        };
+        std::fs::write(
+            self.path(&info.id, "request.json"),
+            serde_json::to_vec(&request)?,
+        )?;
+        let job = Job::new(info);
+        self.save(&job.info())?;
+        let job = Arc::new(job);
+        self.jobs.lock().unwrap().insert(job.id(), job.clone());
+        Ok(job)
+    }
+
+    pub fn get(&self, id: &str) -> Option<Arc<Job>> {
+        self.expire();
+        self.jobs.lock().unwrap().get(id).cloned()
+    }
+
+    /// Deletes finished jobs not updated within the retention period. Jobs
+    /// with a callback still to be sent are kept until it is.
+    fn expire(&self) {
+        let expired: Vec<Arc<Job>> = self
+            .jobs
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|job| {
+                let info = job.info();
+                let age = (Utc::now() - info.updated_at).to_std().unwrap_or_default();
+                info.status.is_finished()
+                    && info.callback_status != Some(CallbackStatus::Pending)
+                    && age >= self.retention
+            })
+            .cloned()
+            .collect();
+        for job in expired {
+            if let Err(e) = self.remove(&job) {
+                warn!(event = "job_expiry_failed", job_id = job.id(), error = %e);
+            }
+        }
+    }
+
+    /// Jobs that still have addresses to process, oldest first.
+    pub fn unfinished(&self) -> Vec<Arc<Job>> {
+        let mut jobs: Vec<Arc<Job>> = self
+            .jobs
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|job| !job.info().status.is_finished())
+            .cloned()
+            .collect();
+        jobs.sort_by_key(|job| job.info().created_at);
+        jobs
+    }
+
+    /// Finished jobs whose callback has not been delivered or given up on,
+    /// oldest first.
+    pub fn undelivered(&self) -> Vec<Arc<Job>> {
+        let mut jobs: Vec<Arc<Job>> = self
+            .jobs
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|job| {
+                let info = job.info();
+                info.status.is_finished() && info.callback_status == Some(CallbackStatus::Pending)
+            })
+            .cloned()
+            .collect();
+        jobs.sort_by_key(|job| job.info().created_at);
+        jobs
+    }
+
+    /// Waits for a slot to run a job in.
+    pub async fn acquire(&self) -> OwnedSemaphorePermit {
+        self.permits
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("job semaphore is never closed")
+    }
+
+    pub fn request(&self, job: &Job) -> io::Result<serde_json::Value> {
+        Ok(serde_json::from_slice(&std::fs::read(
+            self.path(&job.id(), "request.json"),
+        )?)?)
+    }
+
+    /// Moves a job to `status`. A finished job keeps its status, so a
+    /// cancellation is never overwritten by the run completing; returns
+    /// false in that case.
+    pub fn set_status(&self, job: &Job, status: JobStatus) -> io::Result<bool> {
+        self.update(job, |info| info.status = status)
+    }
+
+    /// Stops a job that has not finished. Returns false if it already had.
+    pub fn cancel(&self, job: &Job) -> io::Result<bool> {
+        let cancelled = self.set_status(job, JobStatus::Cancelled)?;
+        if cancelled {
+            job.cancelled.store(true, Ordering::Relaxed);
+        }
+        Ok(cancelled)
+    }
+
+    pub fn fail(&self, job: &Job, error: String) -> io::Result<bool> {
+        self.update(job, |info| {
+            info.status = JobStatus::Failed;
+            info.error = Some(error);
+        })
+    }
+
+    fn update(&self, job: &Job, change: impl FnOnce(&mut JobInfo)) -> io::Result<bool> {
+        // Saved under the lock so concurrent updates are written in order.
+        let mut info = job.info.lock().unwrap();
+        if info.status.is_finished() {
+            return Ok(false);
+        }
+        change(&mut info);
+        info.updated_at = Utc::now();
+        self.save(&info)?;
+        Ok(true)
+    }
+
+    /// Records a callback delivery attempt, and whether the callback is
+    /// now delivered or given up on.
+    pub fn record_delivery(
+        &self,
+        job: &Job,
+        attempt: DeliveryAttempt,
+        status: CallbackStatus,
+    ) -> io::Result<()> {
+        // A job deleted while its callback was being sent stays deleted.
+        let deleted = !self.jobs.lock().unwrap().contains_key(&job.id());
+        let mut info = job.info.lock().unwrap();
+        info.deliveries.push(attempt);
+        info.callback_status = Some(status);
+        info.updated_at = Utc::now();
+        if !deleted {
+            self.save(&info)?;
+        }
+        Ok(())
+    }
+
+    /// Deletes a job and its files.
+    pub fn remove(&self, job: &Job) -> io::Result<()> {
+        let id = job.id();
+        self.jobs.lock().unwrap().remove(&id);
+        for suffix in ["json", "request.json", "results.ndjson"] {
+            match std::fs::remove_file(self.path(&id, suffix)) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Records the result of the next address.
+    pub async fn append_result(&self, job: &Job, line: String, failed: bool) -> io::Result<()> {
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.path(&job.id(), "results.ndjson"))
+            .await?;
+        file.write_all(format!("{}\n", line).as_bytes()).await?;
+        let mut info = job.info.lock().unwrap();
+        info.done += 1;
+        info.failed += usize::from(failed);
+        info.updated_at = Utc::now();
+        Ok(())
+    }
+
+    /// The results recorded so far, as NDJSON.
+    pub async fn results(&self, job: &Job) -> io::Result<BoxStream<'static, io::Result<Bytes>>> {
+        let file = match tokio::fs::File::open(self.path(&job.id(), "results.ndjson")).await {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(stream::empty().boxed()),
+            Err(e) => return Err(e),
+        };
+        // Read in chunks so large results are never held in memory at once.
+        Ok(stream::unfold(Some(file), |file| async move {
+            let mut file = file?;
+            let mut chunk = vec![0; DOWNLOAD_CHUNK_SIZE];
+            match file.read(&mut chunk).await {
+                Ok(0) => None,
+                Ok(read) => {
+                    chunk.truncate(read);
+                    Some((Ok(Bytes::from(chunk)), Some(file)))
+                }
+                Err(e) => Some((Err(e), None)),
+            }
+        })
+        .boxed())
+    }
+
+    /// Writes the job's status file, replacing it atomically.
+    fn save(&self, info: &JobInfo) -> io::Result<()> {
+        let temporary = self.dir.join(format!("{}.json.tmp", info.id));
+        std::fs::write(&temporary, serde_json::to_vec(info)?)?;
+        std::fs::rename(temporary, self.path(&info.id, "json"))
+    }
+
+    fn path(&self, id: &str, suffix: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", id, suffix))
+    }
+}
+
+fn concurrency_from_env() -> usize {
+    std::env::var("JOB_CONCURRENCY")
+        .ok()
+        .and_then(|value| value.parse::<usize>().ok())
+        .filter(|&n| n > 0)
+        .unwrap_or(DEFAULT_JOB_CONCURRENCY)
+}
+
+fn retention_from_env() -> Duration {
+    let secs = std::env::var("JOB_RETENTION_SECS")
+        .ok()
+        .and_then(|value| value.parse::<u64>().ok())
+        .unwrap_or(DEFAULT_JOB_RETENTION_SECS);
+    Duration::from_secs(secs)
+}
+
+/// Opens the store in `JOBS_DIR`; `None` when it is not set.
+pub fn init_from_env() -> Result<Option<JobStore>, String> {
+    let Ok(dir) = std::env::var("JOBS_DIR") else {
+        return Ok(None);
+    };
+    JobStore::open(
+        Path::new(&dir),
+        concurrency_from_env(),
+        retention_from_env(),
+    )
+    .map(Some)
+}
+
+/// A store in a new temporary directory.
+#[cfg(test)]
+pub fn test_store() -> Arc<JobStore> {
+    let dir = std::env::temp_dir().join(format!("jobs-test-{:032x}", rand::random::<u128>()));
+    Arc::new(JobStore::open(&dir, 1, Duration::from_secs(60)).unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const RETENTION: Duration = Duration::from_secs(60);
+
+    #[tokio::test]
+    async fn test_persisted_jobs_resume_after_restart() {
+        let dir = std::env::temp_dir().join(format!("jobs-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let store = JobStore::open(&dir, 1, RETENTION).unwrap();
+        let request = json!({"addresses": ["a", "b", "c"]});
+        let job = store.create(request.clone(), 3, None).unwrap();
+        store.set_status(&job, JobStatus::Running).unwrap();
+        store
+            .append_result(&job, r#"{"index":0,"success":true}"#.to_string(), false)
+            .await
+            .unwrap();
+        store
+            .append_result(&job, r#"{"index":1,"success":false}"#.to_string(), true)
+            .await
+            .unwrap();
+        // A crash in the middle of writing the third result.
+        let results = dir.join(format!("{}.results.ndjson", job.id()));
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&results)
+            .unwrap();
+        std::io::Write::write_all(&mut file, b"{\"index\":2,").unwrap();
+
+        let finished = store
+            .create(
+                json!({"addresses": ["d"]}),
+                1,
+                Some("http://localhost/hook".to_string()),
+            )
+            .unwrap();
+        assert!(store.set_status(&finished, JobStatus::Completed).unwrap());
+        assert!(!store.cancel(&finished).unwrap());
+
+        let reopened = JobStore::open(&dir, 1, RETENTION).unwrap();
+        let unfinished = reopened.unfinished();
+        assert_eq!(unfinished.len(), 1);
+        let info = unfinished[0].info();
+        assert_eq!(info.status, JobStatus::Queued);
+        assert_eq!((info.done, info.failed, info.total), (2, 1, 3));
+        assert_eq!(reopened.request(&unfinished[0]).unwrap(), request);
+
+        // The finished job's callback is still to be sent.
+        let undelivered = reopened.undelivered();
+        assert_eq!(undelivered.len(), 1);
+        assert_eq!(undelivered[0].id(), finished.id());
+
+        let body: Vec<Bytes> = reopened
+            .results(&unfinished[0])
+            .await
+            .unwrap()
+            .map(Result::unwrap)
+            .collect()
+            .await;
+        assert_eq!(body.concat().iter().filter(|b| **b == b'\n').count(), 2);
+
+        reopened.remove(&unfinished[0]).unwrap();
+        assert!(!results.exists());
+        assert!(reopened.get(&info.id).is_none());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_finished_jobs_expire() {
+        let dir = std::env::temp_dir().join(format!("jobs-expiry-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let store = JobStore::open(&dir, 1, Duration::ZERO).unwrap();
+        let running = store.create(json!({}), 1, None).unwrap();
+        store.set_status(&running, JobStatus::Running).unwrap();
+        let completed = store.create(json!({}), 1, None).unwrap();
+        store.set_status(&completed, JobStatus::Completed).unwrap();
+        let undelivered = store
+            .create(json!({}), 1, Some("http://localhost/hook".to_string()))
+            .unwrap();
+        store
+            .set_status(&undelivered, JobStatus::Completed)
+            .unwrap();
+
+        assert!(store.get(&running.id()).is_some());
+        assert!(store.get(&completed.id()).is_none());
+        assert!(store.get(&undelivered.id()).is_some());
+
+        assert_eq!(running.id().len(), 32);
+        assert_ne!(running.id(), undelivered.id());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,21 +1,58 @@
 use axum::{
-    extract::{Query, Request},
-    http::StatusCode,
+    body::{Body, Bytes},
+    extract::{DefaultBodyLimit, Path, Query, Request},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
     middleware::{self, Next},
-    response::Json,
-    routing::get,
+    response::{IntoResponse, Json, Response},
+    routing::{get, post},
     Router,
 };
-use japanese_address_parser::parser::{ParseResult, Parser};
+mod address_format;
+mod candidates;
+mod compare;
+mod csv_table;
+mod dataset;
+mod extract;
+mod fuzzy;
+mod geocode;
+mod jobs;
+mod master_data;
+mod ndjson;
+mod normalize;
+mod parser_pool;
+mod postal_code;
+mod readings;
+mod response_cache;
+mod rest_components;
+mod single_flight;
+mod spatial_index;
+mod suggest;
+mod webhook;
+
+use address_format::{AddressFormat, FormattedAddress, JapaneseStyle};
+use compare::Comparison;
+use dataset::Dataset;
+use futures::stream::{self, StreamExt};
+use fuzzy::Correction;
+use geocode::GeocodePrecision;
+use master_data::MasterDataClient;
+use parser_pool::{parse_bool, ParseOutcome, ParserConfig, ParserOverrides, ParserPool};
+use postal_code::{CityCodeStatus, PostalCodeCheck, PostalCodeIndex, PostalEntry};
+use readings::{Readings, RomajiStyle};
+use response_cache::{CacheStatus, ResponseCache};
+use rest_components::RestComponents;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use single_flight::SingleFlight;
+use spatial_index::SpatialIndex;
 use std::collections::HashMap;
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant, SystemTime};
+use suggest::{ReadingIndex, Suggestion};
 use tokio::net::TcpListener;
 use tokio::signal;
-use tokio::time::timeout;
+use tokio::time::{timeout, timeout_at};
 use tower::ServiceBuilder;
 use tower_http::{
     cors::{Any, CorsLayer},
@@ -24,11 +61,26 @@ use tower_http::{
 };
 use tracing::{debug, error, info, warn};
 use tracing_subscriber::{self, EnvFilter};
+use webhook::{CallbackStatus, WebhookConfig};
 
 // Configuration constants
 const DEFAULT_REQUEST_TIMEOUT_SECS: u64 = 30;
 const DEFAULT_MAX_REQUEST_SIZE: usize = 1024 * 1024; // 1MB
+const DEFAULT_MAX_JOB_REQUEST_SIZE: usize = 64 * 1024 * 1024; // 64MB
 const MAX_ADDRESS_LENGTH: usize = 500;
+const DEFAULT_MAX_BATCH_SIZE: usize = 1000;
+const DEFAULT_BATCH_CONCURRENCY: usize = 16;
+const WARMUP_CONCURRENCY: usize = 8;
+const DEFAULT_SUGGEST_LIMIT: usize = 10;
+const MAX_SUGGEST_LIMIT: usize = 50;
+const MAX_CANDIDATES: usize = 10;
+/// Upper bound on interpretations parsed for one `candidates` request.
+const MAX_INTERPRETATIONS: usize = 5;
+const DEFAULT_FUZZY_THRESHOLD: f64 = 0.65;
+const MAX_EXTRACT_TEXT_LENGTH: usize = 100_000;
+/// Longest line accepted by `/parse/stream`, in bytes.
+const MAX_STREAM_LINE_LENGTH: usize = 16 * 1024;
+const DEFAULT_MAX_JOB_SIZE: usize = 100_000;
 
 // Global metrics
 static TOTAL_REQUESTS: AtomicU64 = AtomicU64::new(0);
@@ -38,6 +90,24 @@ static GET_REQUESTS: AtomicU64 = AtomicU64::new(0);
 static POST_REQUESTS: AtomicU64 = AtomicU64::new(0);
 static TIMEOUT_ERRORS: AtomicU64 = AtomicU64::new(0);
 static VALIDATION_ERRORS: AtomicU64 = AtomicU64::new(0);
+static BATCH_REQUESTS: AtomicU64 = AtomicU64::new(0);
+static BATCH_ADDRESSES: AtomicU64 = AtomicU64::new(0);
+static COALESCED_PARSES: AtomicU64 = AtomicU64::new(0);
+static POSTAL_CODE_LOOKUPS: AtomicU64 = AtomicU64::new(0);
+static REVERSE_GEOCODE_REQUESTS: AtomicU64 = AtomicU64::new(0);
+static SUGGEST_REQUESTS: AtomicU64 = AtomicU64::new(0);
+static FORMAT_REQUESTS: AtomicU64 = AtomicU64::new(0);
+static COMPARE_REQUESTS: AtomicU64 = AtomicU64::new(0);
+static FUZZY_CORRECTIONS: AtomicU64 = AtomicU64::new(0);
+static EXTRACT_REQUESTS: AtomicU64 = AtomicU64::new(0);
+static STREAM_REQUESTS: AtomicU64 = AtomicU64::new(0);
+static STREAM_ADDRESSES: AtomicU64 = AtomicU64::new(0);
+static CSV_REQUESTS: AtomicU64 = AtomicU64::new(0);
+static CSV_ROWS: AtomicU64 = AtomicU64::new(0);
+static JOB_SUBMISSIONS: AtomicU64 = AtomicU64::new(0);
+static JOB_ADDRESSES: AtomicU64 = AtomicU64::new(0);
+static WEBHOOK_ATTEMPTS: AtomicU64 = AtomicU64::new(0);
+static WEBHOOK_FAILED_ATTEMPTS: AtomicU64 = AtomicU64::new(0);
 
 // Performance metrics
 static PARSE_TIME_TOTAL_MS: AtomicU64 = AtomicU64::new(0);
@@ -99,6 +169,90 @@ fn update_parse_time_metrics(duration_ms: u64) {
 #[derive(Debug, Deserialize)]
 struct ParseRequest {
     address: String,
+    /// Postal code to cross-check against the parse result.
+    #[serde(default)]
+    postal_code: Option<String>,
+    #[serde(flatten)]
+    options: ParserOverrides,
+    #[serde(flatten)]
+    request_options: RequestOptions,
+}
+
+/// Per-request options handled by the API itself rather than the parser.
+#[derive(Debug, Default, Deserialize)]
+struct RequestOptions {
+    /// Canonicalize the input before parsing; defaults to `NORMALIZE_INPUT`.
+    normalize: Option<bool>,
+    /// Add `lat`/`lng` from the town-level dataset.
+    #[serde(default)]
+    geocode: bool,
+    /// Add `*_kana` and `*_romaji` fields from the postal code dataset.
+    #[serde(default)]
+    readings: bool,
+    #[serde(default)]
+    romaji_style: RomajiStyle,
+    /// Render the result as a formatted address.
+    format: Option<AddressFormat>,
+    /// Number of ranked interpretations to return, up to `MAX_CANDIDATES`.
+    candidates: Option<usize>,
+    /// Correct misspelled or variant prefecture, city and town names.
+    #[serde(default)]
+    fuzzy: bool,
+    /// Minimum similarity for a correction; defaults to `FUZZY_THRESHOLD`.
+    fuzzy_threshold: Option<f64>,
+}
+
+impl RequestOptions {
+    /// Builds the options from GET query parameters.
+    fn from_query(params: &HashMap<String, String>) -> Result<Self, String> {
+        let bool_param = |name: &str| -> Result<Option<bool>, String> {
+            params
+                .get(name)
+                .map(|value| {
+                    parse_bool(value).map_err(|_| {
+                        format!("Invalid '{}' parameter (expected true or false)", name)
+                    })
+                })
+                .transpose()
+        };
+
+        Ok(Self {
+            normalize: bool_param("normalize")?,
+            geocode: bool_param("geocode")?.unwrap_or(false),
+            readings: bool_param("readings")?.unwrap_or(false),
+            romaji_style: params
+                .get("romaji_style")
+                .map(|value| value.parse())
+                .transpose()?
+                .unwrap_or_default(),
+            format: params
+                .get("format")
+                .map(|value| value.parse())
+                .transpose()?,
+            candidates: params
+                .get("candidates")
+                .map(|value| {
+                    value.parse().map_err(|_| {
+                        "Invalid 'candidates' parameter (expected a number)".to_string()
+                    })
+                })
+                .transpose()?,
+            fuzzy: bool_param("fuzzy")?.unwrap_or(false),
+            fuzzy_threshold: params
+                .get("fuzzy_threshold")
+                .map(|value| {
+                    value
+                        .parse()
+                        .ok()
+                        .filter(|threshold| (0.0..=1.0).contains(threshold))
+                        .ok_or_else(|| {
+                            "Invalid 'fuzzy_threshold' parameter (expected a number from 0 to 1)"
+                                .to_string()
+                        })
+                })
+                .transpose()?,
+        })
+    }
 }
 
 #[derive(Debug, Serialize)]
@@ -108,35 +262,363 @@ struct ParseResponse {
     error: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     processing_time_ms: Option<u64>,
+    /// Response cache outcome, reported through the `X-Cache` header.
+    #[serde(skip)]
+    cache_status: Option<CacheStatus>,
+}
+
+impl ParseResponse {
+    /// Headers to send alongside a single-address response.
+    fn headers(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        if let Some(cache_status) = self.cache_status {
+            headers.insert("x-cache", HeaderValue::from_static(cache_status.as_str()));
+        }
+        headers
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct BatchParseRequest {
+    addresses: Vec<BatchAddress>,
+    /// Parser options applied to every address in the batch.
+    #[serde(flatten)]
+    options: ParserOverrides,
+    #[serde(flatten)]
+    request_options: RequestOptions,
+}
+
+/// A batch entry is either a bare address string or an object carrying a
+/// client-supplied `id` that is echoed back in the matching result, and
+/// optionally a `postal_code` to cross-check.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum BatchAddress {
+    Plain(String),
+    WithId {
+        #[serde(default)]
+        id: Option<serde_json::Value>,
+        address: String,
+        #[serde(default)]
+        postal_code: Option<String>,
+    },
+}
+
+impl BatchAddress {
+    fn into_parts(self) -> (Option<serde_json::Value>, String, Option<String>) {
+        match self {
+            Self::Plain(address) => (None, address, None),
+            Self::WithId {
+                id,
+                address,
+                postal_code,
+            } => (id, address, postal_code),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct BatchParseResponse {
+    success: bool,
+    total: usize,
+    succeeded: usize,
+    failed: usize,
+    results: Vec<BatchItemResult>,
+    error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    processing_time_ms: Option<u64>,
 }
 
 #[derive(Debug, Serialize)]
+struct BatchItemResult {
+    index: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<serde_json::Value>,
+    #[serde(flatten)]
+    response: ParseResponse,
+}
+
+#[derive(Debug, Clone, Serialize)]
 struct ParsedAddress {
     prefecture: Option<String>,
+    /// JIS X 0401 code of the prefecture.
+    prefecture_code: Option<String>,
     city: Option<String>,
+    /// 6-digit 全国地方公共団体コード; requires the postal code dataset.
+    city_code: Option<String>,
+    /// Why `city_code` is or is not set, once the city is resolved.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    city_code_status: Option<CityCodeStatus>,
     town: Option<String>,
     rest: Option<String>,
+    /// `rest` split into block numbers and building details; only set once
+    /// the town is resolved.
+    rest_components: Option<RestComponents>,
+    resolution_level: ResolutionLevel,
+    error: Option<ParserError>,
+    /// The input as handed to the parser, when normalization was applied.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    normalized_address: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    postal_code_check: Option<PostalCodeCheck>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    lat: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    lng: Option<f64>,
+    /// Whether `lat`/`lng` point at the town or only the city.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    geocode_precision: Option<GeocodePrecision>,
+    #[serde(flatten)]
+    readings: Readings,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    formatted: Option<FormattedAddress>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    candidates: Option<Vec<Candidate>>,
+    /// Misspellings corrected before the address resolved, in order.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    corrections: Option<Vec<Correction>>,
+    /// Representative point of the deepest resolved component, when the data
+    /// source reports one (`chimei_ruiju` does).
+    #[serde(skip)]
+    source_point: Option<(f64, f64)>,
+}
+
+/// One interpretation of the input, ranked against the others.
+#[derive(Debug, Clone, Serialize)]
+struct Candidate {
+    /// Share of the total score of all interpretations found, from 0 to 1.
+    confidence: f64,
+    reason: String,
+    #[serde(flatten)]
+    address: ParsedAddress,
+}
+
+impl ParsedAddress {
+    /// Adds the request-specific fields that are not part of a cached result.
+    fn annotate(
+        &mut self,
+        postal_codes: Option<&PostalCodeIndex>,
+        normalized_address: Option<String>,
+        postal_code: Option<&str>,
+        options: &RequestOptions,
+    ) {
+        self.normalized_address = normalized_address;
+        if let (Some(prefecture), Some(city)) = (&self.prefecture, &self.city) {
+            let (city_code, status) = postal_code::lookup_city_code(postal_codes, prefecture, city);
+            self.city_code = city_code;
+            self.city_code_status = Some(status);
+        }
+        self.postal_code_check = postal_code.map(|postal_code| {
+            postal_code::check(
+                postal_codes,
+                postal_code,
+                self.prefecture.as_deref(),
+                self.city.as_deref(),
+                self.town.as_deref(),
+            )
+        });
+        if options.readings {
+            self.readings = readings::readings(
+                postal_codes,
+                self.prefecture.as_deref(),
+                self.city.as_deref(),
+                self.town.as_deref(),
+                options.romaji_style,
+            );
+        }
+        self.formatted = options.format.map(|format| {
+            let components = address_format::Components {
+                prefecture: self.prefecture.as_deref(),
+                city: self.city.as_deref(),
+                town: self.town.as_deref(),
+                rest: self.rest.as_deref(),
+                rest_components: self.rest_components.as_ref(),
+                postal_code,
+            };
+            match format {
+                AddressFormat::En => {
+                    address_format::english(components, options.romaji_style, postal_codes)
+                }
+            }
+        });
+    }
+
+    /// Fills in `lat`/`lng` once at least the city is resolved, preferring
+    /// the point the data source reported over the Geolonia town list.
+    async fn geocode(&mut self, client: &MasterDataClient) {
+        let (Some(prefecture), Some(city)) = (&self.prefecture, &self.city) else {
+            return;
+        };
+        if let Some((lat, lng)) = self.source_point {
+            self.lat = Some(lat);
+            self.lng = Some(lng);
+            self.geocode_precision = Some(match self.resolution_level {
+                ResolutionLevel::Town => GeocodePrecision::Town,
+                _ => GeocodePrecision::City,
+            });
+            return;
+        }
+        if let Some(geocode) =
+            geocode::geocode(client, prefecture, city, self.town.as_deref()).await
+        {
+            self.lat = Some(geocode.lat);
+            self.lng = Some(geocode.lng);
+            self.geocode_precision = Some(geocode.precision);
+        }
+    }
+}
+
+/// The deepest address component the parser managed to resolve.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum ResolutionLevel {
+    None,
+    Prefecture,
+    City,
+    Town,
+}
+
+impl ResolutionLevel {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::Prefecture => "prefecture",
+            Self::City => "city",
+            Self::Town => "town",
+        }
+    }
+}
+
+/// Why the parser could not resolve the full address: `ParseError` when no
+/// matching component was found, `ApiError` when master data could not be
+/// fetched.
+#[derive(Debug, Clone, Serialize)]
+struct ParserError {
+    error_type: String,
+    error_message: String,
+}
+
+fn non_empty(value: String) -> Option<String> {
+    if value.is_empty() {
+        None
+    } else {
+        Some(value)
+    }
 }
 
-impl From<ParseResult> for ParsedAddress {
-    fn from(result: ParseResult) -> Self {
+impl From<ParseOutcome> for ParsedAddress {
+    fn from(outcome: ParseOutcome) -> Self {
+        let address = outcome.address;
+        let source_point = address.metadata.latitude.zip(address.metadata.longitude);
+        let resolution_level = match address.metadata.depth {
+            0 => ResolutionLevel::None,
+            1 => ResolutionLevel::Prefecture,
+            2 => ResolutionLevel::City,
+            _ => ResolutionLevel::Town,
+        };
+
+        // A fetch failure is reported with the upstream client's message. The
+        // experimental parser gives no reason for stopping otherwise, so those
+        // messages mirror the upstream `ParseErrorKind` wording.
+        let error = match (resolution_level, outcome.fetch_error) {
+            (ResolutionLevel::Town, _) => None,
+            (_, Some(message)) => Some(ParserError {
+                error_type: "ApiError".to_string(),
+                error_message: message,
+            }),
+            (level, None) => {
+                let label = match level {
+                    ResolutionLevel::None => "都道府県",
+                    ResolutionLevel::Prefecture => "市区町村",
+                    _ => "町名",
+                };
+                Some(ParserError {
+                    error_type: "ParseError".to_string(),
+                    error_message: format!("一致する{}がありませんでした", label),
+                })
+            }
+        };
+
+        let town = non_empty(address.town);
+        let rest = non_empty(address.rest);
+        let rest_components = match (resolution_level, &rest) {
+            (ResolutionLevel::Town, Some(rest)) => Some(rest_components::split_rest(
+                rest,
+                town.as_ref().is_some_and(|town| town.ends_with("丁目")),
+            )),
+            _ => None,
+        };
+
+        let prefecture = non_empty(address.prefecture);
+        let city = non_empty(address.city);
+        let prefecture_code = prefecture.as_deref().and_then(|prefecture| {
+            jisx0401::Prefecture::try_from(prefecture)
+                .ok()
+                .map(|p| p.code().to_string())
+        });
         Self {
-            prefecture: Some(result.address.prefecture),
-            city: Some(result.address.city),
-            town: Some(result.address.town),
-            rest: Some(result.address.rest),
+            prefecture,
+            prefecture_code,
+            city,
+            city_code: None,
+            city_code_status: None,
+            town,
+            rest,
+            rest_components,
+            resolution_level,
+            error,
+            normalized_address: None,
+            postal_code_check: None,
+            lat: None,
+            lng: None,
+            geocode_precision: None,
+            readings: Readings::default(),
+            formatted: None,
+            candidates: None,
+            corrections: None,
+            source_point,
         }
     }
 }
 
 #[derive(Clone)]
 struct AppState {
-    parser: Arc<Parser>,
+    parsers: Arc<ParserPool>,
     request_timeout: Duration,
+    max_batch_size: usize,
+    /// Maximum number of addresses in a `/jobs` submission.
+    max_job_size: usize,
+    batch_concurrency: usize,
+    /// Whether addresses are normalized unless a request says otherwise.
+    normalize_input: bool,
+    /// Minimum similarity for `fuzzy` corrections unless a request says
+    /// otherwise.
+    fuzzy_threshold: f64,
+    /// False while the master data warm-up is still running.
+    ready: Arc<AtomicBool>,
+    response_cache: Option<Arc<ResponseCache<ParsedAddress>>>,
+    /// Parses currently running, keyed by normalized address and options.
+    in_flight: Arc<SingleFlight<(String, ParserConfig), ParsedAddress>>,
+    /// Offline address master data; `None` fetches it remotely.
+    dataset: Option<Arc<Dataset>>,
+    /// Japan Post data for `/postal-code`, readings and city codes.
+    postal_codes: Option<Arc<PostalCodeIndex>>,
+    /// Town locations for `/reverse`; only available with an offline dataset.
+    spatial_index: Option<Arc<SpatialIndex>>,
+    /// Kana readings for `/suggest`; only available with postal codes.
+    reading_index: Option<Arc<ReadingIndex>>,
+    /// Background batch jobs; `None` when `JOBS_DIR` is not set.
+    jobs: Option<Arc<jobs::JobStore>>,
+    /// Signing and retry settings for job callbacks.
+    webhook: Arc<WebhookConfig>,
 }
 
 impl AppState {
-    fn new() -> Self {
+    fn new(
+        dataset: Option<Arc<Dataset>>,
+        postal_codes: Option<Arc<PostalCodeIndex>>,
+        jobs: Option<Arc<jobs::JobStore>>,
+    ) -> Self {
         START_TIME.set(SystemTime::now()).ok();
         info!("Initializing Japanese address parser");
 
@@ -145,13 +627,169 @@ impl AppState {
             .parse::<u64>()
             .unwrap_or(DEFAULT_REQUEST_TIMEOUT_SECS);
 
-        Self {
-            parser: Arc::new(Parser::default()),
+        let max_batch_size = std::env::var("MAX_BATCH_SIZE")
+            .unwrap_or_else(|_| DEFAULT_MAX_BATCH_SIZE.to_string())
+            .parse::<usize>()
+            .unwrap_or(DEFAULT_MAX_BATCH_SIZE);
+
+        let max_job_size = std::env::var("MAX_JOB_SIZE")
+            .unwrap_or_else(|_| DEFAULT_MAX_JOB_SIZE.to_string())
+            .parse::<usize>()
+            .unwrap_or(DEFAULT_MAX_JOB_SIZE);
+
+        let batch_concurrency = std::env::var("BATCH_CONCURRENCY")
+            .unwrap_or_else(|_| DEFAULT_BATCH_CONCURRENCY.to_string())
+            .parse::<usize>()
+            .ok()
+            .filter(|&n| n > 0)
+            .unwrap_or(DEFAULT_BATCH_CONCURRENCY);
+
+        let parser_config = ParserConfig::from_env();
+        info!(
+            event = "parser_config_loaded",
+            data_source = parser_config.data_source.as_str(),
+            correct_incomplete_city_names = parser_config.correct_incomplete_city_names,
+            verbose = parser_config.verbose,
+            "Loaded default parser options"
+        );
+
+        let normalize_input = std::env::var("NORMALIZE_INPUT")
+            .ok()
+            .and_then(|value| parse_bool(&value).ok())
+            .unwrap_or(true);
+
+        let fuzzy_threshold = std::env::var("FUZZY_THRESHOLD")
+            .unwrap_or_else(|_| DEFAULT_FUZZY_THRESHOLD.to_string())
+            .parse::<f64>()
+            .ok()
+            .filter(|threshold| (0.0..=1.0).contains(threshold))
+            .unwrap_or(DEFAULT_FUZZY_THRESHOLD);
+
+        let warmup = std::env::var("MASTER_DATA_WARMUP")
+            .map(|value| value == "true" || value == "1")
+            .unwrap_or(false);
+
+        let state = Self {
+            parsers: Arc::new(ParserPool::new(parser_config, dataset.clone())),
             request_timeout: Duration::from_secs(timeout_secs),
+            max_batch_size,
+            max_job_size,
+            batch_concurrency,
+            normalize_input,
+            fuzzy_threshold,
+            ready: Arc::new(AtomicBool::new(true)),
+            response_cache: ResponseCache::from_env().map(Arc::new),
+            in_flight: Arc::new(SingleFlight::new()),
+            spatial_index: dataset.as_deref().map(|dataset| {
+                let index = SpatialIndex::build(dataset);
+                info!(
+                    event = "spatial_index_built",
+                    towns = index.len(),
+                    "Built spatial index for reverse geocoding"
+                );
+                Arc::new(index)
+            }),
+            reading_index: postal_codes
+                .as_deref()
+                .map(|index| Arc::new(ReadingIndex::build(index))),
+            dataset,
+            postal_codes,
+            jobs,
+            webhook: Arc::new(WebhookConfig::from_env()),
+        };
+
+        // An offline dataset is already in memory, so there is nothing to warm.
+        if warmup && state.dataset.is_none() {
+            state.ready.store(false, Ordering::Relaxed);
+            tokio::spawn(warm_up_master_data(state.clone()));
         }
+
+        state
+    }
+
+    /// A master data client answering from the offline dataset, if any.
+    fn master_data(&self) -> MasterDataClient {
+        MasterDataClient::with_dataset(self.dataset.clone())
+    }
+}
+
+/// Restarts the jobs left unfinished by the last run where they stopped, and
+/// the callbacks it did not get to deliver.
+fn resume_jobs(state: &AppState) {
+    let Some(store) = &state.jobs else {
+        return;
+    };
+    for job in store.unfinished() {
+        let info = job.info();
+        info!(
+            event = "job_resumed",
+            job_id = info.id,
+            done = info.done,
+            total = info.total,
+            "Resuming batch job"
+        );
+        tokio::spawn(run_job(state.clone(), store.clone(), job));
+    }
+    for job in store.undelivered() {
+        tokio::spawn(deliver_callback(state.clone(), store.clone(), job));
     }
 }
 
+/// Preloads the prefecture master of every prefecture into the master data
+/// cache, then marks the service ready.
+async fn warm_up_master_data(state: AppState) {
+    let start_time = Instant::now();
+    let config = state.parsers.default_config();
+    info!(
+        event = "master_data_warmup_started",
+        data_source = config.data_source.as_str(),
+        "Preloading prefecture master data"
+    );
+
+    // Parsing a bare prefecture name fetches exactly its prefecture master.
+    let prefecture_names: Vec<String> = jisx0401::Prefecture::values()
+        .map(|prefecture| prefecture.name_ja().to_string())
+        .collect();
+    let failures = stream::iter(prefecture_names)
+        .map(|prefecture_name| {
+            let state = &state;
+            async move {
+                let outcome = timeout(
+                    state.request_timeout,
+                    state.parsers.parse(&prefecture_name, config),
+                )
+                .await;
+                match outcome {
+                    Ok(outcome) => outcome.fetch_error,
+                    Err(_) => Some(format!("{}: timed out", prefecture_name)),
+                }
+            }
+        })
+        .buffer_unordered(WARMUP_CONCURRENCY)
+        .filter_map(|failure| async move { failure })
+        .collect::<Vec<String>>()
+        .await;
+
+    if failures.is_empty() {
+        info!(
+            event = "master_data_warmup_completed",
+            duration_ms = start_time.elapsed().as_millis() as u64,
+            cached_entries = master_data::cache().len(),
+            "Prefecture master data preloaded"
+        );
+    } else {
+        warn!(
+            event = "master_data_warmup_completed",
+            duration_ms = start_time.elapsed().as_millis() as u64,
+            failed_prefectures = failures.len(),
+            first_error = failures[0],
+            "Prefecture master data partially preloaded"
+        );
+    }
+
+    state.ready.store(true, Ordering::Relaxed);
+}
+
 fn validate_address(address: &str) -> Result<(), String> {
     if address.trim().is_empty() {
         return Err("Address cannot be empty".to_string());
@@ -205,7 +843,7 @@ async fn request_logging_middleware(request: Request, next: Next) -> axum::respo
 async fn parse_address(
     Query(params): Query<HashMap<String, String>>,
     state: axum::extract::State<AppState>,
-) -> Result<Json<ParseResponse>, StatusCode> {
+) -> Result<(HeaderMap, Json<ParseResponse>), StatusCode> {
     let start_time = Instant::now();
     TOTAL_REQUESTS.fetch_add(1, Ordering::Relaxed);
     GET_REQUESTS.fetch_add(1, Ordering::Relaxed);
@@ -220,556 +858,3357 @@ async fn parse_address(
                 reason = "missing_address_parameter",
                 method = "GET"
             );
-            return Ok(Json(ParseResponse {
-                success: false,
-                result: None,
-                error: Some("Missing 'address' parameter".to_string()),
-                processing_time_ms: Some(start_time.elapsed().as_millis() as u64),
-            }));
+            return Ok((
+                HeaderMap::new(),
+                Json(ParseResponse {
+                    success: false,
+                    result: None,
+                    error: Some("Missing 'address' parameter".to_string()),
+                    processing_time_ms: Some(start_time.elapsed().as_millis() as u64),
+                    cache_status: None,
+                }),
+            ));
         }
     };
 
-    // Validate address
-    if let Err(validation_error) = validate_address(address) {
-        FAILED_PARSES.fetch_add(1, Ordering::Relaxed);
-        VALIDATION_ERRORS.fetch_add(1, Ordering::Relaxed);
-        warn!(
-            event = "parse_request_failed",
-            reason = "validation_failed",
-            method = "GET",
-            error = validation_error
-        );
-        return Ok(Json(ParseResponse {
-            success: false,
-            result: None,
-            error: Some(validation_error),
-            processing_time_ms: Some(start_time.elapsed().as_millis() as u64),
-        }));
-    }
-
-    info!(
-        event = "parse_request_started",
-        method = "GET",
-        address_length = address.len(),
-        "Processing address parsing request"
-    );
-
-    let parse_start = Instant::now();
-    let parse_result = timeout(state.request_timeout, state.parser.parse(address)).await;
-
-    let parsed_result = match parse_result {
-        Ok(result) => result,
-        Err(_) => {
+    let options = ParserOverrides::from_query(&params).and_then(|overrides| {
+        state.parsers.check(&overrides)?;
+        Ok((overrides, RequestOptions::from_query(&params)?))
+    });
+    let (config, request_options) = match options {
+        Ok((overrides, request_options)) => (
+            state.parsers.default_config().with_overrides(&overrides),
+            request_options,
+        ),
+        Err(option_error) => {
             FAILED_PARSES.fetch_add(1, Ordering::Relaxed);
-            TIMEOUT_ERRORS.fetch_add(1, Ordering::Relaxed);
-            error!(
-                event = "parse_request_timeout",
+            VALIDATION_ERRORS.fetch_add(1, Ordering::Relaxed);
+            warn!(
+                event = "parse_request_failed",
+                reason = "invalid_parser_options",
                 method = "GET",
-                address_length = address.len(),
-                timeout_secs = state.request_timeout.as_secs(),
-                "Request timed out"
+                error = option_error
             );
-            return Ok(Json(ParseResponse {
-                success: false,
-                result: None,
-                error: Some("Request timeout".to_string()),
-                processing_time_ms: Some(start_time.elapsed().as_millis() as u64),
-            }));
+            return Ok((
+                HeaderMap::new(),
+                Json(ParseResponse {
+                    success: false,
+                    result: None,
+                    error: Some(option_error),
+                    processing_time_ms: Some(start_time.elapsed().as_millis() as u64),
+                    cache_status: None,
+                }),
+            ));
         }
     };
 
-    let parse_duration = parse_start.elapsed();
-    let total_duration = start_time.elapsed();
-
-    let parse_time_ms = parse_duration.as_millis() as u64;
-    let total_time_ms = total_duration.as_millis() as u64;
-
-    update_parse_time_metrics(parse_time_ms);
-
-    info!(
-        event = "parse_request_completed",
-        method = "GET",
-        success = true,
-        address_length = address.len(),
-        parse_time_ms = parse_time_ms,
-        total_time_ms = total_time_ms,
-        "Successfully parsed address"
-    );
-
-    SUCCESSFUL_PARSES.fetch_add(1, Ordering::Relaxed);
-    Ok(Json(ParseResponse {
-        success: true,
-        result: Some(parsed_result.into()),
-        error: None,
-        processing_time_ms: Some(total_time_ms),
-    }))
+    let postal_code = params.get("postal_code").map(String::as_str);
+    let response = process_address(
+        &state,
+        address,
+        postal_code,
+        config,
+        &request_options,
+        "GET",
+    )
+    .await;
+    Ok((response.headers(), Json(response)))
 }
 
 async fn parse_address_post(
     axum::extract::State(state): axum::extract::State<AppState>,
     Json(payload): Json<ParseRequest>,
-) -> Result<Json<ParseResponse>, StatusCode> {
+) -> Result<(HeaderMap, Json<ParseResponse>), StatusCode> {
     let start_time = Instant::now();
     TOTAL_REQUESTS.fetch_add(1, Ordering::Relaxed);
     POST_REQUESTS.fetch_add(1, Ordering::Relaxed);
 
-    let address = payload.address.trim();
-
-    // Validate address
-    if let Err(validation_error) = validate_address(address) {
+    if let Err(option_error) = state.parsers.check(&payload.options) {
         FAILED_PARSES.fetch_add(1, Ordering::Relaxed);
         VALIDATION_ERRORS.fetch_add(1, Ordering::Relaxed);
         warn!(
             event = "parse_request_failed",
-            reason = "validation_failed",
+            reason = "invalid_parser_options",
             method = "POST",
-            error = validation_error
+            error = option_error
+        );
+        return Ok((
+            HeaderMap::new(),
+            Json(ParseResponse {
+                success: false,
+                result: None,
+                error: Some(option_error),
+                processing_time_ms: Some(start_time.elapsed().as_millis() as u64),
+                cache_status: None,
+            }),
+        ));
+    }
+
+    let address = payload.address.trim();
+    let config = state
+        .parsers
+        .default_config()
+        .with_overrides(&payload.options);
+
+    let response = process_address(
+        &state,
+        address,
+        payload.postal_code.as_deref(),
+        config,
+        &payload.request_options,
+        "POST",
+    )
+    .await;
+    Ok((response.headers(), Json(response)))
+}
+
+async fn parse_address_batch(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Json(payload): Json<BatchParseRequest>,
+) -> Result<Json<BatchParseResponse>, StatusCode> {
+    let start_time = Instant::now();
+    BATCH_REQUESTS.fetch_add(1, Ordering::Relaxed);
+
+    let batch_size = payload.addresses.len();
+
+    let invalid = if batch_size == 0 {
+        Some((
+            "invalid_batch_size",
+            "Batch must contain at least one address".to_string(),
+        ))
+    } else if batch_size > state.max_batch_size {
+        Some((
+            "invalid_batch_size",
+            format!("Batch too large (max {} addresses)", state.max_batch_size),
+        ))
+    } else {
+        state
+            .parsers
+            .check(&payload.options)
+            .err()
+            .map(|error| ("invalid_parser_options", error))
+    };
+    if let Some((reason, error)) = invalid {
+        VALIDATION_ERRORS.fetch_add(1, Ordering::Relaxed);
+        warn!(
+            event = "batch_request_failed",
+            reason = reason,
+            batch_size = batch_size,
+            max_batch_size = state.max_batch_size,
+            error = error
         );
-        return Ok(Json(ParseResponse {
+        return Ok(Json(BatchParseResponse {
             success: false,
-            result: None,
-            error: Some(validation_error),
+            total: batch_size,
+            succeeded: 0,
+            failed: 0,
+            results: Vec::new(),
+            error: Some(error),
             processing_time_ms: Some(start_time.elapsed().as_millis() as u64),
         }));
     }
 
-    info!(
-        event = "parse_request_started",
-        method = "POST",
-        address_length = address.len(),
-        "Processing address parsing request"
-    );
-
-    let parse_start = Instant::now();
-    let parse_result = timeout(state.request_timeout, state.parser.parse(address)).await;
+    let config = state
+        .parsers
+        .default_config()
+        .with_overrides(&payload.options);
 
-    let parsed_result = match parse_result {
-        Ok(result) => result,
-        Err(_) => {
-            FAILED_PARSES.fetch_add(1, Ordering::Relaxed);
-            TIMEOUT_ERRORS.fetch_add(1, Ordering::Relaxed);
-            error!(
-                event = "parse_request_timeout",
-                method = "POST",
-                address_length = address.len(),
-                timeout_secs = state.request_timeout.as_secs(),
-                "Request timed out"
-            );
-            return Ok(Json(ParseResponse {
-                success: false,
-                result: None,
-                error: Some("Request timeout".to_string()),
-                processing_time_ms: Some(start_time.elapsed().as_millis() as u64),
-            }));
-        }
-    };
+    TOTAL_REQUESTS.fetch_add(batch_size as u64, Ordering::Relaxed);
+    BATCH_ADDRESSES.fetch_add(batch_size as u64, Ordering::Relaxed);
 
-    let parse_duration = parse_start.elapsed();
-    let total_duration = start_time.elapsed();
+    info!(
+        event = "batch_request_started",
+        batch_size = batch_size,
+        concurrency = state.batch_concurrency,
+        "Processing batch address parsing request"
+    );
 
-    let parse_time_ms = parse_duration.as_millis() as u64;
-    let total_time_ms = total_duration.as_millis() as u64;
+    // `buffered` runs up to `batch_concurrency` parses at once while still
+    // yielding results in input order.
+    let request_options = &payload.request_options;
+    let results: Vec<BatchItemResult> = stream::iter(payload.addresses.into_iter().enumerate())
+        .map(|(index, item)| {
+            let state = &state;
+            async move {
+                let (id, address, postal_code) = item.into_parts();
+                let response = process_address(
+                    state,
+                    address.trim(),
+                    postal_code.as_deref(),
+                    config,
+                    request_options,
+                    "BATCH",
+                )
+                .await;
+                BatchItemResult {
+                    index,
+                    id,
+                    response,
+                }
+            }
+        })
+        .buffered(state.batch_concurrency)
+        .collect()
+        .await;
 
-    update_parse_time_metrics(parse_time_ms);
+    let succeeded = results.iter().filter(|item| item.response.success).count();
+    let failed = batch_size - succeeded;
+    let total_time_ms = start_time.elapsed().as_millis() as u64;
 
     info!(
-        event = "parse_request_completed",
-        method = "POST",
-        success = true,
-        address_length = address.len(),
-        parse_time_ms = parse_time_ms,
+        event = "batch_request_completed",
+        batch_size = batch_size,
+        succeeded = succeeded,
+        failed = failed,
         total_time_ms = total_time_ms,
-        "Successfully parsed address"
+        "Batch parsing completed"
     );
 
-    SUCCESSFUL_PARSES.fetch_add(1, Ordering::Relaxed);
-    Ok(Json(ParseResponse {
+    Ok(Json(BatchParseResponse {
         success: true,
-        result: Some(parsed_result.into()),
+        total: batch_size,
+        succeeded,
+        failed,
+        results,
         error: None,
         processing_time_ms: Some(total_time_ms),
     }))
 }
 
-async fn health() -> Json<serde_json::Value> {
-    info!(event = "health_check", status = "healthy");
+/// Parses addresses from an NDJSON request body, streaming one NDJSON result
+/// per input line back as soon as it is ready.
+///
+/// Input lines are read only as fast as results are written, so at most
+/// `batch_concurrency` lines are in memory regardless of the body size.
+async fn parse_address_stream(
+    Query(params): Query<HashMap<String, String>>,
+    axum::extract::State(state): axum::extract::State<AppState>,
+    body: Body,
+) -> Response {
+    STREAM_REQUESTS.fetch_add(1, Ordering::Relaxed);
 
-    let uptime_seconds = START_TIME
-        .get()
-        .and_then(|start| SystemTime::now().duration_since(*start).ok())
-        .map(|d| d.as_secs())
-        .unwrap_or(0);
+    let options = ParserOverrides::from_query(&params).and_then(|overrides| {
+        state.parsers.check(&overrides)?;
+        Ok((overrides, RequestOptions::from_query(&params)?))
+    });
+    let (config, request_options) = match options {
+        Ok((overrides, request_options)) => (
+            state.parsers.default_config().with_overrides(&overrides),
+            Arc::new(request_options),
+        ),
+        Err(option_error) => {
+            VALIDATION_ERRORS.fetch_add(1, Ordering::Relaxed);
+            warn!(
+                event = "stream_request_failed",
+                reason = "invalid_parser_options",
+                error = option_error
+            );
+            return Json(json!({"success": false, "error": option_error})).into_response();
+        }
+    };
 
-    Json(json!({
-        "status": "healthy",
-        "service": "japanese-address-parser-api",
-        "version": env!("CARGO_PKG_VERSION"),
-        "timestamp": chrono::Utc::now().to_rfc3339(),
-        "uptime_seconds": uptime_seconds
-    }))
-}
+    info!(
+        event = "stream_request_started",
+        concurrency = state.batch_concurrency,
+        "Processing streamed address parsing request"
+    );
+    let start_time = Instant::now();
+    let concurrency = state.batch_concurrency;
+    let lines = Arc::new(AtomicU64::new(0));
+    let failed = Arc::new(AtomicU64::new(0));
 
-async fn metrics() -> (StatusCode, String) {
-    let total = TOTAL_REQUESTS.load(Ordering::Relaxed);
-    let successful = SUCCESSFUL_PARSES.load(Ordering::Relaxed);
-    let failed = FAILED_PARSES.load(Ordering::Relaxed);
-    let get_requests = GET_REQUESTS.load(Ordering::Relaxed);
-    let post_requests = POST_REQUESTS.load(Ordering::Relaxed);
-    let timeout_errors = TIMEOUT_ERRORS.load(Ordering::Relaxed);
-    let validation_errors = VALIDATION_ERRORS.load(Ordering::Relaxed);
+    let results = ndjson::lines(body.into_data_stream(), MAX_STREAM_LINE_LENGTH)
+        .enumerate()
+        .filter(|(_, line)| {
+            let blank = matches!(line, Ok(line) if line.trim().is_empty());
+            std::future::ready(!blank)
+        })
+        .map({
+            let lines = lines.clone();
+            move |(index, line)| {
+                let state = state.clone();
+                let request_options = request_options.clone();
+                lines.fetch_add(1, Ordering::Relaxed);
+                TOTAL_REQUESTS.fetch_add(1, Ordering::Relaxed);
+                STREAM_ADDRESSES.fetch_add(1, Ordering::Relaxed);
+                async move {
+                    let item = line.and_then(|line| {
+                        serde_json::from_str::<BatchAddress>(&line)
+                            .map_err(|e| format!("Invalid JSON line: {}", e))
+                    });
+                    let (id, address, postal_code) = match item {
+                        Ok(item) => item.into_parts(),
+                        Err(error) => {
+                            FAILED_PARSES.fetch_add(1, Ordering::Relaxed);
+                            VALIDATION_ERRORS.fetch_add(1, Ordering::Relaxed);
+                            return BatchItemResult {
+                                index,
+                                id: None,
+                                response: ParseResponse {
+                                    success: false,
+                                    result: None,
+                                    error: Some(error),
+                                    processing_time_ms: None,
+                                    cache_status: None,
+                                },
+                            };
+                        }
+                    };
+                    let response = process_address(
+                        &state,
+                        address.trim(),
+                        postal_code.as_deref(),
+                        config,
+                        &request_options,
+                        "STREAM",
+                    )
+                    .await;
+                    BatchItemResult {
+                        index,
+                        id,
+                        response,
+                    }
+                }
+            }
+        })
+        .buffered(concurrency)
+        .map({
+            let failed = failed.clone();
+            move |item| {
+                if !item.response.success {
+                    failed.fetch_add(1, Ordering::Relaxed);
+                }
+                let mut line = serde_json::to_vec(&item).unwrap_or_default();
+                line.push(b'\n');
+                Ok::<_, std::convert::Infallible>(Bytes::from(line))
+            }
+        });
 
-    let parse_time_total = PARSE_TIME_TOTAL_MS.load(Ordering::Relaxed);
-    let min_parse_time = MIN_PARSE_TIME_MS.load(Ordering::Relaxed);
-    let max_parse_time = MAX_PARSE_TIME_MS.load(Ordering::Relaxed);
+    let completed = stream::once(async move {
+        info!(
+            event = "stream_request_completed",
+            lines = lines.load(Ordering::Relaxed),
+            failed = failed.load(Ordering::Relaxed),
+            total_time_ms = start_time.elapsed().as_millis() as u64,
+            "Streamed parsing completed"
+        );
+        None
+    })
+    .filter_map(std::future::ready);
 
-    let avg_parse_time = if successful > 0 {
-        parse_time_total as f64 / successful as f64
-    } else {
-        0.0
-    };
+    (
+        [(header::CONTENT_TYPE, "application/x-ndjson")],
+        Body::from_stream(results.chain(completed)),
+    )
+        .into_response()
+}
 
-    let success_rate = if total > 0 {
-        (successful as f64 / total as f64) * 100.0
-    } else {
-        0.0
+/// Columns appended to each row by `/parse/csv`.
+const CSV_RESULT_COLUMNS: [&str; 5] = ["prefecture", "city", "town", "rest", "error"];
+
+/// Parses the address column of an uploaded CSV file and returns the file
+/// with `CSV_RESULT_COLUMNS` appended to every row.
+async fn parse_address_csv(
+    Query(params): Query<HashMap<String, String>>,
+    axum::extract::State(state): axum::extract::State<AppState>,
+    body: Bytes,
+) -> Response {
+    let start_time = Instant::now();
+    CSV_REQUESTS.fetch_add(1, Ordering::Relaxed);
+
+    let failure = |error: String| {
+        VALIDATION_ERRORS.fetch_add(1, Ordering::Relaxed);
+        warn!(event = "csv_request_failed", error = error);
+        Json(json!({"success": false, "error": error})).into_response()
     };
 
-    let uptime_seconds = START_TIME
-        .get()
-        .and_then(|start| SystemTime::now().duration_since(*start).ok())
-        .map(|d| d.as_secs())
-        .unwrap_or(0);
+    let options = ParserOverrides::from_query(&params).and_then(|overrides| {
+        state.parsers.check(&overrides)?;
+        let encoding = params
+            .get("encoding")
+            .map(|value| value.parse::<csv_table::Encoding>())
+            .transpose()?;
+        let column = params
+            .get("address_column")
+            .map(|value| value.parse::<csv_table::Column>())
+            .transpose()?;
+        let has_headers = params
+            .get("has_headers")
+            .map(|value| {
+                parse_bool(value).map_err(|_| {
+                    "Invalid 'has_headers' parameter (expected true or false)".to_string()
+                })
+            })
+            .transpose()?
+            .unwrap_or(true);
+        let request_options = RequestOptions::from_query(&params)?;
+        Ok((overrides, request_options, encoding, column, has_headers))
+    });
+    let (overrides, request_options, encoding, column, has_headers) = match options {
+        Ok(options) => options,
+        Err(error) => return failure(error),
+    };
 
-    let buckets = *PARSE_TIME_BUCKETS.lock().unwrap();
+    let table = match csv_table::Table::read(&body, encoding, has_headers) {
+        Ok(table) => table,
+        Err(error) => return failure(error),
+    };
+    let column = match table.address_column(column.as_ref()) {
+        Ok(column) => column,
+        Err(error) => return failure(error),
+    };
+    let row_count = table.rows.len();
+    if row_count == 0 {
+        return failure("CSV must contain at least one row".to_string());
+    }
+    if row_count > state.max_batch_size {
+        return failure(format!("CSV too large (max {} rows)", state.max_batch_size));
+    }
 
+    TOTAL_REQUESTS.fetch_add(row_count as u64, Ordering::Relaxed);
+    CSV_ROWS.fetch_add(row_count as u64, Ordering::Relaxed);
     info!(
-        event = "metrics_requested",
-        total_requests = total,
-        successful_parses = successful,
-        failed_parses = failed,
-        success_rate = success_rate,
-        avg_parse_time_ms = avg_parse_time
+        event = "csv_request_started",
+        rows = row_count,
+        encoding = table.encoding.as_str(),
+        address_column = column,
+        "Processing CSV address parsing request"
     );
 
-    let prometheus_metrics = format!(
-        "# HELP japanese_address_parser_requests_total Total number of address parsing requests\n\
-         # TYPE japanese_address_parser_requests_total counter\n\
-         japanese_address_parser_requests_total {}\n\
-         \n\
-         # HELP japanese_address_parser_requests_by_method_total Total requests by HTTP method\n\
-         # TYPE japanese_address_parser_requests_by_method_total counter\n\
-         japanese_address_parser_requests_by_method_total{{method=\"GET\"}} {}\n\
-         japanese_address_parser_requests_by_method_total{{method=\"POST\"}} {}\n\
-         \n\
-         # HELP japanese_address_parser_requests_successful_total Total number of successful address parsing requests\n\
-         # TYPE japanese_address_parser_requests_successful_total counter\n\
-         japanese_address_parser_requests_successful_total {}\n\
-         \n\
-         # HELP japanese_address_parser_requests_failed_total Total number of failed address parsing requests\n\
-         # TYPE japanese_address_parser_requests_failed_total counter\n\
-         japanese_address_parser_requests_failed_total {}\n\
-         \n\
-         # HELP japanese_address_parser_timeout_errors_total Total number of timeout errors\n\
-         # TYPE japanese_address_parser_timeout_errors_total counter\n\
-         japanese_address_parser_timeout_errors_total {}\n\
-         \n\
-         # HELP japanese_address_parser_validation_errors_total Total number of validation errors\n\
-         # TYPE japanese_address_parser_validation_errors_total counter\n\
-         japanese_address_parser_validation_errors_total {}\n\
-         \n\
-         # HELP japanese_address_parser_success_rate_percent Success rate of address parsing requests as percentage\n\
-         # TYPE japanese_address_parser_success_rate_percent gauge\n\
-         japanese_address_parser_success_rate_percent {:.2}\n\
-         \n\
-         # HELP japanese_address_parser_parse_duration_seconds_total Total time spent parsing addresses in seconds\n\
-         # TYPE japanese_address_parser_parse_duration_seconds_total counter\n\
-         japanese_address_parser_parse_duration_seconds_total {:.3}\n\
-         \n\
-         # HELP japanese_address_parser_parse_duration_seconds Average parsing duration in seconds\n\
-         # TYPE japanese_address_parser_parse_duration_seconds gauge\n\
-         japanese_address_parser_parse_duration_seconds{{stat=\"avg\"}} {:.6}\n\
-         japanese_address_parser_parse_duration_seconds{{stat=\"min\"}} {:.6}\n\
-         japanese_address_parser_parse_duration_seconds{{stat=\"max\"}} {:.6}\n\
-         \n\
-         # HELP japanese_address_parser_parse_duration_histogram Parse duration distribution\n\
-         # TYPE japanese_address_parser_parse_duration_histogram histogram\n\
-         japanese_address_parser_parse_duration_histogram_bucket{{le=\"0.001\"}} {}\n\
-         japanese_address_parser_parse_duration_histogram_bucket{{le=\"0.005\"}} {}\n\
-         japanese_address_parser_parse_duration_histogram_bucket{{le=\"0.010\"}} {}\n\
-         japanese_address_parser_parse_duration_histogram_bucket{{le=\"0.025\"}} {}\n\
-         japanese_address_parser_parse_duration_histogram_bucket{{le=\"0.050\"}} {}\n\
-         japanese_address_parser_parse_duration_histogram_bucket{{le=\"0.100\"}} {}\n\
-         japanese_address_parser_parse_duration_histogram_bucket{{le=\"0.500\"}} {}\n\
-         japanese_address_parser_parse_duration_histogram_bucket{{le=\"+Inf\"}} {}\n\
-         \n\
-         # HELP japanese_address_parser_uptime_seconds Service uptime in seconds\n\
-         # TYPE japanese_address_parser_uptime_seconds gauge\n\
-         japanese_address_parser_uptime_seconds {}\n",
-        total,
-        get_requests,
-        post_requests,
-        successful,
-        failed,
-        timeout_errors,
-        validation_errors,
-        success_rate,
-        parse_time_total as f64 / 1000.0, // Convert to seconds
-        avg_parse_time / 1000.0,          // Convert to seconds
-        if min_parse_time == u64::MAX { 0.0 } else { min_parse_time as f64 / 1000.0 },
-        max_parse_time as f64 / 1000.0,
-        buckets[0],
-        buckets[0] + buckets[1],
-        buckets[0] + buckets[1] + buckets[2],
-        buckets[0] + buckets[1] + buckets[2] + buckets[3],
-        buckets[0] + buckets[1] + buckets[2] + buckets[3] + buckets[4],
-        buckets[0] + buckets[1] + buckets[2] + buckets[3] + buckets[4] + buckets[5],
-        buckets[0] + buckets[1] + buckets[2] + buckets[3] + buckets[4] + buckets[5] + buckets[6],
-        total,
-        uptime_seconds
+    let config = state.parsers.default_config().with_overrides(&overrides);
+    let request_options = &request_options;
+    let addresses: Vec<Option<String>> = table
+        .rows
+        .iter()
+        .map(|row| row.get(column).map(str::to_string))
+        .collect();
+    let appended: Vec<Vec<String>> = stream::iter(addresses)
+        .map(|address| {
+            let state = &state;
+            async move {
+                let Some(address) = address else {
+                    let error = format!("Row has no column {}", column);
+                    return [
+                        String::new(),
+                        String::new(),
+                        String::new(),
+                        String::new(),
+                        error,
+                    ]
+                    .to_vec();
+                };
+                let response =
+                    process_address(state, address.trim(), None, config, request_options, "CSV")
+                        .await;
+                let Some(result) = response.result else {
+                    let error = response.error.unwrap_or_default();
+                    return [
+                        String::new(),
+                        String::new(),
+                        String::new(),
+                        String::new(),
+                        error,
+                    ]
+                    .to_vec();
+                };
+                [
+                    result.prefecture,
+                    result.city,
+                    result.town,
+                    result.rest,
+                    result.error.map(|error| error.error_message),
+                ]
+                .into_iter()
+                .map(Option::unwrap_or_default)
+                .collect()
+            }
+        })
+        .buffered(state.batch_concurrency)
+        .collect()
+        .await;
+
+    let failed = appended
+        .iter()
+        .filter(|fields| !fields[4].is_empty())
+        .count();
+    info!(
+        event = "csv_request_completed",
+        rows = row_count,
+        failed = failed,
+        total_time_ms = start_time.elapsed().as_millis() as u64,
+        "CSV parsing completed"
     );
 
-    (StatusCode::OK, prometheus_metrics)
+    let content_type = match table.encoding {
+        csv_table::Encoding::Utf8 => "text/csv; charset=utf-8",
+        csv_table::Encoding::ShiftJis => "text/csv; charset=Shift_JIS",
+    };
+    (
+        [(header::CONTENT_TYPE, content_type)],
+        table.write(&CSV_RESULT_COLUMNS, appended),
+    )
+        .into_response()
 }
 
-fn create_app() -> Router {
-    let state = AppState::new();
+/// Whether a parse was asked for by a client, or made internally on behalf
+/// of another parse or request. Only the former count towards
+/// `SUCCESSFUL_PARSES` and `FAILED_PARSES`, which are relative to
+/// `TOTAL_REQUESTS`.
+///
+/// A requested parse gets `request_timeout` for everything it does, fuzzy
+/// corrections, geocoding and candidates included. An internal parse
+/// finishes by the deadline it is given, normally the one of the parse it
+/// is made for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ParseKind {
+    Requested,
+    Internal(tokio::time::Instant),
+}
 
-    let max_request_size = std::env::var("MAX_REQUEST_SIZE")
-        .unwrap_or_else(|_| DEFAULT_MAX_REQUEST_SIZE.to_string())
-        .parse::<usize>()
-        .unwrap_or(DEFAULT_MAX_REQUEST_SIZE);
+impl ParseKind {
+    fn deadline(self, state: &AppState) -> tokio::time::Instant {
+        match self {
+            ParseKind::Requested => tokio::time::Instant::now() + state.request_timeout,
+            ParseKind::Internal(deadline) => deadline,
+        }
+    }
 
-    Router::new()
-        .route("/parse", get(parse_address).post(parse_address_post))
-        .route("/health", get(health))
-        .route("/metrics", get(metrics))
-        .with_state(state)
-        .layer(
-            ServiceBuilder::new()
-                .layer(middleware::from_fn(request_logging_middleware))
-                .layer(TraceLayer::new_for_http())
-                .layer(RequestBodyLimitLayer::new(max_request_size))
-                .layer(
-                    CorsLayer::new()
-                        .allow_origin(Any)
-                        .allow_methods(Any)
-                        .allow_headers(Any),
-                ),
+    fn record_outcome(self, success: bool) {
+        if matches!(self, ParseKind::Internal(_)) {
+            return;
+        }
+        if success {
+            SUCCESSFUL_PARSES.fetch_add(1, Ordering::Relaxed);
+        } else {
+            FAILED_PARSES.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Validates, normalizes and parses a single address, recording parse metrics.
+///
+/// Callers are responsible for the request counters; `method` is only used
+/// to label log events.
+async fn process_address(
+    state: &AppState,
+    address: &str,
+    postal_code: Option<&str>,
+    config: ParserConfig,
+    options: &RequestOptions,
+    method: &'static str,
+) -> ParseResponse {
+    process_address_as(
+        state,
+        address,
+        postal_code,
+        config,
+        options,
+        method,
+        ParseKind::Requested,
+    )
+    .await
+}
+
+/// [`process_address`] for a parse of the given kind.
+async fn process_address_as(
+    state: &AppState,
+    address: &str,
+    postal_code: Option<&str>,
+    config: ParserConfig,
+    options: &RequestOptions,
+    method: &'static str,
+    kind: ParseKind,
+) -> ParseResponse {
+    let start_time = Instant::now();
+    let deadline = kind.deadline(state);
+    let normalize = options.normalize.unwrap_or(state.normalize_input);
+
+    // Validate the raw input, then make sure normalization left something to parse
+    let address = validate_address(address).and_then(|_| {
+        if !normalize {
+            return Ok(address.to_string());
+        }
+        let normalized = normalize::normalize_address(address);
+        validate_address(&normalized).map(|_| normalized)
+    });
+    let address = match address {
+        Ok(address) => address,
+        Err(validation_error) => {
+            kind.record_outcome(false);
+            VALIDATION_ERRORS.fetch_add(1, Ordering::Relaxed);
+            warn!(
+                event = "parse_request_failed",
+                reason = "validation_failed",
+                method = method,
+                error = validation_error
+            );
+            return ParseResponse {
+                success: false,
+                result: None,
+                error: Some(validation_error),
+                processing_time_ms: Some(start_time.elapsed().as_millis() as u64),
+                cache_status: None,
+            };
+        }
+    };
+    let address = address.as_str();
+    let normalized_address = normalize.then(|| address.to_string());
+
+    if let Some(mut cached) = state
+        .response_cache
+        .as_ref()
+        .and_then(|cache| cache.get(address, config))
+    {
+        if options.fuzzy {
+            correct_typos(
+                state,
+                address,
+                &mut cached,
+                config,
+                options,
+                method,
+                deadline,
+            )
+            .await;
+        }
+        cached.annotate(
+            state.postal_codes.as_deref(),
+            normalized_address,
+            postal_code,
+            options,
+        );
+        if options.geocode {
+            let client = state.master_data();
+            let _ = timeout_at(deadline, cached.geocode(&client)).await;
+        }
+        if let Some(limit) = options.candidates {
+            cached.candidates = Some(
+                find_candidates(state, address, &cached, config, limit, method, deadline).await,
+            );
+        }
+        debug!(
+            event = "parse_cache_hit",
+            method = method,
+            address_length = address.len(),
+            "Serving parse result from response cache"
+        );
+        kind.record_outcome(true);
+        return ParseResponse {
+            success: true,
+            result: Some(cached),
+            error: None,
+            processing_time_ms: Some(start_time.elapsed().as_millis() as u64),
+            cache_status: Some(CacheStatus::Hit),
+        };
+    }
+
+    info!(
+        event = "parse_request_started",
+        method = method,
+        address_length = address.len(),
+        data_source = config.data_source.as_str(),
+        "Processing address parsing request"
+    );
+
+    let parse_start = Instant::now();
+    // Identical concurrent requests share a single parse.
+    let parse = state.in_flight.run((address.to_string(), config), || {
+        let parsers = state.parsers.clone();
+        let address = address.to_string();
+        async move { ParsedAddress::from(parsers.parse(&address, config).await) }
+    });
+    let parse_result = timeout_at(deadline, parse).await;
+
+    let mut parsed_address = match parse_result {
+        Ok((parsed_address, coalesced)) => {
+            if coalesced {
+                COALESCED_PARSES.fetch_add(1, Ordering::Relaxed);
+                debug!(
+                    event = "parse_coalesced",
+                    method = method,
+                    address_length = address.len(),
+                    "Joined an in-flight parse of the same address"
+                );
+            }
+            parsed_address
+        }
+        Err(_) => {
+            kind.record_outcome(false);
+            TIMEOUT_ERRORS.fetch_add(1, Ordering::Relaxed);
+            error!(
+                event = "parse_request_timeout",
+                method = method,
+                address_length = address.len(),
+                timeout_secs = state.request_timeout.as_secs(),
+                "Request timed out"
+            );
+            return ParseResponse {
+                success: false,
+                result: None,
+                error: Some("Request timeout".to_string()),
+                processing_time_ms: Some(start_time.elapsed().as_millis() as u64),
+                cache_status: None,
+            };
+        }
+    };
+
+    let parse_duration = parse_start.elapsed();
+    let total_duration = start_time.elapsed();
+
+    let parse_time_ms = parse_duration.as_millis() as u64;
+    let total_time_ms = total_duration.as_millis() as u64;
+
+    update_parse_time_metrics(parse_time_ms);
+
+    // Fetch failures are transient, so only definitive results are cached.
+    let cache_status = state.response_cache.as_ref().map(|cache| {
+        let fetch_failed = parsed_address
+            .error
+            .as_ref()
+            .is_some_and(|error| error.error_type == "ApiError");
+        if !fetch_failed {
+            cache.insert(address, config, parsed_address.clone());
+        }
+        CacheStatus::Miss
+    });
+
+    if options.fuzzy {
+        correct_typos(
+            state,
+            address,
+            &mut parsed_address,
+            config,
+            options,
+            method,
+            deadline,
         )
+        .await;
+    }
+    parsed_address.annotate(
+        state.postal_codes.as_deref(),
+        normalized_address,
+        postal_code,
+        options,
+    );
+    if options.geocode {
+        let client = state.master_data();
+        let _ = timeout_at(deadline, parsed_address.geocode(&client)).await;
+    }
+    if let Some(limit) = options.candidates {
+        parsed_address.candidates = Some(
+            find_candidates(
+                state,
+                address,
+                &parsed_address,
+                config,
+                limit,
+                method,
+                deadline,
+            )
+            .await,
+        );
+    }
+
+    info!(
+        event = "parse_request_completed",
+        method = method,
+        success = true,
+        address_length = address.len(),
+        resolution_level = parsed_address.resolution_level.as_str(),
+        parser_error = parsed_address.error.as_ref().map(|e| e.error_type.as_str()),
+        parse_time_ms = parse_time_ms,
+        total_time_ms = total_time_ms,
+        "Successfully parsed address"
+    );
+
+    kind.record_outcome(true);
+    ParseResponse {
+        success: true,
+        result: Some(parsed_address),
+        error: None,
+        processing_time_ms: Some(total_time_ms),
+        cache_status,
+    }
 }
 
-async fn shutdown_signal() {
-    let ctrl_c = async {
-        signal::ctrl_c()
-            .await
-            .expect("failed to install Ctrl+C handler");
+/// Corrects misspelled names one level at a time, re-parsing after each
+/// correction and keeping it only if the address resolves deeper.
+/// Master data fetches and re-parses all run against `deadline`; corrections
+/// made by then are kept.
+async fn correct_typos(
+    state: &AppState,
+    address: &str,
+    parsed: &mut ParsedAddress,
+    config: ParserConfig,
+    options: &RequestOptions,
+    method: &'static str,
+    deadline: tokio::time::Instant,
+) {
+    let threshold = options
+        .fuzzy_threshold
+        .unwrap_or(state.fuzzy_threshold)
+        .clamp(0.0, 1.0);
+    let reparse_options = RequestOptions {
+        normalize: Some(false),
+        ..Default::default()
+    };
+    let client = state.master_data();
+    let mut address = address.to_string();
+    let mut corrections = Vec::new();
+    while parsed.resolution_level < ResolutionLevel::Town {
+        let correct = fuzzy::correct(
+            &client,
+            &address,
+            parsed.prefecture.as_deref(),
+            parsed.city.as_deref(),
+            threshold,
+        );
+        let Ok(Some((corrected, correction))) = timeout_at(deadline, correct).await else {
+            break;
+        };
+        // Boxed because this recurses into `process_address`.
+        let response = Box::pin(process_address_as(
+            state,
+            &corrected,
+            None,
+            config,
+            &reparse_options,
+            method,
+            ParseKind::Internal(deadline),
+        ))
+        .await;
+        let Some(result) = response
+            .result
+            .filter(|result| result.resolution_level > parsed.resolution_level)
+        else {
+            break;
+        };
+        debug!(
+            event = "fuzzy_correction_applied",
+            method = method,
+            field = correction.field,
+            similarity = correction.similarity,
+            "Corrected a misspelled address component"
+        );
+        *parsed = result;
+        address = corrected;
+        corrections.push(correction);
+    }
+    if !corrections.is_empty() {
+        FUZZY_CORRECTIONS.fetch_add(1, Ordering::Relaxed);
+        parsed.corrections = Some(corrections);
+    }
+}
+
+/// Ranks the parse as written against interpretations that fill in omitted
+/// components. Each is scored by how deep it resolved, discounted for every
+/// inferred component; confidence is the share of the total score. Only the
+/// most plausible interpretations are parsed, no more than `limit` or
+/// `MAX_INTERPRETATIONS`.
+async fn find_candidates(
+    state: &AppState,
+    address: &str,
+    parsed: &ParsedAddress,
+    config: ParserConfig,
+    limit: usize,
+    method: &'static str,
+    deadline: tokio::time::Instant,
+) -> Vec<Candidate> {
+    let score = |level: ResolutionLevel, inferred: usize| {
+        let depth = match level {
+            ResolutionLevel::None => 0.0,
+            ResolutionLevel::Prefecture => 0.25,
+            ResolutionLevel::City => 0.5,
+            ResolutionLevel::Town => 1.0,
+        };
+        depth * 0.8f64.powi(inferred as i32)
+    };
+
+    let masters = if state.dataset.is_none() && parsed.city.is_none() {
+        let client = state.master_data();
+        let fetch = candidates::fetch_masters(&client, parsed.prefecture.as_deref());
+        timeout_at(deadline, fetch).await.unwrap_or_default()
+    } else {
+        Vec::new()
+    };
+    let places = candidates::Places {
+        dataset: state.dataset.as_deref(),
+        postal_codes: state.postal_codes.as_deref(),
+        masters: &masters,
+    };
+    let interpretations = candidates::interpretations(
+        places,
+        address,
+        parsed.prefecture.as_deref(),
+        parsed.city.as_deref(),
+    );
+    let options = RequestOptions {
+        normalize: Some(false),
+        ..Default::default()
+    };
+    let alternatives: Vec<_> = stream::iter(
+        interpretations
+            .into_iter()
+            .take(limit.min(MAX_INTERPRETATIONS)),
+    )
+    .map(|interpretation| {
+        let options = &options;
+        async move {
+            // Boxed because this recurses into `process_address`.
+            let response = Box::pin(process_address_as(
+                state,
+                &interpretation.address,
+                None,
+                config,
+                options,
+                method,
+                ParseKind::Internal(deadline),
+            ))
+            .await;
+            response.result.map(|result| {
+                let score = score(result.resolution_level, interpretation.inferred);
+                (score, interpretation.reason, result)
+            })
+        }
+    })
+    .buffered(state.batch_concurrency)
+    .filter_map(|alternative| async move { alternative })
+    .collect()
+    .await;
+
+    let mut scored = vec![(
+        score(parsed.resolution_level, 0),
+        "parsed as written".to_string(),
+        parsed.clone(),
+    )];
+    scored.extend(alternatives);
+    scored.retain(|(score, _, _)| *score > 0.0);
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+    let mut seen = std::collections::HashSet::new();
+    scored.retain(|(_, _, address)| {
+        seen.insert((
+            address.prefecture.clone(),
+            address.city.clone(),
+            address.town.clone(),
+        ))
+    });
+
+    let total: f64 = scored.iter().map(|(score, _, _)| score).sum();
+    scored
+        .into_iter()
+        .take(limit.min(MAX_CANDIDATES))
+        .map(|(score, reason, address)| Candidate {
+            confidence: (score / total * 1000.0).round() / 1000.0,
+            reason: format!(
+                "{}; resolved to {} level",
+                reason,
+                address.resolution_level.as_str()
+            ),
+            address,
+        })
+        .collect()
+}
+
+#[derive(Debug, Serialize)]
+struct PostalCodeResponse {
+    success: bool,
+    postal_code: String,
+    results: Vec<PostalEntry>,
+    error: Option<String>,
+}
+
+async fn lookup_postal_code(
+    Path(code): Path<String>,
+    axum::extract::State(state): axum::extract::State<AppState>,
+) -> Json<PostalCodeResponse> {
+    POSTAL_CODE_LOOKUPS.fetch_add(1, Ordering::Relaxed);
+
+    let failure = |postal_code: String, error: String| {
+        warn!(
+            event = "postal_code_lookup_failed",
+            postal_code = postal_code,
+            error = error
+        );
+        Json(PostalCodeResponse {
+            success: false,
+            postal_code,
+            results: Vec::new(),
+            error: Some(error),
+        })
     };
 
-    #[cfg(unix)]
-    let terminate = async {
-        signal::unix::signal(signal::unix::SignalKind::terminate())
-            .expect("failed to install signal handler")
-            .recv()
-            .await;
-    };
+    let Some(index) = &state.postal_codes else {
+        return failure(code, "Postal code dataset is not loaded".to_string());
+    };
+    let Some(postal_code) = postal_code::canonical_postal_code(&code) else {
+        let error = format!("Invalid postal code '{}' (expected 7 digits)", code);
+        return failure(code, error);
+    };
+    let Some(entries) = index.lookup(&postal_code) else {
+        let error = format!("Postal code {} not found", postal_code);
+        return failure(postal_code, error);
+    };
+
+    info!(
+        event = "postal_code_lookup_completed",
+        postal_code = postal_code,
+        results = entries.len()
+    );
+    Json(PostalCodeResponse {
+        success: true,
+        postal_code,
+        results: entries.to_vec(),
+        error: None,
+    })
+}
+
+#[derive(Debug, Serialize)]
+struct ReverseGeocodeResponse {
+    success: bool,
+    result: Option<ReverseGeocodeResult>,
+    error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct ReverseGeocodeResult {
+    prefecture: String,
+    prefecture_code: Option<String>,
+    city: String,
+    city_code: Option<String>,
+    city_code_status: CityCodeStatus,
+    town: String,
+    /// Representative point of the town.
+    lat: f64,
+    lng: f64,
+    /// Distance from the requested point to `lat`/`lng`.
+    distance_m: f64,
+}
+
+async fn reverse_geocode(
+    Query(params): Query<HashMap<String, String>>,
+    axum::extract::State(state): axum::extract::State<AppState>,
+) -> Json<ReverseGeocodeResponse> {
+    REVERSE_GEOCODE_REQUESTS.fetch_add(1, Ordering::Relaxed);
+
+    let failure = |error: String| {
+        warn!(event = "reverse_geocode_failed", error = error);
+        Json(ReverseGeocodeResponse {
+            success: false,
+            result: None,
+            error: Some(error),
+        })
+    };
+
+    let coordinate = |name: &str, limit: f64| -> Result<f64, String> {
+        params
+            .get(name)
+            .ok_or_else(|| format!("Missing '{}' parameter", name))?
+            .parse::<f64>()
+            .ok()
+            .filter(|value| value.abs() <= limit)
+            .ok_or_else(|| format!("Invalid '{}' parameter", name))
+    };
+    let (lat, lng) = match (coordinate("lat", 90.0), coordinate("lng", 180.0)) {
+        (Ok(lat), Ok(lng)) => (lat, lng),
+        (Err(error), _) | (_, Err(error)) => return failure(error),
+    };
+
+    let Some(index) = &state.spatial_index else {
+        return failure(
+            "Reverse geocoding requires an offline dataset (DATASET_MODE=local or embedded)"
+                .to_string(),
+        );
+    };
+    let Some((town, distance)) = index.nearest(lat, lng) else {
+        return failure(format!("No town found near {}, {}", lat, lng));
+    };
+
+    let (city_code, city_code_status) =
+        postal_code::lookup_city_code(state.postal_codes.as_deref(), &town.prefecture, &town.city);
+    info!(
+        event = "reverse_geocode_completed",
+        distance_m = distance,
+        "Resolved coordinates to the nearest town"
+    );
+    Json(ReverseGeocodeResponse {
+        success: true,
+        result: Some(ReverseGeocodeResult {
+            prefecture: town.prefecture.clone(),
+            prefecture_code: jisx0401::Prefecture::try_from(town.prefecture.as_str())
+                .ok()
+                .map(|p| p.code().to_string()),
+            city: town.city.clone(),
+            city_code,
+            city_code_status,
+            town: town.town.clone(),
+            lat: town.lat,
+            lng: town.lng,
+            distance_m: distance.round(),
+        }),
+        error: None,
+    })
+}
+
+#[derive(Debug, Serialize)]
+struct SuggestResponse {
+    success: bool,
+    query: String,
+    suggestions: Vec<Suggestion>,
+    error: Option<String>,
+}
+
+async fn suggest_addresses(
+    Query(params): Query<HashMap<String, String>>,
+    axum::extract::State(state): axum::extract::State<AppState>,
+) -> Json<SuggestResponse> {
+    SUGGEST_REQUESTS.fetch_add(1, Ordering::Relaxed);
+
+    let query = params.get("q").cloned().unwrap_or_default();
+    let failure = |query: String, error: String| {
+        warn!(event = "suggest_failed", error = error);
+        Json(SuggestResponse {
+            success: false,
+            query,
+            suggestions: Vec::new(),
+            error: Some(error),
+        })
+    };
+
+    if query.trim().is_empty() {
+        return failure(query, "Missing 'q' parameter".to_string());
+    }
+    if query.chars().count() > MAX_ADDRESS_LENGTH {
+        let error = format!("Query too long (max {} characters)", MAX_ADDRESS_LENGTH);
+        return failure(query, error);
+    }
+    let limit = match params.get("limit").map(|limit| limit.parse::<usize>()) {
+        None => DEFAULT_SUGGEST_LIMIT,
+        Some(Ok(limit)) if (1..=MAX_SUGGEST_LIMIT).contains(&limit) => limit,
+        Some(_) => {
+            let error = format!(
+                "Invalid 'limit' parameter (expected 1-{})",
+                MAX_SUGGEST_LIMIT
+            );
+            return failure(query, error);
+        }
+    };
+
+    let client = state.master_data();
+    let suggest = suggest::suggest(&client, state.reading_index.as_deref(), &query, limit);
+    let suggestions = match timeout(state.request_timeout, suggest).await {
+        Ok(suggestions) => suggestions,
+        Err(_) => {
+            TIMEOUT_ERRORS.fetch_add(1, Ordering::Relaxed);
+            return failure(query, "Suggest request timed out".to_string());
+        }
+    };
+
+    debug!(event = "suggest_completed", results = suggestions.len());
+    Json(SuggestResponse {
+        success: true,
+        query,
+        suggestions,
+        error: None,
+    })
+}
+
+/// Structured address to compose; accepts a `ParsedAddress` as returned by
+/// `/parse`, ignoring fields it does not use.
+#[derive(Debug, Deserialize)]
+struct FormatRequest {
+    prefecture: Option<String>,
+    city: Option<String>,
+    town: Option<String>,
+    rest: Option<String>,
+    rest_components: Option<RestComponents>,
+    postal_code: Option<String>,
+    #[serde(flatten)]
+    style: JapaneseStyle,
+}
+
+#[derive(Debug, Serialize)]
+struct FormatResponse {
+    success: bool,
+    address: Option<String>,
+    error: Option<String>,
+}
+
+async fn format_address(Json(payload): Json<FormatRequest>) -> Json<FormatResponse> {
+    FORMAT_REQUESTS.fetch_add(1, Ordering::Relaxed);
+
+    if payload.prefecture.is_none() && payload.city.is_none() && payload.town.is_none() {
+        let error = "At least one of prefecture, city or town is required".to_string();
+        warn!(event = "format_failed", error = error);
+        return Json(FormatResponse {
+            success: false,
+            address: None,
+            error: Some(error),
+        });
+    }
+
+    let components = address_format::Components {
+        prefecture: payload.prefecture.as_deref(),
+        city: payload.city.as_deref(),
+        town: payload.town.as_deref(),
+        rest: payload.rest.as_deref(),
+        rest_components: payload.rest_components.as_ref(),
+        postal_code: payload.postal_code.as_deref(),
+    };
+    let address = address_format::japanese(components, payload.style);
+    debug!(event = "format_completed", address_length = address.len());
+    Json(FormatResponse {
+        success: true,
+        address: Some(address),
+        error: None,
+    })
+}
+
+#[derive(Debug, Deserialize)]
+struct CompareRequest {
+    a: String,
+    b: String,
+    /// Parser options applied to both addresses.
+    #[serde(flatten)]
+    options: ParserOverrides,
+}
+
+#[derive(Debug, Serialize)]
+struct CompareResponse {
+    success: bool,
+    result: Option<CompareResult>,
+    error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct CompareResult {
+    #[serde(flatten)]
+    comparison: Comparison,
+    a: ParsedAddress,
+    b: ParsedAddress,
+}
+
+impl ParsedAddress {
+    fn components(&self) -> address_format::Components<'_> {
+        address_format::Components {
+            prefecture: self.prefecture.as_deref(),
+            city: self.city.as_deref(),
+            town: self.town.as_deref(),
+            rest: self.rest.as_deref(),
+            rest_components: self.rest_components.as_ref(),
+            postal_code: None,
+        }
+    }
+}
+
+async fn compare_addresses(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Json(payload): Json<CompareRequest>,
+) -> Json<CompareResponse> {
+    COMPARE_REQUESTS.fetch_add(1, Ordering::Relaxed);
+    TOTAL_REQUESTS.fetch_add(2, Ordering::Relaxed);
+
+    if let Err(error) = state.parsers.check(&payload.options) {
+        FAILED_PARSES.fetch_add(2, Ordering::Relaxed);
+        VALIDATION_ERRORS.fetch_add(1, Ordering::Relaxed);
+        warn!(event = "compare_failed", error = error);
+        return Json(CompareResponse {
+            success: false,
+            result: None,
+            error: Some(error),
+        });
+    }
+
+    let config = state
+        .parsers
+        .default_config()
+        .with_overrides(&payload.options);
+    let options = RequestOptions {
+        normalize: Some(true),
+        ..Default::default()
+    };
+    // Retries are held to the deadline of the two parses they follow.
+    let deadline = tokio::time::Instant::now() + state.request_timeout;
+    let parse = |address: String, kind: ParseKind| {
+        let state = state.clone();
+        let options = &options;
+        async move {
+            let response =
+                process_address_as(&state, address.trim(), None, config, options, "POST", kind)
+                    .await;
+            response
+                .result
+                .ok_or_else(|| response.error.unwrap_or_default())
+        }
+    };
+
+    let (a, b) = tokio::join!(
+        parse(payload.a.clone(), ParseKind::Requested),
+        parse(payload.b.clone(), ParseKind::Requested)
+    );
+    let (mut a, mut b) = match (a, b) {
+        (Ok(a), Ok(b)) => (a, b),
+        (Err(error), _) | (_, Err(error)) => {
+            warn!(event = "compare_failed", error = error);
+            return Json(CompareResponse {
+                success: false,
+                result: None,
+                error: Some(error),
+            });
+        }
+    };
+
+    // The parser needs a prefecture to resolve anything, so an address that
+    // omits it is retried with the prefecture of the other one.
+    let with_prefecture = |side: &ParsedAddress, input: &str, other: &ParsedAddress| match (
+        &side.prefecture,
+        &other.prefecture,
+    ) {
+        (None, Some(prefecture)) => Some(format!("{}{}", prefecture, input.trim())),
+        _ => None,
+    };
+    let retries = [
+        with_prefecture(&a, &payload.a, &b),
+        with_prefecture(&b, &payload.b, &a),
+    ];
+    for (side, retry) in [&mut a, &mut b].into_iter().zip(retries) {
+        let Some(address) = retry else {
+            continue;
+        };
+        if let Ok(retried) = parse(address, ParseKind::Internal(deadline)).await {
+            if retried.resolution_level != ResolutionLevel::Prefecture {
+                *side = retried;
+            }
+        }
+    }
+
+    let comparison = compare::compare(a.components(), b.components());
+    info!(
+        event = "compare_completed",
+        equivalent = comparison.equivalent,
+        score = comparison.score
+    );
+    Json(CompareResponse {
+        success: true,
+        result: Some(CompareResult { comparison, a, b }),
+        error: None,
+    })
+}
+
+#[derive(Debug, Deserialize)]
+struct ExtractRequest {
+    text: String,
+    /// Parser options applied to every address found.
+    #[serde(flatten)]
+    options: ParserOverrides,
+    #[serde(flatten)]
+    request_options: RequestOptions,
+}
+
+#[derive(Debug, Serialize)]
+struct ExtractResponse {
+    success: bool,
+    addresses: Vec<ExtractedAddress>,
+    error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    processing_time_ms: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+struct ExtractedAddress {
+    /// The address as it appears in the text.
+    text: String,
+    /// Offset of the first character, counted in Unicode characters.
+    start: usize,
+    /// Offset just past the last character.
+    end: usize,
+    result: ParsedAddress,
+}
+
+async fn extract_addresses(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Json(payload): Json<ExtractRequest>,
+) -> Json<ExtractResponse> {
+    let start_time = Instant::now();
+    EXTRACT_REQUESTS.fetch_add(1, Ordering::Relaxed);
+
+    let failure = |error: String| {
+        warn!(event = "extract_failed", error = error);
+        Json(ExtractResponse {
+            success: false,
+            addresses: Vec::new(),
+            error: Some(error),
+            processing_time_ms: None,
+        })
+    };
+
+    if let Err(error) = state.parsers.check(&payload.options) {
+        return failure(error);
+    }
+    if payload.text.trim().is_empty() {
+        return failure("Text cannot be empty".to_string());
+    }
+    let text_length = payload.text.chars().count();
+    if text_length > MAX_EXTRACT_TEXT_LENGTH {
+        return failure(format!(
+            "Text too long (max {} characters)",
+            MAX_EXTRACT_TEXT_LENGTH
+        ));
+    }
+
+    let Ok(anchors) = timeout(
+        state.request_timeout,
+        extract::Anchors::load(&state.master_data()),
+    )
+    .await
+    else {
+        return failure("Request timeout".to_string());
+    };
+    let mut spans = extract::find_spans(&payload.text, &anchors);
+    let spans_found = spans.len();
+    spans.truncate(state.max_batch_size);
+
+    let config = state
+        .parsers
+        .default_config()
+        .with_overrides(&payload.options);
+    let options = &payload.request_options;
+    let addresses: Vec<ExtractedAddress> = stream::iter(spans)
+        .map(|span| {
+            let state = &state;
+            async move {
+                // A span that starts at a city is parsed with each prefecture
+                // that has the city, keeping the deepest result.
+                let inputs = match span.prefectures.as_slice() {
+                    [] => vec![span.text.clone()],
+                    prefectures => prefectures
+                        .iter()
+                        .map(|prefecture| format!("{}{}", prefecture, span.text))
+                        .collect(),
+                };
+                let mut best: Option<ParsedAddress> = None;
+                for input in inputs {
+                    let response = process_address_as(
+                        state,
+                        &input,
+                        None,
+                        config,
+                        options,
+                        "POST",
+                        ParseKind::Internal(tokio::time::Instant::now() + state.request_timeout),
+                    )
+                    .await;
+                    let Some(result) = response.result else {
+                        continue;
+                    };
+                    if best
+                        .as_ref()
+                        .is_none_or(|best| result.resolution_level > best.resolution_level)
+                    {
+                        best = Some(result);
+                    }
+                }
+                best.filter(|result| result.resolution_level != ResolutionLevel::None)
+                    .map(|result| ExtractedAddress {
+                        text: span.text,
+                        start: span.start,
+                        end: span.end,
+                        result,
+                    })
+            }
+        })
+        .buffered(state.batch_concurrency)
+        .filter_map(|address| async move { address })
+        .collect()
+        .await;
+
+    let processing_time_ms = start_time.elapsed().as_millis() as u64;
+    info!(
+        event = "extract_completed",
+        text_length = text_length,
+        spans_found = spans_found,
+        addresses = addresses.len(),
+        processing_time_ms = processing_time_ms
+    );
+    Json(ExtractResponse {
+        success: true,
+        addresses,
+        error: None,
+        processing_time_ms: Some(processing_time_ms),
+    })
+}
+
+#[derive(Debug, Serialize)]
+struct JobResponse {
+    success: bool,
+    job: Option<jobs::JobInfo>,
+    error: Option<String>,
+}
+
+impl JobResponse {
+    fn found(job: &jobs::Job) -> Json<Self> {
+        Json(Self {
+            success: true,
+            job: Some(job.info()),
+            error: None,
+        })
+    }
+
+    fn failure(error: String) -> Json<Self> {
+        Json(Self {
+            success: false,
+            job: None,
+            error: Some(error),
+        })
+    }
+
+    fn not_found(id: &str) -> Json<Self> {
+        Self::failure(format!("Job {} not found", id))
+    }
+
+    fn unavailable() -> Json<Self> {
+        Self::failure("Background jobs require JOBS_DIR to be set".to_string())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct JobRequest {
+    /// Where to post a signed summary once the job finishes.
+    #[serde(default)]
+    callback_url: Option<String>,
+    #[serde(flatten)]
+    batch: BatchParseRequest,
+}
+
+/// Queues a batch to be parsed in the background. The body is the same as
+/// for `/parse/batch`, but may hold up to `max_job_size` addresses and a
+/// `callback_url`.
+async fn submit_job(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Json(payload): Json<serde_json::Value>,
+) -> (StatusCode, Json<JobResponse>) {
+    JOB_SUBMISSIONS.fetch_add(1, Ordering::Relaxed);
+    let Some(store) = state.jobs.clone() else {
+        VALIDATION_ERRORS.fetch_add(1, Ordering::Relaxed);
+        return (StatusCode::OK, JobResponse::unavailable());
+    };
+
+    let failure = |error: String| {
+        VALIDATION_ERRORS.fetch_add(1, Ordering::Relaxed);
+        warn!(event = "job_submission_failed", error = error);
+        (StatusCode::OK, JobResponse::failure(error))
+    };
+
+    // The request is stored as submitted and deserialized again when the
+    // job runs, so it only needs checking here.
+    let request = match serde_json::from_value::<JobRequest>(payload.clone()) {
+        Ok(request) => request,
+        Err(e) => return failure(format!("Invalid job request: {}", e)),
+    };
+    if let Err(error) = state.parsers.check(&request.batch.options) {
+        return failure(error);
+    }
+    let total = request.batch.addresses.len();
+    if total == 0 {
+        return failure("Job must contain at least one address".to_string());
+    }
+    if total > state.max_job_size {
+        return failure(format!(
+            "Job too large (max {} addresses)",
+            state.max_job_size
+        ));
+    }
+
+    if let Some(url) = &request.callback_url {
+        if state.webhook.secret.is_none() {
+            return failure("Callbacks require WEBHOOK_SECRET to be set".to_string());
+        }
+        if let Err(error) = state.webhook.validate_url(url).await {
+            return failure(error);
+        }
+    }
+
+    let job = match store.create(payload, total, request.callback_url) {
+        Ok(job) => job,
+        Err(e) => {
+            error!(event = "job_submission_failed", error = %e, "Failed to store job");
+            return (
+                StatusCode::OK,
+                JobResponse::failure(format!("Failed to store job: {}", e)),
+            );
+        }
+    };
+    JOB_ADDRESSES.fetch_add(total as u64, Ordering::Relaxed);
+    info!(
+        event = "job_submitted",
+        job_id = job.id(),
+        total = total,
+        "Queued batch job"
+    );
+
+    let response = JobResponse::found(&job);
+    tokio::spawn(run_job(state, store, job));
+    (StatusCode::ACCEPTED, response)
+}
+
+/// Runs a job, then sends its callback if it has one.
+async fn run_job(state: AppState, store: Arc<jobs::JobStore>, job: Arc<jobs::Job>) {
+    process_job(&state, &store, &job).await;
+    if job.info().callback_url.is_some() {
+        deliver_callback(state, store, job).await;
+    }
+}
+
+/// Parses the addresses of a job that have no result yet, appending each
+/// result in input order.
+async fn process_job(state: &AppState, store: &jobs::JobStore, job: &jobs::Job) {
+    let _permit = store.acquire().await;
+    if job.is_cancelled() {
+        return;
+    }
+    let start_time = Instant::now();
+    let info = job.info();
+
+    let fail = |error: String| {
+        warn!(event = "job_failed", job_id = info.id, error = error);
+        if let Err(e) = store.fail(job, error) {
+            error!(event = "job_save_failed", job_id = info.id, error = %e);
+        }
+    };
+
+    let request = store.request(job).and_then(|request| {
+        serde_json::from_value::<BatchParseRequest>(request).map_err(std::io::Error::from)
+    });
+    let request = match request {
+        Ok(request) => request,
+        Err(e) => return fail(format!("Failed to read job request: {}", e)),
+    };
+    if let Err(e) = store.set_status(job, jobs::JobStatus::Running) {
+        return fail(format!("Failed to save job: {}", e));
+    }
+    info!(
+        event = "job_started",
+        job_id = info.id,
+        done = info.done,
+        total = info.total,
+        "Running batch job"
+    );
+
+    TOTAL_REQUESTS.fetch_add((info.total - info.done) as u64, Ordering::Relaxed);
+    let config = state
+        .parsers
+        .default_config()
+        .with_overrides(&request.options);
+    let request_options = &request.request_options;
+    let addresses: Vec<(usize, BatchAddress)> = request
+        .addresses
+        .into_iter()
+        .enumerate()
+        .skip(info.done)
+        .collect();
+    let mut results = stream::iter(addresses)
+        .map(|(index, item)| async move {
+            let (id, address, postal_code) = item.into_parts();
+            let response = process_address(
+                state,
+                address.trim(),
+                postal_code.as_deref(),
+                config,
+                request_options,
+                "JOB",
+            )
+            .await;
+            BatchItemResult {
+                index,
+                id,
+                response,
+            }
+        })
+        .buffered(state.batch_concurrency);
+
+    while let Some(result) = results.next().await {
+        if job.is_cancelled() {
+            break;
+        }
+        let line = serde_json::to_string(&result).unwrap_or_default();
+        if let Err(e) = store
+            .append_result(job, line, !result.response.success)
+            .await
+        {
+            return fail(format!("Failed to save result: {}", e));
+        }
+    }
+
+    let info = job.info();
+    if job.is_cancelled() {
+        info!(
+            event = "job_cancelled",
+            job_id = info.id,
+            done = info.done,
+            total = info.total,
+            "Batch job cancelled"
+        );
+        return;
+    }
+    if let Err(e) = store.set_status(job, jobs::JobStatus::Completed) {
+        return fail(format!("Failed to save job: {}", e));
+    }
+    info!(
+        event = "job_completed",
+        job_id = info.id,
+        total = info.total,
+        failed = info.failed,
+        total_time_ms = start_time.elapsed().as_millis() as u64,
+        "Batch job completed"
+    );
+}
+
+/// Posts the signed summary of a finished job to its callback URL, retrying
+/// failed attempts with exponential backoff. Attempts already made before a
+/// restart count towards `max_attempts`.
+async fn deliver_callback(state: AppState, store: Arc<jobs::JobStore>, job: Arc<jobs::Job>) {
+    let info = job.info();
+    let Some(url) = info.callback_url.as_deref() else {
+        return;
+    };
+    if info.callback_status != Some(CallbackStatus::Pending) {
+        return;
+    }
+    let body = json!({
+        "event": "job.finished",
+        "job_id": info.id,
+        "status": info.status,
+        "total": info.total,
+        "done": info.done,
+        "failed": info.failed,
+        "error": info.error,
+        "created_at": info.created_at,
+        "finished_at": info.updated_at,
+        "results_path": format!("/jobs/{}/results", info.id),
+    })
+    .to_string();
+
+    let max_attempts = state.webhook.max_attempts;
+    for number in info.deliveries.len() as u32 + 1..=max_attempts {
+        let attempt = webhook::attempt(&state.webhook, url, body.as_bytes(), number).await;
+        WEBHOOK_ATTEMPTS.fetch_add(1, Ordering::Relaxed);
+        let status = if attempt.succeeded() {
+            info!(
+                event = "webhook_delivered",
+                job_id = info.id,
+                attempt = number,
+                status_code = attempt.status_code,
+                duration_ms = attempt.duration_ms,
+                "Delivered job callback"
+            );
+            CallbackStatus::Delivered
+        } else {
+            WEBHOOK_FAILED_ATTEMPTS.fetch_add(1, Ordering::Relaxed);
+            warn!(
+                event = "webhook_attempt_failed",
+                job_id = info.id,
+                attempt = number,
+                max_attempts = max_attempts,
+                error = attempt.error.as_deref().unwrap_or_default(),
+            );
+            if number < max_attempts {
+                CallbackStatus::Pending
+            } else {
+                CallbackStatus::Failed
+            }
+        };
+        if let Err(e) = store.record_delivery(&job, attempt, status) {
+            error!(event = "job_save_failed", job_id = info.id, error = %e);
+        }
+        if status != CallbackStatus::Pending {
+            return;
+        }
+    }
+}
+
+async fn get_job(
+    Path(id): Path<String>,
+    axum::extract::State(state): axum::extract::State<AppState>,
+) -> Json<JobResponse> {
+    let Some(store) = &state.jobs else {
+        return JobResponse::unavailable();
+    };
+    match store.get(&id) {
+        Some(job) => JobResponse::found(&job),
+        None => JobResponse::not_found(&id),
+    }
+}
+
+/// Downloads the results recorded so far as NDJSON, one `/parse/batch`
+/// result per line. `x-job-status` tells whether more are still to come.
+async fn get_job_results(
+    Path(id): Path<String>,
+    axum::extract::State(state): axum::extract::State<AppState>,
+) -> Response {
+    let Some(store) = &state.jobs else {
+        return JobResponse::unavailable().into_response();
+    };
+    let Some(job) = store.get(&id) else {
+        return JobResponse::not_found(&id).into_response();
+    };
+    let status = job.info().status;
+    match store.results(&job).await {
+        Ok(results) => (
+            [
+                (header::CONTENT_TYPE, "application/x-ndjson"),
+                (
+                    header::HeaderName::from_static("x-job-status"),
+                    status.as_str(),
+                ),
+            ],
+            Body::from_stream(results),
+        )
+            .into_response(),
+        Err(e) => {
+            error!(event = "job_results_failed", job_id = id, error = %e);
+            JobResponse::failure(format!("Failed to read job results: {}", e)).into_response()
+        }
+    }
+}
+
+/// Cancels a job that is still queued or running, keeping the results it
+/// has so far. Deleting a finished job removes it and its results.
+async fn delete_job(
+    Path(id): Path<String>,
+    axum::extract::State(state): axum::extract::State<AppState>,
+) -> Json<JobResponse> {
+    let Some(store) = &state.jobs else {
+        return JobResponse::unavailable();
+    };
+    let Some(job) = store.get(&id) else {
+        return JobResponse::not_found(&id);
+    };
+    let outcome = store.cancel(&job).and_then(|cancelled| {
+        if !cancelled {
+            store.remove(&job)?;
+        }
+        Ok(cancelled)
+    });
+    match outcome {
+        Ok(cancelled) => {
+            info!(
+                event = if cancelled {
+                    "job_cancel_requested"
+                } else {
+                    "job_deleted"
+                },
+                job_id = id
+            );
+            JobResponse::found(&job)
+        }
+        Err(e) => {
+            error!(event = "job_delete_failed", job_id = id, error = %e);
+            JobResponse::failure(format!("Failed to delete job: {}", e))
+        }
+    }
+}
+
+async fn health(
+    axum::extract::State(state): axum::extract::State<AppState>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let ready = state.ready.load(Ordering::Relaxed);
+    let (status_code, status) = if ready {
+        (StatusCode::OK, "healthy")
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, "warming_up")
+    };
+    info!(event = "health_check", status = status);
+
+    let uptime_seconds = START_TIME
+        .get()
+        .and_then(|start| SystemTime::now().duration_since(*start).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    let dataset_mode = state
+        .dataset
+        .as_ref()
+        .map(|dataset| dataset.source().as_str())
+        .unwrap_or("remote");
+
+    (
+        status_code,
+        Json(json!({
+            "status": status,
+            "service": "japanese-address-parser-api",
+            "version": env!("CARGO_PKG_VERSION"),
+            "dataset": dataset_mode,
+            "timestamp": chrono::Utc::now().to_rfc3339(),
+            "uptime_seconds": uptime_seconds
+        })),
+    )
+}
+
+async fn metrics(
+    axum::extract::State(state): axum::extract::State<AppState>,
+) -> (StatusCode, String) {
+    let total = TOTAL_REQUESTS.load(Ordering::Relaxed);
+    let successful = SUCCESSFUL_PARSES.load(Ordering::Relaxed);
+    let failed = FAILED_PARSES.load(Ordering::Relaxed);
+    let get_requests = GET_REQUESTS.load(Ordering::Relaxed);
+    let post_requests = POST_REQUESTS.load(Ordering::Relaxed);
+    let timeout_errors = TIMEOUT_ERRORS.load(Ordering::Relaxed);
+    let validation_errors = VALIDATION_ERRORS.load(Ordering::Relaxed);
+    let batch_requests = BATCH_REQUESTS.load(Ordering::Relaxed);
+    let batch_addresses = BATCH_ADDRESSES.load(Ordering::Relaxed);
+    let coalesced_parses = COALESCED_PARSES.load(Ordering::Relaxed);
+    let postal_code_lookups = POSTAL_CODE_LOOKUPS.load(Ordering::Relaxed);
+    let reverse_geocode_requests = REVERSE_GEOCODE_REQUESTS.load(Ordering::Relaxed);
+    let suggest_requests = SUGGEST_REQUESTS.load(Ordering::Relaxed);
+    let format_requests = FORMAT_REQUESTS.load(Ordering::Relaxed);
+    let compare_requests = COMPARE_REQUESTS.load(Ordering::Relaxed);
+    let fuzzy_corrections = FUZZY_CORRECTIONS.load(Ordering::Relaxed);
+    let extract_requests = EXTRACT_REQUESTS.load(Ordering::Relaxed);
+    let stream_requests = STREAM_REQUESTS.load(Ordering::Relaxed);
+    let stream_addresses = STREAM_ADDRESSES.load(Ordering::Relaxed);
+    let csv_requests = CSV_REQUESTS.load(Ordering::Relaxed);
+    let csv_rows = CSV_ROWS.load(Ordering::Relaxed);
+    let job_submissions = JOB_SUBMISSIONS.load(Ordering::Relaxed);
+    let job_addresses = JOB_ADDRESSES.load(Ordering::Relaxed);
+    let webhook_attempts = WEBHOOK_ATTEMPTS.load(Ordering::Relaxed);
+    let webhook_failed_attempts = WEBHOOK_FAILED_ATTEMPTS.load(Ordering::Relaxed);
+    let cache_hits = master_data::CACHE_HITS.load(Ordering::Relaxed);
+    let cache_misses = master_data::CACHE_MISSES.load(Ordering::Relaxed);
+    let cache_evictions = master_data::CACHE_EVICTIONS.load(Ordering::Relaxed);
+    let cache_entries = master_data::cache().len();
+    let response_cache_hits = response_cache::HITS.load(Ordering::Relaxed);
+    let response_cache_misses = response_cache::MISSES.load(Ordering::Relaxed);
+
+    let parse_time_total = PARSE_TIME_TOTAL_MS.load(Ordering::Relaxed);
+    let min_parse_time = MIN_PARSE_TIME_MS.load(Ordering::Relaxed);
+    let max_parse_time = MAX_PARSE_TIME_MS.load(Ordering::Relaxed);
+
+    let avg_parse_time = if successful > 0 {
+        parse_time_total as f64 / successful as f64
+    } else {
+        0.0
+    };
+
+    let success_rate = if total > 0 {
+        (successful as f64 / total as f64) * 100.0
+    } else {
+        0.0
+    };
+
+    let uptime_seconds = START_TIME
+        .get()
+        .and_then(|start| SystemTime::now().duration_since(*start).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    let buckets = *PARSE_TIME_BUCKETS.lock().unwrap();
+
+    let response_cache_entries = state
+        .response_cache
+        .as_ref()
+        .map(|cache| cache.len())
+        .unwrap_or(0);
+
+    info!(
+        event = "metrics_requested",
+        total_requests = total,
+        successful_parses = successful,
+        failed_parses = failed,
+        success_rate = success_rate,
+        avg_parse_time_ms = avg_parse_time
+    );
+
+    let prometheus_metrics = format!(
+        "# HELP japanese_address_parser_requests_total Total number of address parsing requests\n\
+         # TYPE japanese_address_parser_requests_total counter\n\
+         japanese_address_parser_requests_total {}\n\
+         \n\
+         # HELP japanese_address_parser_requests_by_method_total Total requests by HTTP method\n\
+         # TYPE japanese_address_parser_requests_by_method_total counter\n\
+         japanese_address_parser_requests_by_method_total{{method=\"GET\"}} {}\n\
+         japanese_address_parser_requests_by_method_total{{method=\"POST\"}} {}\n\
+         \n\
+         # HELP japanese_address_parser_batch_requests_total Total number of batch parsing requests\n\
+         # TYPE japanese_address_parser_batch_requests_total counter\n\
+         japanese_address_parser_batch_requests_total {}\n\
+         \n\
+         # HELP japanese_address_parser_batch_addresses_total Total number of addresses received through batch requests\n\
+         # TYPE japanese_address_parser_batch_addresses_total counter\n\
+         japanese_address_parser_batch_addresses_total {}\n\
+         \n\
+         # HELP japanese_address_parser_coalesced_requests_total Parse requests that joined an identical in-flight parse\n\
+         # TYPE japanese_address_parser_coalesced_requests_total counter\n\
+         japanese_address_parser_coalesced_requests_total {}\n\
+         \n\
+         # HELP japanese_address_parser_postal_code_lookups_total Total number of postal code lookup requests\n\
+         # TYPE japanese_address_parser_postal_code_lookups_total counter\n\
+         japanese_address_parser_postal_code_lookups_total {}\n\
+         \n\
+         # HELP japanese_address_parser_reverse_geocode_requests_total Total number of reverse geocoding requests\n\
+         # TYPE japanese_address_parser_reverse_geocode_requests_total counter\n\
+         japanese_address_parser_reverse_geocode_requests_total {}\n\
+         \n\
+         # HELP japanese_address_parser_suggest_requests_total Total number of autocomplete requests\n\
+         # TYPE japanese_address_parser_suggest_requests_total counter\n\
+         japanese_address_parser_suggest_requests_total {}\n\
+         \n\
+         # HELP japanese_address_parser_format_requests_total Total number of address formatting requests\n\
+         # TYPE japanese_address_parser_format_requests_total counter\n\
+         japanese_address_parser_format_requests_total {}\n\
+         \n\
+         # HELP japanese_address_parser_compare_requests_total Total number of address comparison requests\n\
+         # TYPE japanese_address_parser_compare_requests_total counter\n\
+         japanese_address_parser_compare_requests_total {}\n\
+         \n\
+         # HELP japanese_address_parser_fuzzy_corrections_total Total number of parses resolved by correcting misspelled names\n\
+         # TYPE japanese_address_parser_fuzzy_corrections_total counter\n\
+         japanese_address_parser_fuzzy_corrections_total {}\n\
+         \n\
+         # HELP japanese_address_parser_extract_requests_total Total number of free-text address extraction requests\n\
+         # TYPE japanese_address_parser_extract_requests_total counter\n\
+         japanese_address_parser_extract_requests_total {}\n\
+         \n\
+         # HELP japanese_address_parser_stream_requests_total Total number of streamed NDJSON parsing requests\n\
+         # TYPE japanese_address_parser_stream_requests_total counter\n\
+         japanese_address_parser_stream_requests_total {}\n\
+         \n\
+         # HELP japanese_address_parser_stream_addresses_total Total number of lines received by streamed parsing requests\n\
+         # TYPE japanese_address_parser_stream_addresses_total counter\n\
+         japanese_address_parser_stream_addresses_total {}\n\
+         \n\
+         # HELP japanese_address_parser_csv_requests_total Total number of CSV parsing requests\n\
+         # TYPE japanese_address_parser_csv_requests_total counter\n\
+         japanese_address_parser_csv_requests_total {}\n\
+         \n\
+         # HELP japanese_address_parser_csv_rows_total Total number of rows received by CSV parsing requests\n\
+         # TYPE japanese_address_parser_csv_rows_total counter\n\
+         japanese_address_parser_csv_rows_total {}\n\
+         \n\
+         # HELP japanese_address_parser_job_submissions_total Total number of batch jobs submitted\n\
+         # TYPE japanese_address_parser_job_submissions_total counter\n\
+         japanese_address_parser_job_submissions_total {}\n\
+         \n\
+         # HELP japanese_address_parser_job_addresses_total Total number of addresses submitted in batch jobs\n\
+         # TYPE japanese_address_parser_job_addresses_total counter\n\
+         japanese_address_parser_job_addresses_total {}\n\
+         \n\
+         # HELP japanese_address_parser_webhook_attempts_total Total number of job callback delivery attempts\n\
+         # TYPE japanese_address_parser_webhook_attempts_total counter\n\
+         japanese_address_parser_webhook_attempts_total {}\n\
+         \n\
+         # HELP japanese_address_parser_webhook_failed_attempts_total Total number of job callback delivery attempts that failed\n\
+         # TYPE japanese_address_parser_webhook_failed_attempts_total counter\n\
+         japanese_address_parser_webhook_failed_attempts_total {}\n\
+         \n\
+         # HELP japanese_address_parser_requests_successful_total Total number of successful address parsing requests\n\
+         # TYPE japanese_address_parser_requests_successful_total counter\n\
+         japanese_address_parser_requests_successful_total {}\n\
+         \n\
+         # HELP japanese_address_parser_requests_failed_total Total number of failed address parsing requests\n\
+         # TYPE japanese_address_parser_requests_failed_total counter\n\
+         japanese_address_parser_requests_failed_total {}\n\
+         \n\
+         # HELP japanese_address_parser_timeout_errors_total Total number of timeout errors\n\
+         # TYPE japanese_address_parser_timeout_errors_total counter\n\
+         japanese_address_parser_timeout_errors_total {}\n\
+         \n\
+         # HELP japanese_address_parser_validation_errors_total Total number of validation errors\n\
+         # TYPE japanese_address_parser_validation_errors_total counter\n\
+         japanese_address_parser_validation_errors_total {}\n\
+         \n\
+         # HELP japanese_address_parser_success_rate_percent Success rate of address parsing requests as percentage\n\
+         # TYPE japanese_address_parser_success_rate_percent gauge\n\
+         japanese_address_parser_success_rate_percent {:.2}\n\
+         \n\
+         # HELP japanese_address_parser_parse_duration_seconds_total Total time spent parsing addresses in seconds\n\
+         # TYPE japanese_address_parser_parse_duration_seconds_total counter\n\
+         japanese_address_parser_parse_duration_seconds_total {:.3}\n\
+         \n\
+         # HELP japanese_address_parser_parse_duration_seconds Average parsing duration in seconds\n\
+         # TYPE japanese_address_parser_parse_duration_seconds gauge\n\
+         japanese_address_parser_parse_duration_seconds{{stat=\"avg\"}} {:.6}\n\
+         japanese_address_parser_parse_duration_seconds{{stat=\"min\"}} {:.6}\n\
+         japanese_address_parser_parse_duration_seconds{{stat=\"max\"}} {:.6}\n\
+         \n\
+         # HELP japanese_address_parser_parse_duration_histogram Parse duration distribution\n\
+         # TYPE japanese_address_parser_parse_duration_histogram histogram\n\
+         japanese_address_parser_parse_duration_histogram_bucket{{le=\"0.001\"}} {}\n\
+         japanese_address_parser_parse_duration_histogram_bucket{{le=\"0.005\"}} {}\n\
+         japanese_address_parser_parse_duration_histogram_bucket{{le=\"0.010\"}} {}\n\
+         japanese_address_parser_parse_duration_histogram_bucket{{le=\"0.025\"}} {}\n\
+         japanese_address_parser_parse_duration_histogram_bucket{{le=\"0.050\"}} {}\n\
+         japanese_address_parser_parse_duration_histogram_bucket{{le=\"0.100\"}} {}\n\
+         japanese_address_parser_parse_duration_histogram_bucket{{le=\"0.500\"}} {}\n\
+         japanese_address_parser_parse_duration_histogram_bucket{{le=\"+Inf\"}} {}\n\
+         \n\
+         # HELP japanese_address_parser_master_data_cache_hits_total Master data fetches served from the cache\n\
+         # TYPE japanese_address_parser_master_data_cache_hits_total counter\n\
+         japanese_address_parser_master_data_cache_hits_total {}\n\
+         \n\
+         # HELP japanese_address_parser_master_data_cache_misses_total Master data fetches that went to the remote source\n\
+         # TYPE japanese_address_parser_master_data_cache_misses_total counter\n\
+         japanese_address_parser_master_data_cache_misses_total {}\n\
+         \n\
+         # HELP japanese_address_parser_master_data_cache_evictions_total Master data cache entries evicted to stay within the size limit\n\
+         # TYPE japanese_address_parser_master_data_cache_evictions_total counter\n\
+         japanese_address_parser_master_data_cache_evictions_total {}\n\
+         \n\
+         # HELP japanese_address_parser_master_data_cache_entries Number of entries in the master data cache\n\
+         # TYPE japanese_address_parser_master_data_cache_entries gauge\n\
+         japanese_address_parser_master_data_cache_entries {}\n\
+         \n\
+         # HELP japanese_address_parser_response_cache_hits_total Parse requests served from the response cache\n\
+         # TYPE japanese_address_parser_response_cache_hits_total counter\n\
+         japanese_address_parser_response_cache_hits_total {}\n\
+         \n\
+         # HELP japanese_address_parser_response_cache_misses_total Parse requests not found in the response cache\n\
+         # TYPE japanese_address_parser_response_cache_misses_total counter\n\
+         japanese_address_parser_response_cache_misses_total {}\n\
+         \n\
+         # HELP japanese_address_parser_response_cache_entries Number of entries in the response cache\n\
+         # TYPE japanese_address_parser_response_cache_entries gauge\n\
+         japanese_address_parser_response_cache_entries {}\n\
+         \n\
+         # HELP japanese_address_parser_uptime_seconds Service uptime in seconds\n\
+         # TYPE japanese_address_parser_uptime_seconds gauge\n\
+         japanese_address_parser_uptime_seconds {}\n",
+        total,
+        get_requests,
+        post_requests,
+        batch_requests,
+        batch_addresses,
+        coalesced_parses,
+        postal_code_lookups,
+        reverse_geocode_requests,
+        suggest_requests,
+        format_requests,
+        compare_requests,
+        fuzzy_corrections,
+        extract_requests,
+        stream_requests,
+        stream_addresses,
+        csv_requests,
+        csv_rows,
+        job_submissions,
+        job_addresses,
+        webhook_attempts,
+        webhook_failed_attempts,
+        successful,
+        failed,
+        timeout_errors,
+        validation_errors,
+        success_rate,
+        parse_time_total as f64 / 1000.0, // Convert to seconds
+        avg_parse_time / 1000.0,          // Convert to seconds
+        if min_parse_time == u64::MAX { 0.0 } else { min_parse_time as f64 / 1000.0 },
+        max_parse_time as f64 / 1000.0,
+        buckets[0],
+        buckets[0] + buckets[1],
+        buckets[0] + buckets[1] + buckets[2],
+        buckets[0] + buckets[1] + buckets[2] + buckets[3],
+        buckets[0] + buckets[1] + buckets[2] + buckets[3] + buckets[4],
+        buckets[0] + buckets[1] + buckets[2] + buckets[3] + buckets[4] + buckets[5],
+        buckets[0] + buckets[1] + buckets[2] + buckets[3] + buckets[4] + buckets[5] + buckets[6],
+        total,
+        cache_hits,
+        cache_misses,
+        cache_evictions,
+        cache_entries,
+        response_cache_hits,
+        response_cache_misses,
+        response_cache_entries,
+        uptime_seconds
+    );
+
+    (StatusCode::OK, prometheus_metrics)
+}
+
+fn create_router(state: AppState) -> Router {
+    let max_request_size = std::env::var("MAX_REQUEST_SIZE")
+        .unwrap_or_else(|_| DEFAULT_MAX_REQUEST_SIZE.to_string())
+        .parse::<usize>()
+        .unwrap_or(DEFAULT_MAX_REQUEST_SIZE);
+
+    let max_job_request_size = std::env::var("MAX_JOB_REQUEST_SIZE")
+        .unwrap_or_else(|_| DEFAULT_MAX_JOB_REQUEST_SIZE.to_string())
+        .parse::<usize>()
+        .unwrap_or(DEFAULT_MAX_JOB_REQUEST_SIZE);
+
+    Router::new()
+        .route("/parse", get(parse_address).post(parse_address_post))
+        .route("/parse/batch", post(parse_address_batch))
+        .route("/parse/csv", post(parse_address_csv))
+        .route("/postal-code/:code", get(lookup_postal_code))
+        .route("/reverse", get(reverse_geocode))
+        .route("/suggest", get(suggest_addresses))
+        .route("/format", post(format_address))
+        .route("/compare", post(compare_addresses))
+        .route("/extract", post(extract_addresses))
+        .route("/jobs/:id", get(get_job).delete(delete_job))
+        .route("/jobs/:id/results", get(get_job_results))
+        .route("/health", get(health))
+        .route("/metrics", get(metrics))
+        // Streamed bodies are read line by line, so the size limit only
+        // applies to the routes above. Jobs take far larger batches than
+        // `/parse/batch` and have a limit of their own.
+        .layer(RequestBodyLimitLayer::new(max_request_size))
+        .route("/parse/stream", post(parse_address_stream))
+        .route(
+            "/jobs",
+            post(submit_job).layer(DefaultBodyLimit::max(max_job_request_size)),
+        )
+        .with_state(state)
+        .layer(
+            ServiceBuilder::new()
+                .layer(middleware::from_fn(request_logging_middleware))
+                .layer(TraceLayer::new_for_http())
+                .layer(
+                    CorsLayer::new()
+                        .allow_origin(Any)
+                        .allow_methods(Any)
+                        .allow_headers(Any),
+                ),
+        )
+}
+
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        signal::ctrl_c()
+            .await
+            .expect("failed to install Ctrl+C handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        signal::unix::signal(signal::unix::SignalKind::terminate())
+            .expect("failed to install signal handler")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {
+            info!("Received Ctrl+C signal, initiating graceful shutdown...");
+        },
+        _ = terminate => {
+            info!("Received SIGTERM signal, initiating graceful shutdown...");
+        },
+    }
+
+    info!("Shutdown signal received, cleaning up...");
+}
+
+fn init_tracing() {
+    let env_filter = EnvFilter::try_from_default_env()
+        .or_else(|_| EnvFilter::try_new("info"))
+        .unwrap();
+
+    tracing_subscriber::fmt()
+        .with_env_filter(env_filter)
+        .json()
+        .with_target(false)
+        .with_current_span(false)
+        .with_span_list(false)
+        .init();
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    init_tracing();
+
+    // Fail fast on a missing or incomplete offline dataset rather than
+    // serving partial results.
+    let dataset = dataset::init_from_env().map_err(|e| {
+        error!(event = "dataset_load_failed", error = %e, "Failed to load address dataset");
+        e
+    })?;
+    match &dataset {
+        Some(dataset) => info!(
+            event = "dataset_loaded",
+            source = dataset.source().as_str(),
+            prefectures = dataset.prefectures().len(),
+            towns = dataset.town_count(),
+            "Serving address master data from offline dataset"
+        ),
+        None => info!(
+            event = "dataset_remote",
+            "Fetching address master data from remote sources"
+        ),
+    }
+    if dataset.is_some() {
+        let data_source = ParserConfig::from_env().data_source;
+        data_source.check_offline().map_err(|_| {
+            let e = format!(
+                "PARSER_DATA_SOURCE={} cannot be used with an offline dataset (DATASET_MODE)",
+                data_source.as_str()
+            );
+            error!(event = "parser_config_invalid", error = %e, "Invalid default parser options");
+            e
+        })?;
+    }
+
+    let postal_codes = postal_code::init_from_env().map_err(|e| {
+        error!(event = "postal_code_dataset_load_failed", error = %e, "Failed to load postal code dataset");
+        e
+    })?;
+    if let Some(index) = &postal_codes {
+        info!(
+            event = "postal_code_dataset_loaded",
+            postal_codes = index.len(),
+            "Loaded postal code dataset"
+        );
+    }
+
+    let job_store = jobs::init_from_env().map_err(|e| {
+        error!(event = "job_store_load_failed", error = %e, "Failed to open job store");
+        e
+    })?;
+    if let Some(store) = &job_store {
+        info!(
+            event = "job_store_loaded",
+            unfinished_jobs = store.unfinished().len(),
+            "Persisting batch jobs to JOBS_DIR"
+        );
+    }
+
+    let state = AppState::new(
+        dataset.map(Arc::new),
+        postal_codes.map(Arc::new),
+        job_store.map(Arc::new),
+    );
+    resume_jobs(&state);
+    let app = create_router(state);
+
+    let port = std::env::var("PORT")
+        .unwrap_or_else(|_| "3000".to_string())
+        .parse::<u16>()
+        .map_err(|e| {
+            error!(event = "invalid_port", error = %e, "Failed to parse PORT environment variable");
+            e
+        })?;
+
+    let host = std::env::var("HOST").unwrap_or_else(|_| "0.0.0.0".to_string());
+    let addr = format!("{}:{}", host, port);
+
+    info!(
+        event = "server_starting",
+        host = %host,
+        port = port,
+        addr = %addr,
+        version = env!("CARGO_PKG_VERSION"),
+        "Starting Japanese Address Parser API"
+    );
+
+    let listener = TcpListener::bind(&addr).await.map_err(|e| {
+        error!(event = "bind_failed", addr = %addr, error = %e, "Failed to bind to address");
+        e
+    })?;
+
+    info!(
+        event = "server_started",
+        addr = %addr,
+        endpoints = ?["/parse", "/parse/batch", "/parse/csv", "/parse/stream", "/jobs", "/jobs/:id", "/jobs/:id/results", "/postal-code/:code", "/reverse", "/suggest", "/format", "/compare", "/extract", "/health", "/metrics"],
+        "Server running successfully"
+    );
+
+    axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown_signal())
+        .await
+        .map_err(|e| {
+            error!(event = "server_error", error = %e, "Server encountered an error");
+            e
+        })?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use tower::ServiceExt;
+
+    fn create_app() -> Router {
+        create_router(AppState::new(None, None, None))
+    }
+
+    #[tokio::test]
+    async fn test_health_endpoint() {
+        let app = create_app();
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/health")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_metrics_endpoint() {
+        let app = create_app();
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/metrics")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_parse_get_missing_address() {
+        let app = create_app();
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/parse")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_parse_get_valid_address() {
+        let app = create_app();
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/parse?address=東京都渋谷区神宮前1-1-1")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_parse_post_valid_address() {
+        let app = create_app();
+
+        let body = serde_json::json!({
+            "address": "東京都渋谷区神宮前1-1-1"
+        });
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/parse")
+                    .method("POST")
+                    .header("content-type", "application/json")
+                    .body(Body::from(body.to_string()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_parse_post_empty_address() {
+        let app = create_app();
+
+        let body = serde_json::json!({
+            "address": ""
+        });
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/parse")
+                    .method("POST")
+                    .header("content-type", "application/json")
+                    .body(Body::from(body.to_string()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    /// A response with its body read.
+    struct TestResponse {
+        status: StatusCode,
+        headers: HeaderMap,
+        body: Bytes,
+    }
+
+    impl TestResponse {
+        fn json(&self) -> serde_json::Value {
+            serde_json::from_slice(&self.body).unwrap()
+        }
+
+        fn text(&self) -> String {
+            String::from_utf8(self.body.to_vec()).unwrap()
+        }
+    }
+
+    async fn send(
+        app: &Router,
+        method: &str,
+        uri: &str,
+        content_type: Option<&str>,
+        body: Body,
+    ) -> TestResponse {
+        let mut request = Request::builder().method(method).uri(uri);
+        if let Some(content_type) = content_type {
+            request = request.header(header::CONTENT_TYPE, content_type);
+        }
+        let response = app
+            .clone()
+            .oneshot(request.body(body).unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        let headers = response.headers().clone();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        TestResponse {
+            status,
+            headers,
+            body,
+        }
+    }
+
+    async fn get_json(app: &Router, uri: &str) -> serde_json::Value {
+        let response = send(app, "GET", uri, None, Body::empty()).await;
+        assert_eq!(response.status, StatusCode::OK);
+        response.json()
+    }
+
+    async fn post_json(app: &Router, uri: &str, body: serde_json::Value) -> serde_json::Value {
+        let body = Body::from(body.to_string());
+        let response = send(app, "POST", uri, Some("application/json"), body).await;
+        assert_eq!(response.status, StatusCode::OK);
+        response.json()
+    }
+
+    #[tokio::test]
+    async fn test_parse_batch_per_item_validation_errors() {
+        let body = serde_json::json!({
+            "addresses": ["", {"id": "customer-7", "address": "   "}, {"id": 42, "address": ""}]
+        });
+
+        let json = post_json(&create_app(), "/parse/batch", body).await;
+
+        assert_eq!(json["success"], true);
+        assert_eq!(json["total"], 3);
+        assert_eq!(json["failed"], 3);
+        let results = json["results"].as_array().unwrap();
+        assert_eq!(results.len(), 3);
+        for (i, item) in results.iter().enumerate() {
+            assert_eq!(item["index"], i);
+            assert_eq!(item["success"], false);
+            assert_eq!(item["error"], "Address cannot be empty");
+        }
+        assert!(results[0].get("id").is_none());
+        assert_eq!(results[1]["id"], "customer-7");
+        assert_eq!(results[2]["id"], 42);
+    }
+
+    #[tokio::test]
+    async fn test_parse_batch_rejects_empty_and_oversized_batches() {
+        let mut state = AppState::new(None, None, None);
+        state.max_batch_size = 2;
+        let app = create_router(state);
+
+        let json = post_json(&app, "/parse/batch", serde_json::json!({ "addresses": [] })).await;
+        assert_eq!(json["success"], false);
+
+        let json = post_json(
+            &app,
+            "/parse/batch",
+            serde_json::json!({ "addresses": ["", "", ""] }),
+        )
+        .await;
+        assert_eq!(json["success"], false);
+        assert_eq!(json["error"], "Batch too large (max 2 addresses)");
+        assert_eq!(json["results"].as_array().unwrap().len(), 0);
+
+        let json = post_json(
+            &app,
+            "/parse/batch",
+            serde_json::json!({ "addresses": ["", ""] }),
+        )
+        .await;
+        assert_eq!(json["success"], true);
+        assert_eq!(json["total"], 2);
+    }
+
+    fn outcome(parts: [&str; 4], depth: u8, fetch_error: Option<&str>) -> ParseOutcome {
+        use japanese_address_parser::experimental::parser::{
+            Metadata, ParsedAddress as UpstreamParsedAddress,
+        };
+
+        ParseOutcome {
+            address: UpstreamParsedAddress {
+                prefecture: parts[0].to_string(),
+                city: parts[1].to_string(),
+                town: parts[2].to_string(),
+                rest: parts[3].to_string(),
+                metadata: Metadata {
+                    latitude: None,
+                    longitude: None,
+                    depth,
+                },
+            },
+            fetch_error: fetch_error.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_parsed_address_from_partial_result() {
+        let parsed = ParsedAddress::from(outcome(
+            ["青森県", "", "", "青盛市長島１丁目１−１"],
+            1,
+            None,
+        ));
+
+        assert_eq!(parsed.prefecture.as_deref(), Some("青森県"));
+        assert_eq!(parsed.city, None);
+        assert_eq!(parsed.town, None);
+        assert_eq!(parsed.resolution_level, ResolutionLevel::Prefecture);
+        let error = parsed.error.unwrap();
+        assert_eq!(error.error_type, "ParseError");
+        assert_eq!(error.error_message, "一致する市区町村がありませんでした");
+    }
+
+    #[test]
+    fn test_parsed_address_from_fetch_failure() {
+        let parsed = ParsedAddress::from(outcome(
+            ["東京都", "", "", "渋谷区神宮前1-1-1"],
+            1,
+            Some("Network error: https://example.com/東京都/master.json 404 Not Found"),
+        ));
+
+        assert_eq!(parsed.resolution_level, ResolutionLevel::Prefecture);
+        let error = parsed.error.unwrap();
+        assert_eq!(error.error_type, "ApiError");
+        assert_eq!(
+            error.error_message,
+            "Network error: https://example.com/東京都/master.json 404 Not Found"
+        );
+    }
+
+    #[test]
+    fn test_parsed_address_from_complete_result() {
+        let parsed = ParsedAddress::from(outcome(["東京都", "渋谷区", "神宮前", ""], 3, None));
+
+        assert_eq!(parsed.prefecture_code.as_deref(), Some("13"));
+        assert_eq!(parsed.town.as_deref(), Some("神宮前"));
+        assert_eq!(parsed.rest, None);
+        assert_eq!(parsed.rest_components, None);
+        assert_eq!(parsed.resolution_level, ResolutionLevel::Town);
+        assert!(parsed.error.is_none());
+    }
+
+    #[test]
+    fn test_parsed_address_splits_rest() {
+        let parsed = ParsedAddress::from(outcome(
+            ["東京都", "渋谷区", "神宮前一丁目", "1-1 神宮前ビル 305号室"],
+            3,
+            None,
+        ));
+
+        assert_eq!(parsed.rest.as_deref(), Some("1-1 神宮前ビル 305号室"));
+        let components = parsed.rest_components.unwrap();
+        assert_eq!(components.chome, None);
+        assert_eq!(components.banchi.as_deref(), Some("1"));
+        assert_eq!(components.go.as_deref(), Some("1"));
+        assert_eq!(components.building.as_deref(), Some("神宮前ビル"));
+        assert_eq!(components.room.as_deref(), Some("305"));
+    }
+
+    #[tokio::test]
+    async fn test_parse_splits_rest_after_normalization() {
+        let app = create_router(AppState::new(Some(dataset::test_dataset()), None, None));
+
+        // Normalization drops the spaces around the building name.
+        let body = json!({"address": "東京都渋谷区神宮前1丁目3番2号 三田マンション 201"});
+        let json = post_json(&app, "/parse", body).await;
+        assert_eq!(json["result"]["rest"], "3番2号三田マンション201");
+        let components = &json["result"]["rest_components"];
+        assert_eq!(components["banchi"], "3");
+        assert_eq!(components["go"], "2");
+        assert_eq!(components["building"], "三田マンション");
+        assert_eq!(components["room"], "201");
+
+        let body = json!({"address": "東京都渋谷区神宮前1-2-3 101"});
+        let json = post_json(&app, "/parse", body).await;
+        let components = &json["result"]["rest_components"];
+        assert_eq!(components["go"], "3");
+        assert_eq!(components["building"], serde_json::Value::Null);
+        assert_eq!(components["room"], "101");
+    }
+
+    #[tokio::test]
+    async fn test_parse_get_invalid_parser_option() {
+        let json = get_json(&create_app(), "/parse?address=Tokyo&data_source=unknown").await;
+        assert_eq!(json["success"], false);
+        assert!(json["error"].as_str().unwrap().contains("data_source"));
+    }
+
+    #[tokio::test]
+    async fn test_offline_dataset_rejects_other_data_sources() {
+        let app = create_router(AppState::new(Some(dataset::test_dataset()), None, None));
+        let error = "Invalid data_source 'chimei_ruiju' (only 'geolonia' is available with an offline dataset)";
+
+        let uri = "/parse?address=%E6%9D%B1%E4%BA%AC%E9%83%BD&data_source=chimei_ruiju";
+        let json = get_json(&app, uri).await;
+        assert_eq!(json["success"], false);
+        assert_eq!(json["error"], error);
+
+        let body = json!({"address": "東京都渋谷区神宮前1-1-1", "data_source": "chimei_ruiju"});
+        let json = post_json(&app, "/parse", body).await;
+        assert_eq!(json["error"], error);
+
+        let body = json!({"addresses": ["東京都"], "data_source": "chimei_ruiju"});
+        let json = post_json(&app, "/parse/batch", body).await;
+        assert_eq!(json["error"], error);
+
+        let body = json!({"address": "東京都渋谷区神宮前1-1-1", "data_source": "geolonia"});
+        let json = post_json(&app, "/parse", body).await;
+        assert_eq!(json["success"], true);
+    }
+
+    #[tokio::test]
+    async fn test_parse_post_invalid_parser_option() {
+        let body = serde_json::json!({
+            "address": "Tokyo",
+            "data_source": "unknown"
+        });
+        let json = post_json(&create_app(), "/parse", body).await;
+        assert_eq!(json["success"], false);
+        assert!(json["error"].as_str().unwrap().contains("data_source"));
+    }
+
+    #[tokio::test]
+    async fn test_parse_with_offline_dataset() {
+        let pool = ParserPool::new(
+            ParserConfig {
+                data_source: parser_pool::DataSource::Geolonia,
+                correct_incomplete_city_names: true,
+                verbose: false,
+            },
+            Some(dataset::test_dataset()),
+        );
+
+        let parsed = ParsedAddress::from(
+            pool.parse("東京都渋谷区神宮前1-1-1", pool.default_config())
+                .await,
+        );
+        assert_eq!(parsed.prefecture.as_deref(), Some("東京都"));
+        assert_eq!(parsed.city.as_deref(), Some("渋谷区"));
+        assert_eq!(parsed.town.as_deref(), Some("神宮前一丁目"));
+        assert_eq!(parsed.resolution_level, ResolutionLevel::Town);
+        assert!(parsed.error.is_none());
+
+        let parsed = ParsedAddress::from(
+            pool.parse("東京都港区芝公園4-2-8", pool.default_config())
+                .await,
+        );
+        assert_eq!(parsed.resolution_level, ResolutionLevel::Prefecture);
+        assert_eq!(parsed.error.unwrap().error_type, "ParseError");
+    }
+
+    #[tokio::test]
+    async fn test_health_reports_warming_up_until_warmup_completes() {
+        let state = AppState::new(Some(dataset::test_dataset()), None, None);
+        state.ready.store(false, Ordering::Relaxed);
+
+        let (status, Json(body)) = health(axum::extract::State(state.clone())).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["status"], "warming_up");
+
+        warm_up_master_data(state.clone()).await;
+
+        let (status, Json(body)) = health(axum::extract::State(state)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["status"], "healthy");
+    }
+
+    #[tokio::test]
+    async fn test_parse_response_cache_header() {
+        let mut state = AppState::new(Some(dataset::test_dataset()), None, None);
+        state.response_cache = Some(Arc::new(ResponseCache::new(
+            std::num::NonZeroUsize::new(16).unwrap(),
+            Duration::from_secs(60),
+        )));
+        let app = create_router(state);
+
+        let mut cache_headers = Vec::new();
+        for _ in 0..2 {
+            let uri = "/parse?address=東京都渋谷区神宮前1-1-1";
+            let response = send(&app, "GET", uri, None, Body::empty()).await;
+            assert_eq!(response.status, StatusCode::OK);
+            cache_headers.push(response.headers["x-cache"].to_str().unwrap().to_string());
+        }
+
+        assert_eq!(cache_headers, ["MISS", "HIT"]);
+    }
+
+    #[tokio::test]
+    async fn test_parse_normalizes_input_unless_disabled() {
+        let state = AppState::new(Some(dataset::test_dataset()), None, None);
+        let config = state.parsers.default_config();
+
+        let response = process_address(
+            &state,
+            "〒150-0001 東京都渋谷区神宮前一丁目１ー１",
+            None,
+            config,
+            &RequestOptions::default(),
+            "GET",
+        )
+        .await;
+        let result = response.result.unwrap();
+        assert_eq!(
+            result.normalized_address.as_deref(),
+            Some("東京都渋谷区神宮前1丁目1-1")
+        );
+        assert_eq!(result.town.as_deref(), Some("神宮前一丁目"));
+
+        let options = RequestOptions {
+            normalize: Some(false),
+            ..Default::default()
+        };
+        let response = process_address(&state, "〒150-0001", None, config, &options, "GET").await;
+        assert!(response.success);
+        assert!(response.result.unwrap().normalized_address.is_none());
+
+        let response = process_address(
+            &state,
+            "〒150-0001",
+            None,
+            config,
+            &RequestOptions::default(),
+            "GET",
+        )
+        .await;
+        assert_eq!(response.error.as_deref(), Some("Address cannot be empty"));
+    }
+
+    #[tokio::test]
+    async fn test_postal_code_lookup_and_cross_check() {
+        let app = create_router(AppState::new(
+            Some(dataset::test_dataset()),
+            Some(postal_code::test_index()),
+            None,
+        ));
 
-    #[cfg(not(unix))]
-    let terminate = std::future::pending::<()>();
+        let json = get_json(&app, "/postal-code/150-0001").await;
+        assert_eq!(json["success"], true);
+        assert_eq!(json["postal_code"], "1500001");
+        assert_eq!(json["results"][0]["town"], "神宮前");
 
-    tokio::select! {
-        _ = ctrl_c => {
-            info!("Received Ctrl+C signal, initiating graceful shutdown...");
-        },
-        _ = terminate => {
-            info!("Received SIGTERM signal, initiating graceful shutdown...");
-        },
+        let body = serde_json::json!({
+            "address": "東京都渋谷区神宮前1-1-1",
+            "postal_code": "100-0005"
+        });
+        let json = post_json(&app, "/parse", body).await;
+        assert_eq!(json["result"]["prefecture_code"], "13");
+        assert_eq!(json["result"]["city_code"], "131130");
+        assert_eq!(json["result"]["city_code_status"], "found");
+        let check = &json["result"]["postal_code_check"];
+        assert_eq!(check["status"], "mismatch");
+        assert_eq!(
+            check["mismatched_fields"],
+            serde_json::json!(["city", "town"])
+        );
+        assert_eq!(check["expected"][0]["city"], "千代田区");
     }
 
-    info!("Shutdown signal received, cleaning up...");
-}
+    #[tokio::test]
+    async fn test_parse_with_geocode() {
+        let state = AppState::new(Some(dataset::test_dataset()), None, None);
+        let config = state.parsers.default_config();
+        let options = RequestOptions {
+            geocode: true,
+            ..Default::default()
+        };
 
-fn init_tracing() {
-    let env_filter = EnvFilter::try_from_default_env()
-        .or_else(|_| EnvFilter::try_new("info"))
+        let parsed = process_address(
+            &state,
+            "東京都千代田区丸の内1-9-1",
+            None,
+            config,
+            &options,
+            "GET",
+        )
+        .await
+        .result
         .unwrap();
+        assert_eq!(parsed.lat, Some(35.681688));
+        assert_eq!(parsed.lng, Some(139.765396));
+        assert_eq!(parsed.geocode_precision, Some(GeocodePrecision::Town));
 
-    tracing_subscriber::fmt()
-        .with_env_filter(env_filter)
-        .json()
-        .with_target(false)
-        .with_current_span(false)
-        .with_span_list(false)
-        .init();
-}
+        let parsed = process_address(
+            &state,
+            "東京都千代田区丸の内1-9-1",
+            None,
+            config,
+            &RequestOptions::default(),
+            "GET",
+        )
+        .await
+        .result
+        .unwrap();
+        assert_eq!(parsed.lat, None);
+        assert_eq!(parsed.geocode_precision, None);
+    }
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    init_tracing();
+    #[tokio::test]
+    async fn test_geocode_prefers_point_reported_by_data_source() {
+        let mut outcome = outcome(["東京都", "渋谷区", "神宮前一丁目", "1-1"], 3, None);
+        outcome.address.metadata.latitude = Some(35.6702);
+        outcome.address.metadata.longitude = Some(139.7064);
+        let mut parsed = ParsedAddress::from(outcome);
 
-    let app = create_app();
+        parsed.geocode(&MasterDataClient::with_dataset(None)).await;
+        assert_eq!((parsed.lat, parsed.lng), (Some(35.6702), Some(139.7064)));
+        assert_eq!(parsed.geocode_precision, Some(GeocodePrecision::Town));
+        assert!(serde_json::to_value(&parsed)
+            .unwrap()
+            .get("source_point")
+            .is_none());
+    }
 
-    let port = std::env::var("PORT")
-        .unwrap_or_else(|_| "3000".to_string())
-        .parse::<u16>()
-        .map_err(|e| {
-            error!(event = "invalid_port", error = %e, "Failed to parse PORT environment variable");
-            e
-        })?;
+    #[tokio::test]
+    async fn test_parse_with_readings_and_english_format() {
+        let state = AppState::new(
+            Some(dataset::test_dataset()),
+            Some(postal_code::test_index()),
+            None,
+        );
+        let config = state.parsers.default_config();
+        let options = RequestOptions {
+            readings: true,
+            romaji_style: RomajiStyle::HepburnMacrons,
+            ..Default::default()
+        };
 
-    let host = std::env::var("HOST").unwrap_or_else(|_| "0.0.0.0".to_string());
-    let addr = format!("{}:{}", host, port);
+        let parsed = process_address(
+            &state,
+            "東京都渋谷区神宮前1-1-1",
+            None,
+            config,
+            &options,
+            "GET",
+        )
+        .await
+        .result
+        .unwrap();
+        let json = serde_json::to_value(&parsed).unwrap();
+        assert_eq!(json["prefecture_kana"], "トウキョウト");
+        assert_eq!(json["prefecture_romaji"], "Tōkyō-to");
+        assert_eq!(json["city_romaji"], "Shibuya-ku");
+        assert_eq!(json["town_romaji"], "Jingūmae 1-chōme");
+        assert!(json.get("formatted").is_none());
 
-    info!(
-        event = "server_starting",
-        host = %host,
-        port = port,
-        addr = %addr,
-        version = env!("CARGO_PKG_VERSION"),
-        "Starting Japanese Address Parser API"
-    );
+        let options = RequestOptions {
+            format: Some(AddressFormat::En),
+            ..Default::default()
+        };
+        let parsed = process_address(
+            &state,
+            "東京都渋谷区神宮前1-1-1 神宮前ビル 305号室",
+            None,
+            config,
+            &options,
+            "GET",
+        )
+        .await
+        .result
+        .unwrap();
+        let formatted = parsed.formatted.unwrap();
+        assert_eq!(
+            formatted.single_line,
+            "Room 305, 神宮前ビル, 1-1-1 Jingumae, Shibuya-ku, Tokyo 150-0001, JAPAN"
+        );
+        assert_eq!(parsed.readings, Readings::default());
 
-    let listener = TcpListener::bind(&addr).await.map_err(|e| {
-        error!(event = "bind_failed", addr = %addr, error = %e, "Failed to bind to address");
-        e
-    })?;
+        let options = RequestOptions::from_query(&HashMap::from([(
+            "romaji_style".to_string(),
+            "kunrei".to_string(),
+        )]));
+        assert_eq!(
+            options.unwrap_err(),
+            "Invalid romaji_style 'kunrei' (expected hepburn or hepburn_macrons)"
+        );
+    }
 
-    info!(
-        event = "server_started",
-        addr = %addr,
-        endpoints = ?["/parse", "/health", "/metrics"],
-        "Server running successfully"
-    );
+    #[tokio::test]
+    async fn test_reverse_geocode() {
+        let app = create_router(AppState::new(Some(dataset::test_dataset()), None, None));
 
-    axum::serve(listener, app)
-        .with_graceful_shutdown(shutdown_signal())
-        .await
-        .map_err(|e| {
-            error!(event = "server_error", error = %e, "Server encountered an error");
-            e
-        })?;
+        let json = get_json(&app, "/reverse?lat=35.681236&lng=139.767125").await;
+        assert_eq!(json["success"], true);
+        assert_eq!(json["result"]["prefecture_code"], "13");
+        assert_eq!(json["result"]["city"], "千代田区");
+        assert_eq!(json["result"]["town"], "丸の内一丁目");
+        // No postal code dataset is loaded.
+        assert_eq!(json["result"]["city_code"], serde_json::Value::Null);
+        assert_eq!(json["result"]["city_code_status"], "unavailable");
+        assert!(json["result"]["distance_m"].as_f64().unwrap() < 200.0);
 
-    Ok(())
-}
+        let json = get_json(&app, "/reverse?lat=135.0&lng=139.7").await;
+        assert_eq!(json["error"], "Invalid 'lat' parameter");
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use axum::body::Body;
-    use axum::http::{Request, StatusCode};
-    use tower::ServiceExt;
+        let json = get_json(&app, "/reverse?lat=34.702485&lng=135.495951").await;
+        assert_eq!(json["success"], false);
+    }
 
     #[tokio::test]
-    async fn test_health_endpoint() {
-        let app = create_app();
+    async fn test_suggest() {
+        let app = create_router(AppState::new(Some(dataset::test_dataset()), None, None));
 
-        let response = app
-            .oneshot(
-                Request::builder()
-                    .uri("/health")
-                    .body(Body::empty())
-                    .unwrap(),
-            )
-            .await
-            .unwrap();
+        // 東京都渋
+        let json = get_json(&app, "/suggest?q=%E6%9D%B1%E4%BA%AC%E9%83%BD%E6%B8%8B").await;
+        assert_eq!(json["success"], true);
+        assert_eq!(json["query"], "東京都渋");
+        assert_eq!(json["suggestions"][0]["kind"], "city");
+        assert_eq!(json["suggestions"][0]["label"], "東京都渋谷区");
+        assert_eq!(json["suggestions"][0]["city"], "渋谷区");
 
-        assert_eq!(response.status(), StatusCode::OK);
+        let json = get_json(&app, "/suggest?q=%E6%9D%B1&limit=0").await;
+        assert_eq!(json["error"], "Invalid 'limit' parameter (expected 1-50)");
+
+        let json = get_json(&app, "/suggest").await;
+        assert_eq!(json["error"], "Missing 'q' parameter");
     }
 
     #[tokio::test]
-    async fn test_metrics_endpoint() {
-        let app = create_app();
+    async fn test_parse_with_candidates() {
+        let state = AppState::new(Some(dataset::test_dataset()), None, None);
+        let config = state.parsers.default_config();
+        let options = RequestOptions {
+            candidates: Some(3),
+            ..Default::default()
+        };
 
-        let response = app
-            .oneshot(
-                Request::builder()
-                    .uri("/metrics")
-                    .body(Body::empty())
-                    .unwrap(),
-            )
+        // The city is omitted, so the parser alone stops at the prefecture.
+        let parsed = process_address(&state, "東京都丸の内1-9-1", None, config, &options, "GET")
             .await
+            .result
             .unwrap();
+        assert_eq!(parsed.resolution_level, ResolutionLevel::Prefecture);
+        let candidates = parsed.candidates.unwrap();
+        assert_eq!(candidates.len(), 2);
+        assert_eq!(candidates[0].address.city.as_deref(), Some("千代田区"));
+        assert_eq!(candidates[0].address.town.as_deref(), Some("丸の内一丁目"));
+        assert_eq!(candidates[0].confidence, 0.762);
+        assert_eq!(
+            candidates[0].reason,
+            "city 千代田区 inferred from town 丸の内; resolved to town level"
+        );
+        assert_eq!(candidates[1].confidence, 0.238);
+        assert_eq!(
+            candidates[1].address.resolution_level,
+            ResolutionLevel::Prefecture
+        );
 
-        assert_eq!(response.status(), StatusCode::OK);
+        let parsed = process_address(&state, "神宮前1-1-1", None, config, &options, "GET")
+            .await
+            .result
+            .unwrap();
+        let candidates = parsed.candidates.unwrap();
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(candidates[0].confidence, 1.0);
+        assert_eq!(candidates[0].address.town.as_deref(), Some("神宮前一丁目"));
     }
 
     #[tokio::test]
-    async fn test_parse_get_missing_address() {
-        let app = create_app();
+    async fn test_parse_with_fuzzy_matching() {
+        let state = AppState::new(Some(dataset::test_dataset()), None, None);
+        let config = state.parsers.default_config();
+        let options = RequestOptions {
+            fuzzy: true,
+            ..Default::default()
+        };
 
-        let response = app
-            .oneshot(
-                Request::builder()
-                    .uri("/parse")
-                    .body(Body::empty())
-                    .unwrap(),
+        let parsed = process_address(
+            &state,
+            "東京都渋谷匹神宮全1-1-1",
+            None,
+            config,
+            &options,
+            "GET",
+        )
+        .await
+        .result
+        .unwrap();
+        assert_eq!(parsed.resolution_level, ResolutionLevel::Town);
+        assert_eq!(parsed.town.as_deref(), Some("神宮前一丁目"));
+        let corrections = parsed.corrections.unwrap();
+        assert_eq!(corrections.len(), 2);
+        assert_eq!(corrections[0].field, "city");
+        assert_eq!(corrections[0].corrected, "渋谷区");
+        assert_eq!(corrections[1].field, "town");
+        assert_eq!(corrections[1].input, "神宮全");
+
+        // Off unless requested, and nothing passes a strict threshold.
+        for options in [
+            RequestOptions::default(),
+            RequestOptions {
+                fuzzy: true,
+                fuzzy_threshold: Some(0.9),
+                ..Default::default()
+            },
+        ] {
+            let parsed = process_address(
+                &state,
+                "東京都渋谷区神宮全1-1-1",
+                None,
+                config,
+                &options,
+                "GET",
             )
             .await
+            .result
             .unwrap();
+            assert_eq!(parsed.resolution_level, ResolutionLevel::City);
+            assert!(parsed.corrections.is_none());
+        }
 
-        assert_eq!(response.status(), StatusCode::OK);
+        let params = HashMap::from([("fuzzy_threshold".to_string(), "1.5".to_string())]);
+        assert!(RequestOptions::from_query(&params).is_err());
     }
 
     #[tokio::test]
-    async fn test_parse_get_valid_address() {
-        let app = create_app();
+    async fn test_parse_stream_endpoint() {
+        let app = create_router(AppState::new(Some(dataset::test_dataset()), None, None));
 
-        let response = app
-            .oneshot(
-                Request::builder()
-                    .uri("/parse?address=東京都渋谷区神宮前1-1-1")
-                    .body(Body::empty())
-                    .unwrap(),
-            )
-            .await
-            .unwrap();
+        let post = |uri: &'static str, body: String| {
+            let app = app.clone();
+            async move {
+                let response = send(
+                    &app,
+                    "POST",
+                    uri,
+                    Some("application/x-ndjson"),
+                    Body::from(body),
+                )
+                .await;
+                (
+                    response.headers[header::CONTENT_TYPE].clone(),
+                    response.text(),
+                )
+            }
+        };
 
-        assert_eq!(response.status(), StatusCode::OK);
+        let body = "\"東京都渋谷区神宮前1-1-1\"\n\
+                    {\"id\": 7, \"address\": \"東京都千代田区丸の内1-9-1\"}\n\
+                    \n\
+                    not json\n\
+                    \"\""
+            .to_string();
+        let (content_type, body) = post("/parse/stream?geocode=true", body).await;
+        assert_eq!(content_type, "application/x-ndjson");
+        let lines: Vec<serde_json::Value> = body
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0]["index"], 0);
+        assert_eq!(lines[0]["result"]["town"], "神宮前一丁目");
+        assert!(lines[0]["result"]["lat"].is_number());
+        assert_eq!(lines[1]["id"], 7);
+        assert_eq!(lines[1]["result"]["city"], "千代田区");
+        // Blank lines are skipped but still counted.
+        assert_eq!(lines[2]["index"], 3);
+        assert_eq!(lines[2]["success"], false);
+        assert!(lines[2]["error"]
+            .as_str()
+            .unwrap()
+            .starts_with("Invalid JSON line"));
+        assert_eq!(lines[3]["error"], "Address cannot be empty");
+
+        // Bodies beyond MAX_REQUEST_SIZE are accepted.
+        let body = format!(
+            "{}\"東京都渋谷区神宮前1-1-1\"\n",
+            "\n".repeat(DEFAULT_MAX_REQUEST_SIZE + 1)
+        );
+        let (_, body) = post("/parse/stream", body).await;
+        assert_eq!(body.lines().count(), 1);
+
+        let (content_type, body) = post("/parse/stream?data_source=unknown", String::new()).await;
+        assert_eq!(content_type, "application/json");
+        assert!(body.contains("\"success\":false"));
     }
 
     #[tokio::test]
-    async fn test_parse_post_valid_address() {
-        let app = create_app();
+    async fn test_parse_csv_endpoint() {
+        let app = create_router(AppState::new(Some(dataset::test_dataset()), None, None));
 
-        let body = serde_json::json!({
-            "address": "東京都渋谷区神宮前1-1-1"
-        });
+        let post = |uri: &'static str, body: Vec<u8>| {
+            let app = app.clone();
+            async move {
+                let response = send(&app, "POST", uri, Some("text/csv"), Body::from(body)).await;
+                (
+                    response.headers[header::CONTENT_TYPE].clone(),
+                    response.body.to_vec(),
+                )
+            }
+        };
 
-        let response = app
-            .oneshot(
-                Request::builder()
-                    .uri("/parse")
-                    .method("POST")
-                    .header("content-type", "application/json")
-                    .body(Body::from(body.to_string()))
-                    .unwrap(),
-            )
-            .await
-            .unwrap();
+        let csv = "顧客ID,送付先,備考\r\n\
+                   1,東京都渋谷区神宮前1-1-1,\"至急, 午前中\"\r\n\
+                   2,,\r\n\
+                   3,東京都千代田区丸の内1-9-1,\r\n";
+        let (encoded, _, _) = encoding_rs::SHIFT_JIS.encode(csv);
+        let (content_type, body) = post(
+            "/parse/csv?address_column=%E9%80%81%E4%BB%98%E5%85%88",
+            encoded.to_vec(),
+        )
+        .await;
+        assert_eq!(content_type, "text/csv; charset=Shift_JIS");
+        let (decoded, _, _) = encoding_rs::SHIFT_JIS.decode(&body);
+        assert_eq!(
+            decoded,
+            "顧客ID,送付先,備考,prefecture,city,town,rest,error\r\n\
+             1,東京都渋谷区神宮前1-1-1,\"至急, 午前中\",東京都,渋谷区,神宮前一丁目,1-1,\r\n\
+             2,,,,,,,Address cannot be empty\r\n\
+             3,東京都千代田区丸の内1-9-1,,東京都,千代田区,丸の内一丁目,9-1,\r\n"
+        );
 
-        assert_eq!(response.status(), StatusCode::OK);
+        // Without headers, the address column is given by position.
+        let (content_type, body) = post(
+            "/parse/csv?has_headers=false&address_column=1",
+            "x,東京都渋谷区神宮前1-1-1\n".as_bytes().to_vec(),
+        )
+        .await;
+        assert_eq!(content_type, "text/csv; charset=utf-8");
+        assert_eq!(
+            String::from_utf8(body).unwrap(),
+            "x,東京都渋谷区神宮前1-1-1,東京都,渋谷区,神宮前一丁目,1-1,\n"
+        );
+
+        let (content_type, body) = post(
+            "/parse/csv?address_column=missing",
+            b"address\nx\n".to_vec(),
+        )
+        .await;
+        assert_eq!(content_type, "application/json");
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["error"], "No column named 'missing' in the CSV header");
     }
 
     #[tokio::test]
-    async fn test_parse_post_empty_address() {
-        let app = create_app();
+    async fn test_extract_endpoint() {
+        let app = create_router(AppState::new(Some(dataset::test_dataset()), None, None));
 
-        let body = serde_json::json!({
-            "address": ""
-        });
+        let json = post_json(&app, "/extract", json!({
+            "text": "お届け先は東京都渋谷区神宮前1-1-1までお願いします。\n請求書は千代田区丸の内1-9-1へ。"
+        }))
+        .await;
+        assert_eq!(json["success"], true);
+        let addresses = json["addresses"].as_array().unwrap();
+        assert_eq!(addresses.len(), 2);
+        assert_eq!(addresses[0]["text"], "東京都渋谷区神宮前1-1-1");
+        assert_eq!(addresses[0]["start"], 5);
+        assert_eq!(addresses[0]["end"], 19);
+        assert_eq!(addresses[0]["result"]["town"], "神宮前一丁目");
+        // The prefecture is filled in from the city.
+        assert_eq!(addresses[1]["text"], "千代田区丸の内1-9-1");
+        assert_eq!(addresses[1]["result"]["prefecture"], "東京都");
+        assert_eq!(addresses[1]["result"]["town"], "丸の内一丁目");
 
-        let response = app
-            .oneshot(
-                Request::builder()
-                    .uri("/parse")
-                    .method("POST")
-                    .header("content-type", "application/json")
-                    .body(Body::from(body.to_string()))
-                    .unwrap(),
-            )
-            .await
-            .unwrap();
+        let json = post_json(&app, "/extract", json!({"text": "住所は書いていません"})).await;
+        assert_eq!(json["success"], true);
+        assert_eq!(json["addresses"], json!([]));
 
-        assert_eq!(response.status(), StatusCode::OK);
+        let json = post_json(&app, "/extract", json!({"text": "  "})).await;
+        assert_eq!(json["success"], false);
+        assert_eq!(json["error"], "Text cannot be empty");
+    }
+
+    #[tokio::test]
+    async fn test_compare_endpoint() {
+        let app = create_router(AppState::new(Some(dataset::test_dataset()), None, None));
+
+        let json = post_json(
+            &app,
+            "/compare",
+            serde_json::json!({
+                "a": "東京都渋谷区神宮前1丁目1番1号",
+                "b": "渋谷区神宮前１－１－１"
+            }),
+        )
+        .await;
+        assert_eq!(json["success"], true, "{}", json);
+        assert_eq!(json["result"]["equivalent"], true, "{}", json);
+        assert_eq!(json["result"]["score"], 1.0);
+        assert_eq!(json["result"]["b"]["prefecture"], "東京都");
+
+        let json = post_json(
+            &app,
+            "/compare",
+            serde_json::json!({
+                "a": "東京都渋谷区神宮前1-1-1",
+                "b": "東京都渋谷区神宮前2-1-1"
+            }),
+        )
+        .await;
+        assert_eq!(json["result"]["equivalent"], false);
+        assert_eq!(json["result"]["levels"]["city"], "match");
+        assert_eq!(json["result"]["levels"]["town"], "mismatch");
+
+        let json = post_json(
+            &app,
+            "/compare",
+            serde_json::json!({"a": "東京都渋谷区", "b": " "}),
+        )
+        .await;
+        assert_eq!(json["success"], false);
+        assert_eq!(json["error"], "Address cannot be empty");
+    }
+
+    #[tokio::test]
+    async fn test_format_endpoint() {
+        let app = create_app();
+
+        let json = post_json(&app, "/format", serde_json::json!({
+            "prefecture": "東京都",
+            "prefecture_code": "13",
+            "city": "渋谷区",
+            "town": "神宮前一丁目",
+            "rest": "1-1",
+            "rest_components": {"chome": null, "banchi": "1", "go": "1", "building": null, "floor": null, "room": null},
+            "resolution_level": "town",
+            "block_style": "label",
+            "chome_numerals": "arabic",
+            "include_prefecture": false
+        }))
+        .await;
+        assert_eq!(json["success"], true);
+        assert_eq!(json["address"], "渋谷区神宮前1丁目1番1号");
+
+        let json = post_json(&app, "/format", serde_json::json!({"rest": "1-1"})).await;
+        assert_eq!(json["success"], false);
     }
 
     #[test]
@@ -784,4 +4223,205 @@ mod tests {
         assert!(validate_address("   ").is_err());
         assert!(validate_address(&"a".repeat(501)).is_err());
     }
+
+    #[tokio::test]
+    async fn test_jobs_endpoints() {
+        let app = create_router(AppState::new(
+            Some(dataset::test_dataset()),
+            None,
+            Some(jobs::test_store()),
+        ));
+
+        let body = json!({
+            "addresses": [
+                "東京都渋谷区神宮前1-1-1",
+                "",
+                {"id": "a", "address": "東京都千代田区丸の内1-9-1"}
+            ]
+        });
+        let response = send(
+            &app,
+            "POST",
+            "/jobs",
+            Some("application/json"),
+            Body::from(body.to_string()),
+        )
+        .await;
+        assert_eq!(response.status, StatusCode::ACCEPTED);
+        let job = response.json()["job"].clone();
+        assert_eq!(job["total"], 3);
+        let id = job["id"].as_str().unwrap().to_string();
+
+        let mut job = job;
+        for _ in 0..100 {
+            if job["status"] == "completed" {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            job = get_json(&app, &format!("/jobs/{}", id)).await["job"].clone();
+        }
+        assert_eq!(job["status"], "completed");
+        assert_eq!(
+            (job["done"].clone(), job["failed"].clone()),
+            (json!(3), json!(1))
+        );
+
+        let uri = format!("/jobs/{}/results", id);
+        let response = send(&app, "GET", &uri, None, Body::empty()).await;
+        assert_eq!(response.status, StatusCode::OK);
+        let results: Vec<serde_json::Value> = response
+            .text()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0]["result"]["town"], "神宮前一丁目");
+        assert_eq!(results[1]["success"], false);
+        assert_eq!(results[2]["id"], "a");
+
+        // Deleting a finished job removes it.
+        let uri = format!("/jobs/{}", id);
+        let response = send(&app, "DELETE", &uri, None, Body::empty()).await;
+        assert_eq!(response.json()["success"], true);
+        let body = get_json(&app, &uri).await;
+        assert_eq!(body["success"], false);
+        assert_eq!(body["error"], format!("Job {} not found", id));
+
+        let body = post_json(&app, "/jobs", json!({"addresses": []})).await;
+        assert_eq!(body["error"], "Job must contain at least one address");
+
+        // Job bodies are not held to MAX_REQUEST_SIZE, so this one is only
+        // turned down for its number of addresses.
+        let addresses = vec!["東京都渋谷区神宮前1-1-1"; DEFAULT_MAX_JOB_SIZE + 1];
+        let response = send(
+            &app,
+            "POST",
+            "/jobs",
+            Some("application/json"),
+            Body::from(json!({ "addresses": addresses }).to_string()),
+        )
+        .await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(
+            response.json()["error"],
+            format!("Job too large (max {} addresses)", DEFAULT_MAX_JOB_SIZE)
+        );
+
+        // Without JOBS_DIR there is nowhere to keep jobs.
+        let app = create_app();
+        let body = post_json(&app, "/jobs", json!({"addresses": ["a"]})).await;
+        assert_eq!(body["error"], "Background jobs require JOBS_DIR to be set");
+        let body = get_json(&app, &uri).await;
+        assert_eq!(body["error"], "Background jobs require JOBS_DIR to be set");
+    }
+
+    #[tokio::test]
+    async fn test_job_callback_is_signed_and_retried() {
+        // A stand-in receiver that fails the first delivery.
+        let received: Arc<Mutex<Vec<(HeaderMap, Bytes)>>> = Arc::default();
+        let receiver = Router::new().route(
+            "/hook",
+            post({
+                let received = received.clone();
+                move |headers: HeaderMap, body: Bytes| async move {
+                    let mut received = received.lock().unwrap();
+                    received.push((headers, body));
+                    if received.len() == 1 {
+                        StatusCode::INTERNAL_SERVER_ERROR
+                    } else {
+                        StatusCode::OK
+                    }
+                }
+            }),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let callback_url = format!("http://{}/hook", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, receiver).await });
+
+        let submission = json!({
+            "addresses": ["東京都渋谷区神宮前1-1-1"],
+            "callback_url": callback_url
+        });
+
+        let mut state = AppState::new(
+            Some(dataset::test_dataset()),
+            None,
+            Some(jobs::test_store()),
+        );
+        state.webhook = Arc::new(WebhookConfig {
+            secret: None,
+            max_attempts: 3,
+            retry_base: Duration::from_millis(10),
+            timeout: Duration::from_secs(5),
+            // The stand-in receiver listens on loopback.
+            allowed_hosts: vec!["127.0.0.1".to_string()],
+        });
+        let body = post_json(&create_router(state.clone()), "/jobs", submission.clone()).await;
+        assert_eq!(body["error"], "Callbacks require WEBHOOK_SECRET to be set");
+
+        state.webhook = Arc::new(WebhookConfig {
+            secret: Some("secret".to_string()),
+            allowed_hosts: Vec::new(),
+            ..(*state.webhook).clone()
+        });
+        let body = post_json(&create_router(state.clone()), "/jobs", submission.clone()).await;
+        assert!(body["error"]
+            .as_str()
+            .unwrap()
+            .ends_with("which is not a public address"));
+
+        state.webhook = Arc::new(WebhookConfig {
+            allowed_hosts: vec!["127.0.0.1".to_string()],
+            ..(*state.webhook).clone()
+        });
+        let app = create_router(state);
+        let response = send(
+            &app,
+            "POST",
+            "/jobs",
+            Some("application/json"),
+            Body::from(submission.to_string()),
+        )
+        .await;
+        assert_eq!(response.status, StatusCode::ACCEPTED);
+        let body = response.json();
+        assert_eq!(body["job"]["callback_status"], "pending");
+        let id = body["job"]["id"].as_str().unwrap().to_string();
+
+        let mut job = serde_json::Value::Null;
+        for _ in 0..200 {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            job = get_json(&app, &format!("/jobs/{}", id)).await["job"].clone();
+            if job["callback_status"] != "pending" {
+                break;
+            }
+        }
+        assert_eq!(job["callback_status"], "delivered");
+        let deliveries = job["deliveries"].as_array().unwrap();
+        assert_eq!(deliveries.len(), 2);
+        assert_eq!(deliveries[0]["status_code"], 500);
+        assert!(deliveries[0]["error"].is_string());
+        assert_eq!(deliveries[1]["status_code"], 200);
+        assert!(deliveries[1]["error"].is_null());
+
+        let received = received.lock().unwrap();
+        let (headers, body) = &received[1];
+        let timestamp: i64 = headers[webhook::TIMESTAMP_HEADER]
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert_eq!(
+            headers[webhook::SIGNATURE_HEADER],
+            webhook::sign("secret", timestamp, body)
+        );
+        let summary: serde_json::Value = serde_json::from_slice(body).unwrap();
+        assert_eq!(summary["job_id"], id);
+        assert_eq!(summary["status"], "completed");
+        assert_eq!(
+            (summary["total"].clone(), summary["failed"].clone()),
+            (json!(1), json!(0))
+        );
+        assert_eq!(summary["results_path"], format!("/jobs/{}/results", id));
+    }
 }
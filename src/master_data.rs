@@ -0,0 +1,259 @@
+//! Address master data access for the parsers.
+//!
+//! Every master data fetch goes through [`MasterDataClient`], which answers
+//! from the offline dataset when it is given one and otherwise from an
+//! in-process cache in front of the remote sources. The upstream parser
+//! constructs its own clients, so those take the dataset from
+//! [`with_dataset`] around the parse. The upstream parser also only
+//! tells us how deep it got, not why it stopped, so the client also records
+//! fetch failures for the duration of a single parse and reports them as
+//! `ApiError`s carrying the upstream [`ApiClientError`] message.
+
+use crate::dataset::Dataset;
+use japanese_address_parser::http::client::ApiClient;
+use japanese_address_parser::http::error::ApiClientError;
+use japanese_address_parser::http::reqwest_client::ReqwestApiClient;
+use serde::de::DeserializeOwned;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+const DEFAULT_CACHE_TTL_SECS: u64 = 24 * 60 * 60;
+const DEFAULT_CACHE_MAX_ENTRIES: usize = 4096;
+
+// Cache metrics
+pub static CACHE_HITS: AtomicU64 = AtomicU64::new(0);
+pub static CACHE_MISSES: AtomicU64 = AtomicU64::new(0);
+pub static CACHE_EVICTIONS: AtomicU64 = AtomicU64::new(0);
+
+static CACHE: OnceLock<MasterDataCache> = OnceLock::new();
+
+tokio::task_local! {
+    static FETCH_ERROR: RefCell<Option<String>>;
+    static DATASET: Option<Arc<Dataset>>;
+}
+
+/// `ApiClient` handed to the upstream parser.
+pub struct MasterDataClient {
+    inner: ReqwestApiClient,
+    dataset: Option<Arc<Dataset>>,
+}
+
+impl MasterDataClient {
+    /// A client that answers from `dataset`, or from the remote sources when
+    /// there is none.
+    pub fn with_dataset(dataset: Option<Arc<Dataset>>) -> Self {
+        Self {
+            inner: ReqwestApiClient::new(),
+            dataset,
+        }
+    }
+
+    pub fn dataset(&self) -> Option<&Dataset> {
+        self.dataset.as_deref()
+    }
+}
+
+impl ApiClient for MasterDataClient {
+    /// Called by the upstream parser, which takes the dataset of the
+    /// enclosing [`with_dataset`] scope.
+    fn new() -> Self {
+        Self::with_dataset(DATASET.try_with(Clone::clone).ok().flatten())
+    }
+
+    async fn fetch<T: DeserializeOwned>(&self, url: &str) -> Result<T, ApiClientError> {
+        let result = match &self.dataset {
+            Some(dataset) => dataset.lookup(url).and_then(|value| {
+                serde_json::from_value(value).map_err(|e| ApiClientError::Deserialize {
+                    url: url.to_string(),
+                    message: e.to_string(),
+                })
+            }),
+            None => fetch_cached(&self.inner, url).await,
+        };
+        if let Err(error) = &result {
+            record_fetch_error(error);
+        }
+        result
+    }
+}
+
+async fn fetch_cached<T: DeserializeOwned>(
+    inner: &ReqwestApiClient,
+    url: &str,
+) -> Result<T, ApiClientError> {
+    let cache = cache();
+    let value = match cache.get(url) {
+        Some(value) => {
+            CACHE_HITS.fetch_add(1, Ordering::Relaxed);
+            value
+        }
+        None => {
+            CACHE_MISSES.fetch_add(1, Ordering::Relaxed);
+            let value = Arc::new(inner.fetch::<serde_json::Value>(url).await?);
+            cache.insert(url, value.clone());
+            value
+        }
+    };
+    T::deserialize(value.as_ref()).map_err(|e| ApiClientError::Deserialize {
+        url: url.to_string(),
+        message: e.to_string(),
+    })
+}
+
+struct CacheEntry {
+    value: Arc<serde_json::Value>,
+    fetched_at: Instant,
+    /// Insertion order, used to pick the eviction victim.
+    sequence: u64,
+}
+
+/// Remote master data keyed by URL, expiring after a TTL.
+///
+/// When full, the entry fetched longest ago is evicted first.
+pub struct MasterDataCache {
+    ttl: Duration,
+    max_entries: usize,
+    entries: Mutex<HashMap<String, CacheEntry>>,
+    next_sequence: AtomicU64,
+}
+
+impl MasterDataCache {
+    fn new(ttl: Duration, max_entries: usize) -> Self {
+        Self {
+            ttl,
+            max_entries,
+            entries: Mutex::new(HashMap::new()),
+            next_sequence: AtomicU64::new(0),
+        }
+    }
+
+    fn from_env() -> Self {
+        let ttl_secs = std::env::var("MASTER_DATA_CACHE_TTL_SECS")
+            .unwrap_or_else(|_| DEFAULT_CACHE_TTL_SECS.to_string())
+            .parse::<u64>()
+            .unwrap_or(DEFAULT_CACHE_TTL_SECS);
+
+        let max_entries = std::env::var("MASTER_DATA_CACHE_MAX_ENTRIES")
+            .unwrap_or_else(|_| DEFAULT_CACHE_MAX_ENTRIES.to_string())
+            .parse::<usize>()
+            .unwrap_or(DEFAULT_CACHE_MAX_ENTRIES);
+
+        Self::new(Duration::from_secs(ttl_secs), max_entries)
+    }
+
+    fn get(&self, url: &str) -> Option<Arc<serde_json::Value>> {
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get(url) {
+            Some(entry) if entry.fetched_at.elapsed() < self.ttl => Some(entry.value.clone()),
+            Some(_) => {
+                entries.remove(url);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn insert(&self, url: &str, value: Arc<serde_json::Value>) {
+        if self.max_entries == 0 {
+            return;
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+        if !entries.contains_key(url) && entries.len() >= self.max_entries {
+            let oldest = entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.sequence)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                entries.remove(&oldest);
+                CACHE_EVICTIONS.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        entries.insert(
+            url.to_string(),
+            CacheEntry {
+                value,
+                fetched_at: Instant::now(),
+                sequence: self.next_sequence.fetch_add(1, Ordering::Relaxed),
+            },
+        );
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+}
+
+/// Returns the process-wide master data cache.
+pub fn cache() -> &'static MasterDataCache {
+    CACHE.get_or_init(MasterDataCache::from_env)
+}
+
+fn record_fetch_error(error: &ApiClientError) {
+    let message = error.to_string();
+    // Fetches outside of `capture_fetch_errors` have nobody to report to.
+    let _ = FETCH_ERROR.try_with(|slot| {
+        slot.borrow_mut().get_or_insert(message);
+    });
+}
+
+/// Runs `future` with `dataset` serving the clients the upstream parser
+/// constructs within it.
+pub async fn with_dataset<F: Future>(dataset: Option<Arc<Dataset>>, future: F) -> F::Output {
+    DATASET.scope(dataset, future).await
+}
+
+/// Runs `future` and returns its output together with the first master data
+/// fetch error that occurred while it was running.
+pub async fn capture_fetch_errors<F: Future>(future: F) -> (F::Output, Option<String>) {
+    FETCH_ERROR
+        .scope(RefCell::new(None), async {
+            let output = future.await;
+            let error = FETCH_ERROR.with(|slot| slot.borrow_mut().take());
+            (output, error)
+        })
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(n: u64) -> Arc<serde_json::Value> {
+        Arc::new(serde_json::json!({ "n": n }))
+    }
+
+    #[test]
+    fn test_cache_hit_and_expiry() {
+        let cache = MasterDataCache::new(Duration::from_secs(60), 8);
+        cache.insert("a", value(1));
+        assert_eq!(cache.get("a").unwrap()["n"], 1);
+        assert!(cache.get("b").is_none());
+
+        let expired = MasterDataCache::new(Duration::ZERO, 8);
+        expired.insert("a", value(1));
+        assert!(expired.get("a").is_none());
+        assert_eq!(expired.len(), 0);
+    }
+
+    #[test]
+    fn test_cache_evicts_oldest_entry_when_full() {
+        let cache = MasterDataCache::new(Duration::from_secs(60), 2);
+        cache.insert("a", value(1));
+        cache.insert("b", value(2));
+        cache.insert("c", value(3));
+
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get("a").is_none());
+        assert!(cache.get("b").is_some());
+        assert!(cache.get("c").is_some());
+
+        let disabled = MasterDataCache::new(Duration::from_secs(60), 0);
+        disabled.insert("a", value(1));
+        assert_eq!(disabled.len(), 0);
+    }
+}
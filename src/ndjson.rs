@@ -0,0 +1,158 @@
+//! Splitting streamed request bodies into NDJSON lines.
+//!
+//! Only the current partial line is buffered, so memory stays bounded no
+//! matter how large the body is. A line longer than the limit is reported
+//! once and the rest of it is skipped instead of buffered.
+
+use axum::body::Bytes;
+use futures::stream::{self, Stream, StreamExt};
+
+struct Lines<S> {
+    body: S,
+    buffer: Vec<u8>,
+    /// Bytes at the start of `buffer` already returned as lines. They are
+    /// dropped before the next chunk is appended rather than line by line,
+    /// which would copy the rest of a large chunk once per line.
+    consumed: usize,
+    max_length: usize,
+    /// Inside an oversized line that has already been reported.
+    skipping: bool,
+    done: bool,
+}
+
+impl<S> Lines<S> {
+    /// Takes the next complete line out of the buffer.
+    fn next_line(&mut self) -> Option<Result<String, String>> {
+        loop {
+            let pending = &self.buffer[self.consumed..];
+            let Some(position) = pending.iter().position(|byte| *byte == b'\n') else {
+                if self.skipping {
+                    self.clear();
+                } else if pending.len() > self.max_length {
+                    self.clear();
+                    self.skipping = true;
+                    return Some(Err(self.too_long()));
+                }
+                return None;
+            };
+            let start = self.consumed;
+            self.consumed += position + 1;
+            if std::mem::take(&mut self.skipping) {
+                continue;
+            }
+            return Some(self.decode(&self.buffer[start..start + position]));
+        }
+    }
+
+    /// The final line, when the body does not end with a newline.
+    fn last_line(&mut self) -> Option<Result<String, String>> {
+        let line = &self.buffer[self.consumed..];
+        let line = (!line.is_empty() && !self.skipping).then(|| self.decode(line));
+        self.clear();
+        line
+    }
+
+    fn append(&mut self, chunk: &[u8]) {
+        self.buffer.drain(..self.consumed);
+        self.consumed = 0;
+        self.buffer.extend_from_slice(chunk);
+    }
+
+    fn clear(&mut self) {
+        self.buffer.clear();
+        self.consumed = 0;
+    }
+
+    fn decode(&self, line: &[u8]) -> Result<String, String> {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        if line.len() > self.max_length {
+            return Err(self.too_long());
+        }
+        String::from_utf8(line.to_vec()).map_err(|_| "Line is not valid UTF-8".to_string())
+    }
+
+    fn too_long(&self) -> String {
+        format!("Line too long (max {} bytes)", self.max_length)
+    }
+}
+
+/// Splits a streamed body into lines, without their `\n` or `\r\n`. Lines
+/// that are too long or not UTF-8 come out as errors, as does a failure to
+/// read the body, which also ends the stream.
+pub fn lines<S, E>(body: S, max_length: usize) -> impl Stream<Item = Result<String, String>>
+where
+    S: Stream<Item = Result<Bytes, E>> + Unpin,
+    E: std::fmt::Display,
+{
+    let lines = Lines {
+        body,
+        buffer: Vec::new(),
+        consumed: 0,
+        max_length,
+        skipping: false,
+        done: false,
+    };
+    stream::unfold(lines, |mut lines| async move {
+        loop {
+            if let Some(line) = lines.next_line() {
+                return Some((line, lines));
+            }
+            if lines.done {
+                return lines.last_line().map(|line| (line, lines));
+            }
+            match lines.body.next().await {
+                Some(Ok(chunk)) => lines.append(&chunk),
+                Some(Err(error)) => {
+                    lines.clear();
+                    lines.done = true;
+                    return Some((
+                        Err(format!("Failed to read request body: {}", error)),
+                        lines,
+                    ));
+                }
+                None => lines.done = true,
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn split(chunks: &[&[u8]], max_length: usize) -> Vec<Result<String, String>> {
+        let chunks: Vec<Result<Bytes, String>> = chunks
+            .iter()
+            .map(|chunk| Ok(Bytes::copy_from_slice(chunk)))
+            .collect();
+        lines(stream::iter(chunks), max_length).collect().await
+    }
+
+    #[tokio::test]
+    async fn test_lines_across_chunks() {
+        // `東` is split between chunks.
+        let bytes = "\"東京都\"\r\n\n\"大阪府\"".as_bytes();
+        let lines = split(&[&bytes[..2], &bytes[2..9], &bytes[9..]], 100).await;
+        assert_eq!(
+            lines,
+            vec![
+                Ok("\"東京都\"".to_string()),
+                Ok(String::new()),
+                Ok("\"大阪府\"".to_string())
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_oversized_lines_are_skipped() {
+        let lines = split(&[b"0123456789", b"0123\nok\n", b"0123456789\n"], 8).await;
+        assert_eq!(
+            lines,
+            vec![
+                Err("Line too long (max 8 bytes)".to_string()),
+                Ok("ok".to_string()),
+                Err("Line too long (max 8 bytes)".to_string())
+            ]
+        );
+    }
+}
@@ -0,0 +1,267 @@
+//! Canonicalization of raw address input before it reaches the parser.
+//!
+//! Addresses typed by people mix full-width and half-width characters, use
+//! several dash look-alikes between block numbers and sometimes carry a postal
+//! code in front. The steps below bring them to one spelling so the parser and
+//! the response cache see the same string for the same address.
+
+/// Full-width counterparts of the half-width katakana block (U+FF61..=U+FF9F).
+const HALFWIDTH_KANA: &str =
+    "。「」、・ヲァィゥェォャュョッーアイウエオカキクケコサシスセソタチツテトナニヌネノハヒフヘホマミムメモヤユヨラリルレロワン゛゜";
+
+/// Characters used in place of a hyphen between block numbers.
+const DASHES: [char; 9] = ['‐', '‑', '‒', '–', '—', '―', '−', '﹣', '─'];
+
+const KANJI_DIGITS: &str = "〇一二三四五六七八九";
+
+/// Runs the full normalization pipeline.
+pub fn normalize_address(input: &str) -> String {
+    let address = chome_to_arabic(&normalize_characters(input));
+    let address = collapse_whitespace(&address);
+    strip_postal_code(&address).to_string()
+}
+
+/// Applies only the character-level steps (width and dashes), leaving
+/// whitespace and structure untouched.
+pub fn normalize_characters(input: &str) -> String {
+    normalize_dashes(&normalize_width(input))
+}
+
+/// Converts full-width ASCII to half-width and half-width katakana to
+/// full-width, combining voiced sound marks with the preceding kana.
+fn normalize_width(input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    for c in input.chars() {
+        match c {
+            '\u{3000}' => output.push(' '),
+            '\u{FF01}'..='\u{FF5E}' => {
+                output.push(char::from_u32(c as u32 - 0xFEE0).unwrap_or(c));
+            }
+            '\u{FF9E}' | '\u{FF9F}' => {
+                let offset = if c == '\u{FF9E}' { 1 } else { 2 };
+                match output.pop() {
+                    Some(kana) if offset == 1 && kana == 'ウ' => output.push('ヴ'),
+                    Some(kana)
+                        if (offset == 1
+                            && "カキクケコサシスセソタチツテトハヒフヘホ".contains(kana))
+                            || (offset == 2 && "ハヒフヘホ".contains(kana)) =>
+                    {
+                        output.push(char::from_u32(kana as u32 + offset).unwrap_or(kana));
+                    }
+                    previous => {
+                        output.extend(previous);
+                        output.push(if offset == 1 { '゛' } else { '゜' });
+                    }
+                }
+            }
+            '\u{FF61}'..='\u{FF9D}' => {
+                let index = (c as u32 - 0xFF61) as usize;
+                output.push(HALFWIDTH_KANA.chars().nth(index).unwrap_or(c));
+            }
+            _ => output.push(c),
+        }
+    }
+    output
+}
+
+/// Rewrites kanji numerals in front of `丁目` as Arabic numerals.
+fn chome_to_arabic(input: &str) -> String {
+    let pieces: Vec<&str> = input.split("丁目").collect();
+    let mut output = String::with_capacity(input.len());
+    for (i, piece) in pieces.iter().enumerate() {
+        if i + 1 == pieces.len() {
+            output.push_str(piece);
+            break;
+        }
+
+        let numeral_start = piece
+            .char_indices()
+            .rev()
+            .take_while(|(_, c)| is_kanji_numeral(*c))
+            .last()
+            .map(|(index, _)| index);
+        match numeral_start.and_then(|start| Some((start, kanji_to_number(&piece[start..])?))) {
+            Some((start, number)) => {
+                output.push_str(&piece[..start]);
+                output.push_str(&number.to_string());
+            }
+            None => output.push_str(piece),
+        }
+        output.push_str("丁目");
+    }
+    output
+}
+
+pub fn is_kanji_numeral(c: char) -> bool {
+    KANJI_DIGITS.contains(c) || matches!(c, '十' | '百' | '千')
+}
+
+/// Reads positional (`二十三`) and digit-by-digit (`二三`) kanji numerals.
+/// Returns `None` for numerals too large for a `u32`.
+pub fn kanji_to_number(numeral: &str) -> Option<u32> {
+    let digit = |c: char| KANJI_DIGITS.chars().position(|d| d == c).map(|d| d as u32);
+
+    if !numeral.contains(['十', '百', '千']) {
+        return numeral.chars().try_fold(0u32, |number, c| {
+            number.checked_mul(10)?.checked_add(digit(c)?)
+        });
+    }
+
+    let mut total = 0u32;
+    let mut current = None;
+    for c in numeral.chars() {
+        let unit = match c {
+            '十' => 10,
+            '百' => 100,
+            '千' => 1000,
+            _ => {
+                current = Some(digit(c)?);
+                continue;
+            }
+        };
+        total = total.checked_add(current.take().unwrap_or(1) * unit)?;
+    }
+    total.checked_add(current.unwrap_or(0))
+}
+
+/// Writes a number as a positional kanji numeral (`23` → `二十三`), the form
+/// used in town names. Numbers of 10000 and above are left in Arabic digits.
+pub fn number_to_kanji(number: u32) -> String {
+    if number == 0 || number >= 10000 {
+        return number.to_string();
+    }
+    let digits: Vec<char> = KANJI_DIGITS.chars().collect();
+    let mut output = String::new();
+    for (unit, name) in [(1000, "千"), (100, "百"), (10, "十")] {
+        let digit = (number / unit % 10) as usize;
+        if digit > 1 {
+            output.push(digits[digit]);
+        }
+        if digit > 0 {
+            output.push_str(name);
+        }
+    }
+    let ones = (number % 10) as usize;
+    if ones > 0 {
+        output.push(digits[ones]);
+    }
+    output
+}
+
+/// Replaces dash look-alikes with `-`. The long vowel mark `ー` is only
+/// treated as a dash right after a digit, since it is legitimate in katakana.
+fn normalize_dashes(input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    for c in input.chars() {
+        let after_digit = output.ends_with(|previous: char| previous.is_ascii_digit());
+        if DASHES.contains(&c) || (c == 'ー' && after_digit) {
+            output.push('-');
+        } else {
+            output.push(c);
+        }
+    }
+    output
+}
+
+/// Drops whitespace, keeping a single space only between ASCII words such
+/// as romanized building names.
+fn collapse_whitespace(input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    let mut pending_space = false;
+    for c in input.trim().chars() {
+        if c.is_whitespace() {
+            pending_space = true;
+            continue;
+        }
+        if pending_space
+            && c.is_ascii_alphanumeric()
+            && output.ends_with(|previous: char| previous.is_ascii_alphanumeric())
+        {
+            output.push(' ');
+        }
+        pending_space = false;
+        output.push(c);
+    }
+    output
+}
+
+/// Strips a leading postal code written as `〒1500001`, `〒150-0001` or
+/// `150-0001`.
+fn strip_postal_code(input: &str) -> &str {
+    let (marked, rest) = match input.strip_prefix('〒') {
+        Some(rest) => (true, rest),
+        None => (false, input),
+    };
+
+    let bytes = rest.as_bytes();
+    let digits = |range: std::ops::Range<usize>| {
+        bytes
+            .get(range)
+            .is_some_and(|b| b.iter().all(u8::is_ascii_digit))
+    };
+    let code_len = if digits(0..3) && bytes.get(3) == Some(&b'-') && digits(4..8) {
+        8
+    } else if marked && digits(0..7) {
+        7
+    } else {
+        return input;
+    };
+
+    if bytes.get(code_len).is_some_and(u8::is_ascii_digit) {
+        return input;
+    }
+    rest[code_len..].trim_start()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_address() {
+        assert_eq!(
+            normalize_address("〒150-0001 東京都 渋谷区　神宮前１ー１－１"),
+            "東京都渋谷区神宮前1-1-1"
+        );
+        assert_eq!(
+            normalize_address("東京都渋谷区神宮前二十三丁目4‐5 ｾﾝﾀｰﾋﾞﾙ 3F"),
+            "東京都渋谷区神宮前23丁目4-5センタービル3F"
+        );
+        assert_eq!(
+            normalize_address("〒1000005千代田区丸の内一丁目9—1 Tokyo  Building"),
+            "千代田区丸の内1丁目9-1 Tokyo Building"
+        );
+        assert_eq!(normalize_address("東京都渋谷区"), "東京都渋谷区");
+    }
+
+    #[test]
+    fn test_kanji_to_number() {
+        assert_eq!(kanji_to_number("一"), Some(1));
+        assert_eq!(kanji_to_number("十"), Some(10));
+        assert_eq!(kanji_to_number("十二"), Some(12));
+        assert_eq!(kanji_to_number("四十二"), Some(42));
+        assert_eq!(kanji_to_number("百二十七"), Some(127));
+        assert_eq!(kanji_to_number("二〇"), Some(20));
+        assert_eq!(kanji_to_number("九九九九九九九九九九"), None);
+    }
+
+    #[test]
+    fn test_number_to_kanji() {
+        assert_eq!(number_to_kanji(1), "一");
+        assert_eq!(number_to_kanji(10), "十");
+        assert_eq!(number_to_kanji(23), "二十三");
+        assert_eq!(number_to_kanji(127), "百二十七");
+        for number in 1..2000 {
+            assert_eq!(kanji_to_number(&number_to_kanji(number)), Some(number));
+        }
+    }
+
+    #[test]
+    fn test_strip_postal_code() {
+        assert_eq!(strip_postal_code("〒150-0001東京都"), "東京都");
+        assert_eq!(strip_postal_code("〒1500001東京都"), "東京都");
+        assert_eq!(strip_postal_code("150-0001東京都"), "東京都");
+        assert_eq!(strip_postal_code("1500001東京都"), "1500001東京都");
+        assert_eq!(strip_postal_code("150-00012"), "150-00012");
+    }
+}
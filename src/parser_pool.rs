@@ -0,0 +1,355 @@
+//! Pre-built parsers keyed by the option set they run with.
+
+use crate::dataset::Dataset;
+use crate::master_data::{capture_fetch_errors, with_dataset, MasterDataClient};
+use japanese_address_parser::experimental::parser::{
+    DataSource as UpstreamDataSource, ParsedAddress as UpstreamParsedAddress, Parser, ParserOptions,
+};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Address dataset used to resolve cities and towns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DataSource {
+    Geolonia,
+    ChimeiRuiju,
+}
+
+impl DataSource {
+    const ALL: [DataSource; 2] = [DataSource::Geolonia, DataSource::ChimeiRuiju];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Geolonia => "geolonia",
+            Self::ChimeiRuiju => "chimei_ruiju",
+        }
+    }
+
+    fn to_upstream(self) -> UpstreamDataSource {
+        match self {
+            Self::Geolonia => UpstreamDataSource::Geolonia,
+            Self::ChimeiRuiju => UpstreamDataSource::ChimeiRuiju,
+        }
+    }
+}
+
+impl DataSource {
+    /// The offline dataset only has the Geolonia masters, so other sources
+    /// cannot be used with it.
+    pub fn check_offline(self) -> Result<(), String> {
+        match self {
+            Self::Geolonia => Ok(()),
+            other => Err(format!(
+                "Invalid data_source '{}' (only 'geolonia' is available with an offline dataset)",
+                other.as_str()
+            )),
+        }
+    }
+}
+
+impl std::str::FromStr for DataSource {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "geolonia" => Ok(Self::Geolonia),
+            "chimei_ruiju" => Ok(Self::ChimeiRuiju),
+            other => Err(format!(
+                "Invalid data_source '{}' (expected 'geolonia' or 'chimei_ruiju')",
+                other
+            )),
+        }
+    }
+}
+
+/// The full option set a parse runs with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ParserConfig {
+    pub data_source: DataSource,
+    pub correct_incomplete_city_names: bool,
+    pub verbose: bool,
+}
+
+impl ParserConfig {
+    /// Reads the server-level defaults from the environment.
+    pub fn from_env() -> Self {
+        let data_source = std::env::var("PARSER_DATA_SOURCE")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(DataSource::Geolonia);
+
+        let correct_incomplete_city_names = std::env::var("PARSER_CORRECT_INCOMPLETE_CITY_NAMES")
+            .ok()
+            .and_then(|value| parse_bool(&value).ok())
+            .unwrap_or(true);
+
+        let verbose = std::env::var("PARSER_VERBOSE")
+            .ok()
+            .and_then(|value| parse_bool(&value).ok())
+            .unwrap_or(false);
+
+        Self {
+            data_source,
+            correct_incomplete_city_names,
+            verbose,
+        }
+    }
+
+    /// Applies per-request overrides on top of this configuration.
+    pub fn with_overrides(self, overrides: &ParserOverrides) -> Self {
+        Self {
+            data_source: overrides.data_source.unwrap_or(self.data_source),
+            correct_incomplete_city_names: overrides
+                .correct_incomplete_city_names
+                .unwrap_or(self.correct_incomplete_city_names),
+            verbose: overrides.verbose.unwrap_or(self.verbose),
+        }
+    }
+
+    fn all() -> impl Iterator<Item = ParserConfig> {
+        DataSource::ALL.into_iter().flat_map(|data_source| {
+            [false, true]
+                .into_iter()
+                .flat_map(move |correct_incomplete_city_names| {
+                    [false, true].into_iter().map(move |verbose| ParserConfig {
+                        data_source,
+                        correct_incomplete_city_names,
+                        verbose,
+                    })
+                })
+        })
+    }
+}
+
+/// Per-request parser options; unset fields fall back to the server defaults.
+///
+/// Invalid values in a JSON body do not fail deserialization; they are
+/// reported by [`ParserOverrides::check`] so the request can be answered the
+/// same way as an invalid query parameter.
+#[derive(Debug, Default, Deserialize)]
+#[serde(from = "JsonParserOverrides")]
+pub struct ParserOverrides {
+    pub data_source: Option<DataSource>,
+    pub correct_incomplete_city_names: Option<bool>,
+    pub verbose: Option<bool>,
+    /// The first invalid value found in a JSON body.
+    invalid: Option<String>,
+}
+
+/// Parser options as they appear in a JSON body, before validation.
+#[derive(Deserialize)]
+struct JsonParserOverrides {
+    data_source: Option<serde_json::Value>,
+    correct_incomplete_city_names: Option<serde_json::Value>,
+    verbose: Option<serde_json::Value>,
+}
+
+impl From<JsonParserOverrides> for ParserOverrides {
+    fn from(json: JsonParserOverrides) -> Self {
+        let bool_field = |name: &str, value: Option<serde_json::Value>| match value {
+            None => Ok(None),
+            Some(serde_json::Value::Bool(value)) => Ok(Some(value)),
+            Some(_) => Err(format!(
+                "Invalid '{}' parameter (expected true or false)",
+                name
+            )),
+        };
+        let data_source = json
+            .data_source
+            .map(|value| match value {
+                serde_json::Value::String(value) => value.parse(),
+                other => other.to_string().parse(),
+            })
+            .transpose();
+        let correct_incomplete_city_names = bool_field(
+            "correct_incomplete_city_names",
+            json.correct_incomplete_city_names,
+        );
+        let verbose = bool_field("verbose", json.verbose);
+
+        let invalid = data_source
+            .as_ref()
+            .err()
+            .or(correct_incomplete_city_names.as_ref().err())
+            .or(verbose.as_ref().err())
+            .cloned();
+        Self {
+            data_source: data_source.ok().flatten(),
+            correct_incomplete_city_names: correct_incomplete_city_names.ok().flatten(),
+            verbose: verbose.ok().flatten(),
+            invalid,
+        }
+    }
+}
+
+impl ParserOverrides {
+    /// Fails with the first invalid value given in a JSON body.
+    pub fn check(&self) -> Result<(), String> {
+        match &self.invalid {
+            Some(error) => Err(error.clone()),
+            None => Ok(()),
+        }
+    }
+
+    /// Builds overrides from GET query parameters.
+    pub fn from_query(params: &HashMap<String, String>) -> Result<Self, String> {
+        let bool_param = |name: &str| -> Result<Option<bool>, String> {
+            params
+                .get(name)
+                .map(|value| {
+                    parse_bool(value).map_err(|_| {
+                        format!("Invalid '{}' parameter (expected true or false)", name)
+                    })
+                })
+                .transpose()
+        };
+
+        Ok(Self {
+            data_source: params
+                .get("data_source")
+                .map(|value| value.parse())
+                .transpose()?,
+            correct_incomplete_city_names: bool_param("correct_incomplete_city_names")?,
+            verbose: bool_param("verbose")?,
+            invalid: None,
+        })
+    }
+}
+
+pub fn parse_bool(value: &str) -> Result<bool, ()> {
+    match value {
+        "true" | "1" => Ok(true),
+        "false" | "0" => Ok(false),
+        _ => Err(()),
+    }
+}
+
+struct PooledParser {
+    parser: Parser<MasterDataClient>,
+    options: ParserOptions,
+}
+
+/// Result of a pooled parse, including any master data fetch failure.
+pub struct ParseOutcome {
+    pub address: UpstreamParsedAddress,
+    pub fetch_error: Option<String>,
+}
+
+/// One parser per option set, built once at startup.
+pub struct ParserPool {
+    default_config: ParserConfig,
+    parsers: HashMap<ParserConfig, PooledParser>,
+    /// Offline dataset the parsers resolve against; `None` fetches master
+    /// data remotely.
+    dataset: Option<Arc<Dataset>>,
+}
+
+impl ParserPool {
+    pub fn new(default_config: ParserConfig, dataset: Option<Arc<Dataset>>) -> Self {
+        let parsers = ParserConfig::all()
+            .map(|config| {
+                let parser = PooledParser {
+                    parser: Parser::new(),
+                    options: ParserOptions {
+                        data_source: config.data_source.to_upstream(),
+                        correct_incomplete_city_names: config.correct_incomplete_city_names,
+                        verbose: config.verbose,
+                    },
+                };
+                (config, parser)
+            })
+            .collect();
+
+        Self {
+            default_config,
+            parsers,
+            dataset,
+        }
+    }
+
+    pub fn default_config(&self) -> ParserConfig {
+        self.default_config
+    }
+
+    /// Fails when `overrides` has an invalid value or, with an offline
+    /// dataset loaded, asks for a data source the dataset cannot serve.
+    pub fn check(&self, overrides: &ParserOverrides) -> Result<(), String> {
+        overrides.check()?;
+        match overrides.data_source {
+            Some(data_source) if self.dataset.is_some() => data_source.check_offline(),
+            _ => Ok(()),
+        }
+    }
+
+    pub async fn parse(&self, address: &str, config: ParserConfig) -> ParseOutcome {
+        let pooled = &self.parsers[&config];
+        let parse = pooled.parser.parse_with_options(address, &pooled.options);
+        let (address, fetch_error) =
+            capture_fetch_errors(with_dataset(self.dataset.clone(), parse)).await;
+        ParseOutcome {
+            address,
+            fetch_error,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pool_covers_every_option_set() {
+        let pool = ParserPool::new(ParserConfig::from_env(), None);
+        assert_eq!(pool.parsers.len(), 8);
+        assert!(ParserConfig::all().all(|config| pool.parsers.contains_key(&config)));
+    }
+
+    #[test]
+    fn test_overrides_from_query() {
+        let params = HashMap::from([
+            ("data_source".to_string(), "chimei_ruiju".to_string()),
+            ("verbose".to_string(), "1".to_string()),
+        ]);
+        let overrides = ParserOverrides::from_query(&params).unwrap();
+
+        let base = ParserConfig {
+            data_source: DataSource::Geolonia,
+            correct_incomplete_city_names: true,
+            verbose: false,
+        };
+        let config = base.with_overrides(&overrides);
+        assert_eq!(config.data_source, DataSource::ChimeiRuiju);
+        assert!(config.correct_incomplete_city_names);
+        assert!(config.verbose);
+
+        let params = HashMap::from([("data_source".to_string(), "zenrin".to_string())]);
+        assert!(ParserOverrides::from_query(&params).is_err());
+
+        let params = HashMap::from([("verbose".to_string(), "yes".to_string())]);
+        assert!(ParserOverrides::from_query(&params).is_err());
+    }
+
+    #[test]
+    fn test_overrides_from_json_report_invalid_values() {
+        let overrides: ParserOverrides =
+            serde_json::from_str(r#"{"data_source": "chimei_ruiju", "verbose": true}"#).unwrap();
+        assert!(overrides.check().is_ok());
+        assert_eq!(overrides.data_source, Some(DataSource::ChimeiRuiju));
+        assert_eq!(overrides.verbose, Some(true));
+
+        let overrides: ParserOverrides =
+            serde_json::from_str(r#"{"data_source": "zenrin"}"#).unwrap();
+        assert_eq!(
+            overrides.check().unwrap_err(),
+            "Invalid data_source 'zenrin' (expected 'geolonia' or 'chimei_ruiju')"
+        );
+
+        let overrides: ParserOverrides = serde_json::from_str(r#"{"verbose": "yes"}"#).unwrap();
+        assert_eq!(
+            overrides.check().unwrap_err(),
+            "Invalid 'verbose' parameter (expected true or false)"
+        );
+    }
+}
@@ -0,0 +1,458 @@
+//! Postal code index built from the Japan Post `KEN_ALL.CSV` dataset.
+//!
+//! The file is distributed in Shift_JIS (a UTF-8 edition also exists); both are
+//! accepted. Each row maps a 7-digit postal code to a prefecture, city and
+//! town. Town names carry annotations Japan Post uses for its own purposes
+//! (`丸の内（次のビルを除く）`, `以下に掲載がない場合`), which are stripped so the
+//! entries compare cleanly against parse results.
+//!
+//! The first column holds the 5-digit 全国地方公共団体コード of the city, which
+//! is kept (with its check digit) to report city codes on parse results.
+
+use crate::normalize::normalize_characters;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::Path;
+#[cfg(test)]
+use std::sync::Arc;
+
+/// Town names Japan Post uses for codes that cover a whole city.
+const WHOLE_CITY_TOWNS: [&str; 2] = ["以下に掲載がない場合", "の次に番地がくる場合"];
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PostalEntry {
+    pub prefecture: String,
+    pub city: String,
+    /// `None` when the code covers the whole city.
+    pub town: Option<String>,
+    pub prefecture_kana: String,
+    pub city_kana: String,
+    pub town_kana: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct PostalCodeIndex {
+    entries: HashMap<String, Vec<PostalEntry>>,
+    /// 6-digit local government codes keyed by prefecture and city name.
+    city_codes: HashMap<(String, String), String>,
+    /// Katakana readings keyed by prefecture and city name.
+    readings: HashMap<(String, String), CityReadings>,
+    /// First postal code listed for each town, or for the whole city when the
+    /// town is `None`.
+    postal_codes: HashMap<(String, String, Option<String>), String>,
+}
+
+/// Katakana readings of a city, its prefecture and its towns.
+#[derive(Debug, Default)]
+pub struct CityReadings {
+    pub prefecture_kana: String,
+    pub city_kana: String,
+    /// Town names paired with their readings.
+    pub towns: Vec<(String, String)>,
+}
+
+impl PostalCodeIndex {
+    /// Builds the index from the contents of a `KEN_ALL.CSV` file.
+    pub fn from_csv(bytes: &[u8]) -> Result<Self, String> {
+        let text = match std::str::from_utf8(bytes) {
+            Ok(text) => std::borrow::Cow::Borrowed(text),
+            Err(_) => encoding_rs::SHIFT_JIS.decode(bytes).0,
+        };
+
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_reader(text.as_bytes());
+
+        let mut index = Self::default();
+        // Long town names are split over several rows; the first row carries
+        // an unclosed `（` and the following rows only continue the annotation.
+        let mut in_continuation = false;
+        for (line, record) in reader.records().enumerate() {
+            let record = record.map_err(|e| format!("KEN_ALL line {}: {}", line + 1, e))?;
+            if record.len() < 9 {
+                return Err(format!(
+                    "KEN_ALL line {}: expected at least 9 columns, found {}",
+                    line + 1,
+                    record.len()
+                ));
+            }
+
+            let town = &record[8];
+            if in_continuation {
+                in_continuation = !town.contains('）');
+                continue;
+            }
+            in_continuation = town.contains('（') && !town.contains('）');
+
+            // Whole-city rows read `イカニケイサイガナイバアイ` and the like, which
+            // is not a town reading.
+            let town = clean_town(town);
+            let town_kana = town
+                .as_ref()
+                .and_then(|_| clean_town(&normalize_characters(&record[5])));
+            let entry = PostalEntry {
+                prefecture: record[6].to_string(),
+                city: record[7].to_string(),
+                town,
+                prefecture_kana: normalize_characters(&record[3]),
+                city_kana: normalize_characters(&record[4]),
+                town_kana,
+            };
+            if let Some(code) = local_government_code(&record[0]) {
+                index
+                    .city_codes
+                    .entry((entry.prefecture.clone(), entry.city.clone()))
+                    .or_insert(code);
+            }
+            let readings = index
+                .readings
+                .entry((entry.prefecture.clone(), entry.city.clone()))
+                .or_insert_with(|| CityReadings {
+                    prefecture_kana: entry.prefecture_kana.clone(),
+                    city_kana: entry.city_kana.clone(),
+                    towns: Vec::new(),
+                });
+            if let (Some(town), Some(kana)) = (&entry.town, &entry.town_kana) {
+                if !readings.towns.iter().any(|(name, _)| name == town) {
+                    readings.towns.push((town.clone(), kana.clone()));
+                }
+            }
+            index
+                .postal_codes
+                .entry((
+                    entry.prefecture.clone(),
+                    entry.city.clone(),
+                    entry.town.clone(),
+                ))
+                .or_insert_with(|| record[2].to_string());
+            let entries = index.entries.entry(record[2].to_string()).or_default();
+            if !entries.contains(&entry) {
+                entries.push(entry);
+            }
+        }
+
+        Ok(index)
+    }
+
+    pub fn load_file(path: &Path) -> Result<Self, String> {
+        let bytes =
+            std::fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        Self::from_csv(&bytes)
+    }
+
+    /// Looks up a postal code given as `1500001`, `150-0001` or `〒150-0001`.
+    pub fn lookup(&self, postal_code: &str) -> Option<&[PostalEntry]> {
+        self.entries
+            .get(&canonical_postal_code(postal_code)?)
+            .map(Vec::as_slice)
+    }
+
+    /// Returns the 6-digit 全国地方公共団体コード of a city.
+    pub fn city_code(&self, prefecture: &str, city: &str) -> Option<&str> {
+        self.city_codes
+            .get(&(prefecture.to_string(), city.to_string()))
+            .map(String::as_str)
+    }
+
+    pub fn readings(&self, prefecture: &str, city: &str) -> Option<&CityReadings> {
+        self.readings
+            .get(&(prefecture.to_string(), city.to_string()))
+    }
+
+    /// Postal code of a town, falling back to the code covering the whole city.
+    pub fn find_postal_code(
+        &self,
+        prefecture: &str,
+        city: &str,
+        town: Option<&str>,
+    ) -> Option<&str> {
+        let key = |town: Option<&str>| {
+            (
+                prefecture.to_string(),
+                city.to_string(),
+                town.map(str::to_string),
+            )
+        };
+        town.and_then(|town| self.postal_codes.get(&key(Some(town))))
+            .or_else(|| self.postal_codes.get(&key(None)))
+            .map(String::as_str)
+    }
+
+    /// Reading of a prefecture, taken from any of its cities.
+    pub fn prefecture_kana(&self, prefecture: &str) -> Option<&str> {
+        self.readings
+            .iter()
+            .find(|((name, _), _)| name == prefecture)
+            .map(|(_, readings)| readings.prefecture_kana.as_str())
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &PostalEntry> {
+        self.entries.values().flatten()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+}
+
+/// Appends the check digit to a 5-digit local government code.
+fn local_government_code(code: &str) -> Option<String> {
+    let digits: Vec<u32> = code
+        .chars()
+        .map(|c| c.to_digit(10))
+        .collect::<Option<_>>()?;
+    if digits.len() != 5 {
+        return None;
+    }
+    let sum: u32 = digits.iter().zip([6, 5, 4, 3, 2]).map(|(d, w)| d * w).sum();
+    Some(format!("{}{}", code, (11 - sum % 11) % 10))
+}
+
+/// Outcome of comparing a supplied postal code with a parse result.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PostalCodeStatus {
+    Match,
+    Mismatch,
+    UnknownCode,
+    InvalidCode,
+    /// No postal code dataset is loaded.
+    Unavailable,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PostalCodeCheck {
+    pub postal_code: String,
+    pub status: PostalCodeStatus,
+    /// Components that disagree with the closest entry for the code.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub mismatched_fields: Vec<&'static str>,
+    /// What the postal code resolves to, reported on a mismatch.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub expected: Vec<PostalEntry>,
+}
+
+/// Why a resolved city has or lacks a `city_code`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CityCodeStatus {
+    Found,
+    /// The dataset has no code for the city.
+    Unknown,
+    /// No postal code dataset is loaded.
+    Unavailable,
+}
+
+/// Looks up the city code of `city` using `index`, if one is loaded.
+pub fn lookup_city_code(
+    index: Option<&PostalCodeIndex>,
+    prefecture: &str,
+    city: &str,
+) -> (Option<String>, CityCodeStatus) {
+    let Some(index) = index else {
+        return (None, CityCodeStatus::Unavailable);
+    };
+    match index.city_code(prefecture, city) {
+        Some(code) => (Some(code.to_string()), CityCodeStatus::Found),
+        None => (None, CityCodeStatus::Unknown),
+    }
+}
+
+/// Compares `postal_code` with parsed components using `index`, if one is
+/// loaded. Components the parser did not resolve are not counted as
+/// mismatches.
+pub fn check(
+    index: Option<&PostalCodeIndex>,
+    postal_code: &str,
+    prefecture: Option<&str>,
+    city: Option<&str>,
+    town: Option<&str>,
+) -> PostalCodeCheck {
+    let result = |status, mismatched_fields, expected| PostalCodeCheck {
+        postal_code: postal_code.to_string(),
+        status,
+        mismatched_fields,
+        expected,
+    };
+
+    let Some(index) = index else {
+        return result(PostalCodeStatus::Unavailable, Vec::new(), Vec::new());
+    };
+    if canonical_postal_code(postal_code).is_none() {
+        return result(PostalCodeStatus::InvalidCode, Vec::new(), Vec::new());
+    }
+    let Some(entries) = index.lookup(postal_code) else {
+        return result(PostalCodeStatus::UnknownCode, Vec::new(), Vec::new());
+    };
+
+    let mismatches = |entry: &PostalEntry| {
+        let mut fields = Vec::new();
+        if prefecture.is_some_and(|prefecture| prefecture != entry.prefecture) {
+            fields.push("prefecture");
+        }
+        if city.is_some_and(|city| city != entry.city) {
+            fields.push("city");
+        }
+        // Parsed towns include their 丁目 (`神宮前一丁目`); Japan Post's do not.
+        if let (Some(town), Some(expected)) = (town, entry.town.as_deref()) {
+            if !town.starts_with(expected) && !expected.starts_with(town) {
+                fields.push("town");
+            }
+        }
+        fields
+    };
+
+    let closest = entries
+        .iter()
+        .map(mismatches)
+        .min_by_key(Vec::len)
+        .unwrap_or_default();
+    if closest.is_empty() {
+        result(PostalCodeStatus::Match, Vec::new(), Vec::new())
+    } else {
+        result(PostalCodeStatus::Mismatch, closest, entries.to_vec())
+    }
+}
+
+/// Strips Japan Post annotations from a town name.
+fn clean_town(town: &str) -> Option<String> {
+    if WHOLE_CITY_TOWNS.contains(&town) || town.ends_with("一円") {
+        return None;
+    }
+    let town = match town.find(['（', '(']) {
+        Some(start) => &town[..start],
+        None => town,
+    };
+    Some(town.to_string()).filter(|town| !town.is_empty())
+}
+
+/// Returns the 7 ASCII digits of a postal code, or `None` if it is malformed.
+pub fn canonical_postal_code(postal_code: &str) -> Option<String> {
+    let postal_code = normalize_characters(postal_code.trim());
+    let digits: String = postal_code
+        .trim_start_matches('〒')
+        .chars()
+        .filter(|c| *c != '-')
+        .collect();
+    (digits.len() == 7 && digits.chars().all(|c| c.is_ascii_digit())).then_some(digits)
+}
+
+/// Loads the index from `POSTAL_CODE_DATASET` when it is set.
+pub fn init_from_env() -> Result<Option<PostalCodeIndex>, String> {
+    let Ok(path) = std::env::var("POSTAL_CODE_DATASET") else {
+        return Ok(None);
+    };
+    PostalCodeIndex::load_file(Path::new(&path)).map(Some)
+}
+
+/// A few Tokyo codes for tests to look up.
+#[cfg(test)]
+pub fn test_index() -> Arc<PostalCodeIndex> {
+    Arc::new(PostalCodeIndex::from_csv(TEST_KEN_ALL.as_bytes()).unwrap())
+}
+
+#[cfg(test)]
+const TEST_KEN_ALL: &str = r#"13113,"150  ","1500000","ﾄｳｷｮｳﾄ","ｼﾌﾞﾔｸ","ｲｶﾆｹｲｻｲｶﾞﾅｲﾊﾞｱｲ","東京都","渋谷区","以下に掲載がない場合",0,0,0,0,0,0
+13113,"150  ","1500001","ﾄｳｷｮｳﾄ","ｼﾌﾞﾔｸ","ｼﾞﾝｸﾞｳﾏｴ","東京都","渋谷区","神宮前",0,0,1,0,0,0
+13113,"150  ","1500002","ﾄｳｷｮｳﾄ","ｼﾌﾞﾔｸ","ｼﾌﾞﾔ","東京都","渋谷区","渋谷",0,0,1,0,0,0
+13101,"100  ","1000005","ﾄｳｷｮｳﾄ","ﾁﾖﾀﾞｸ","ﾏﾙﾉｳﾁ(ﾂｷﾞﾉﾋﾞﾙｦﾉｿﾞｸ)","東京都","千代田区","丸の内（次のビルを除く）",0,0,1,0,0,0
+13101,"100  ","1006890","ﾄｳｷｮｳﾄ","ﾁﾖﾀﾞｸ","ﾏﾙﾉｳﾁJPﾀﾜｰ(ﾁｶｲ･ｶｲｿｳﾌﾒｲ)","東京都","千代田区","丸の内ＪＰタワー（地階・階層不明）",0,0,0,0,0,0
+01101,"064  ","0640941","ﾎｯｶｲﾄﾞｳ","ｻｯﾎﾟﾛｼﾁｭｳｵｳｸ","ｱｻﾋｶﾞｵｶ","北海道","札幌市中央区","旭ケ丘（１～３丁目、５丁目、６丁目、７丁目１～４番、",0,0,1,0,0,0
+01101,"064  ","0640941","ﾎｯｶｲﾄﾞｳ","ｻｯﾎﾟﾛｼﾁｭｳｵｳｸ","ｱｻﾋｶﾞｵｶ","北海道","札幌市中央区","８丁目）",0,0,1,0,0,0
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup_cleans_japan_post_annotations() {
+        let index = test_index();
+        assert_eq!(index.len(), 6);
+
+        let entries = index.lookup("〒150-0001").unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].town.as_deref(), Some("神宮前"));
+        assert_eq!(entries[0].town_kana.as_deref(), Some("ジングウマエ"));
+
+        let whole_city = &index.lookup("1500000").unwrap()[0];
+        assert_eq!(whole_city.town, None);
+        assert_eq!(whole_city.town_kana, None);
+        assert_eq!(
+            index.lookup("１００－０００５").unwrap()[0].town.as_deref(),
+            Some("丸の内")
+        );
+
+        let entries = index.lookup("064-0941").unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].town.as_deref(), Some("旭ケ丘"));
+
+        assert!(index.lookup("999-9999").is_none());
+        assert!(index.lookup("12345").is_none());
+    }
+
+    #[test]
+    fn test_check_against_parsed_components() {
+        let index = test_index();
+        let index = Some(index.as_ref());
+        let tokyo = Some("東京都");
+
+        let result = check(
+            index,
+            "150-0001",
+            tokyo,
+            Some("渋谷区"),
+            Some("神宮前一丁目"),
+        );
+        assert_eq!(result.status, PostalCodeStatus::Match);
+
+        let result = check(
+            index,
+            "1000005",
+            tokyo,
+            Some("渋谷区"),
+            Some("神宮前一丁目"),
+        );
+        assert_eq!(result.status, PostalCodeStatus::Mismatch);
+        assert_eq!(result.mismatched_fields, ["city", "town"]);
+        assert_eq!(result.expected[0].town.as_deref(), Some("丸の内"));
+
+        // A whole-city code matches any town in the city.
+        let result = check(index, "1500000", tokyo, Some("渋谷区"), Some("渋谷一丁目"));
+        assert_eq!(result.status, PostalCodeStatus::Match);
+
+        let status = |index, postal_code| check(index, postal_code, None, None, None).status;
+        assert_eq!(status(index, "150-000"), PostalCodeStatus::InvalidCode);
+        assert_eq!(status(index, "9999999"), PostalCodeStatus::UnknownCode);
+        assert_eq!(status(None, "150-0001"), PostalCodeStatus::Unavailable);
+    }
+
+    #[test]
+    fn test_city_codes_carry_check_digit() {
+        let index = test_index();
+        assert_eq!(index.city_code("東京都", "渋谷区"), Some("131130"));
+        assert_eq!(index.city_code("東京都", "千代田区"), Some("131016"));
+        assert_eq!(index.city_code("北海道", "札幌市中央区"), Some("011011"));
+        assert_eq!(index.city_code("東京都", "港区"), None);
+
+        assert_eq!(
+            lookup_city_code(Some(&*index), "東京都", "港区"),
+            (None, CityCodeStatus::Unknown)
+        );
+        assert_eq!(
+            lookup_city_code(None, "東京都", "渋谷区"),
+            (None, CityCodeStatus::Unavailable)
+        );
+    }
+
+    #[test]
+    fn test_from_csv_accepts_shift_jis() {
+        let (encoded, _, _) = encoding_rs::SHIFT_JIS.encode(TEST_KEN_ALL);
+        let index = PostalCodeIndex::from_csv(&encoded).unwrap();
+        assert_eq!(
+            index.lookup("1500002").unwrap()[0].city,
+            "渋谷区".to_string()
+        );
+    }
+}
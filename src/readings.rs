@@ -0,0 +1,502 @@
+//! Kana readings and romanized names for parse results.
+//!
+//! Readings come from the postal code dataset, which lists a katakana reading
+//! for every prefecture, city and town. Romanization follows modified Hepburn,
+//! with long vowels either dropped (`Tokyo`) or marked with macrons (`Tōkyō`).
+//! Administrative suffixes are split off the way they are written on English
+//! addresses: `Tokyo-to`, `Shibuya-ku`, `Nishitama-gun Okutama-machi`.
+
+use crate::normalize::{is_kanji_numeral, kanji_to_number};
+use crate::postal_code::{CityReadings, PostalCodeIndex};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// Suffixes split off prefecture and city names, with their readings.
+const SUFFIXES: [(&str, &[&str]); 8] = [
+    ("都", &["ト"]),
+    ("府", &["フ"]),
+    ("県", &["ケン"]),
+    ("市", &["シ"]),
+    ("区", &["ク"]),
+    ("町", &["チョウ", "マチ"]),
+    ("村", &["ムラ", "ソン"]),
+    ("郡", &["グン"]),
+];
+
+const KANA_DIGITS: [&str; 10] = [
+    "",
+    "イチ",
+    "ニ",
+    "サン",
+    "ヨン",
+    "ゴ",
+    "ロク",
+    "ナナ",
+    "ハチ",
+    "キュウ",
+];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RomajiStyle {
+    /// Long vowels are written as a single vowel: `Tokyo`, `Jingumae`.
+    #[default]
+    Hepburn,
+    /// Long vowels carry a macron: `Tōkyō`, `Jingūmae`.
+    HepburnMacrons,
+}
+
+impl FromStr for RomajiStyle {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "hepburn" => Ok(Self::Hepburn),
+            "hepburn_macrons" => Ok(Self::HepburnMacrons),
+            other => Err(format!(
+                "Invalid romaji_style '{}' (expected hepburn or hepburn_macrons)",
+                other
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Readings {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prefecture_kana: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prefecture_romaji: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub city_kana: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub city_romaji: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub town_kana: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub town_romaji: Option<String>,
+}
+
+/// Looks up readings for the resolved components in `index`. Components the
+/// postal code dataset has no reading for are left empty.
+pub fn readings(
+    index: Option<&PostalCodeIndex>,
+    prefecture: Option<&str>,
+    city: Option<&str>,
+    town: Option<&str>,
+    style: RomajiStyle,
+) -> Readings {
+    let mut readings = Readings::default();
+    let (Some(index), Some(prefecture)) = (index, prefecture) else {
+        return readings;
+    };
+
+    let city_readings = city.and_then(|city| index.readings(prefecture, city));
+    let prefecture_kana = match city_readings {
+        Some(city_readings) => Some(city_readings.prefecture_kana.as_str()),
+        None => index.prefecture_kana(prefecture),
+    };
+    if let Some(kana) = prefecture_kana {
+        readings.prefecture_romaji = Some(romanize_name(prefecture, kana, style));
+        readings.prefecture_kana = Some(kana.to_string());
+    }
+
+    let (Some(city), Some(city_readings)) = (city, city_readings) else {
+        return readings;
+    };
+    readings.city_kana = Some(city_readings.city_kana.clone());
+    readings.city_romaji = Some(romanize_name(city, &city_readings.city_kana, style));
+
+    if let Some((kana, romaji)) = town.and_then(|town| town_readings(city_readings, town, style)) {
+        readings.town_kana = Some(kana);
+        readings.town_romaji = Some(romaji);
+    }
+    readings
+}
+
+/// Reads a parsed town. Postal code towns carry no 丁目, so `神宮前一丁目` is
+/// read as `神宮前` plus its 丁目 number.
+fn town_readings(
+    city_readings: &CityReadings,
+    town: &str,
+    style: RomajiStyle,
+) -> Option<(String, String)> {
+    let reading = |name: &str| {
+        city_readings
+            .towns
+            .iter()
+            .find(|(town, _)| town == name)
+            .map(|(_, kana)| (kana, capitalize(&romanize(kana, style))))
+    };
+    if let Some((kana, romaji)) = reading(town) {
+        return Some((kana.clone(), romaji));
+    }
+
+    let (base, Some(number)) = split_chome(town) else {
+        return None;
+    };
+    let (kana, romaji) = reading(base)?;
+    Some((
+        format!("{}{}", kana, chome_kana(number)?),
+        format!("{} {}-{}", romaji, number, romanize("チョウメ", style)),
+    ))
+}
+
+/// Splits a trailing `{number}丁目` off a town name: `神宮前一丁目` becomes
+/// `神宮前` and 1.
+pub fn split_chome(town: &str) -> (&str, Option<u32>) {
+    let Some(before) = town.strip_suffix("丁目") else {
+        return (town, None);
+    };
+    let start = before
+        .char_indices()
+        .rev()
+        .take_while(|(_, c)| c.is_ascii_digit() || is_kanji_numeral(*c))
+        .last()
+        .map(|(index, _)| index);
+    let number = start.and_then(|start| {
+        let number = &before[start..];
+        number.parse().ok().or_else(|| kanji_to_number(number))
+    });
+    match (start, number) {
+        (Some(start), Some(number)) if start > 0 => (&before[..start], Some(number)),
+        _ => (town, None),
+    }
+}
+
+/// Reading of `{number}丁目`, with the sound changes of `一丁目` (イッチョウメ)
+/// and `八丁目` (ハッチョウメ).
+fn chome_kana(number: u32) -> Option<String> {
+    if !(1..100).contains(&number) {
+        return None;
+    }
+    let (tens, units) = ((number / 10) as usize, (number % 10) as usize);
+    let mut kana = String::new();
+    if tens > 1 {
+        kana.push_str(KANA_DIGITS[tens]);
+    }
+    if tens > 0 {
+        kana.push_str(if units == 0 { "ジュッ" } else { "ジュウ" });
+    }
+    kana.push_str(match units {
+        1 => "イッ",
+        8 => "ハッ",
+        units => KANA_DIGITS[units],
+    });
+    kana.push_str("チョウメ");
+    Some(kana)
+}
+
+/// Romanizes a prefecture or city name, splitting off its administrative
+/// suffix when the reading ends with the suffix's reading.
+fn romanize_name(name: &str, kana: &str, style: RomajiStyle) -> String {
+    // A town or village inside a county: 西多摩郡奥多摩町.
+    if let (Some((county, name_rest)), Some((county_kana, kana_rest))) =
+        (name.split_once('郡'), kana.split_once("グン"))
+    {
+        if !county.is_empty() && !name_rest.is_empty() {
+            return format!(
+                "{}-{} {}",
+                capitalize(&romanize(county_kana, style)),
+                romanize("グン", style),
+                romanize_name(name_rest, kana_rest, style)
+            );
+        }
+    }
+
+    for (suffix, suffix_readings) in SUFFIXES {
+        if !name.ends_with(suffix) || name == suffix {
+            continue;
+        }
+        for suffix_kana in suffix_readings {
+            if let Some(base) = kana.strip_suffix(suffix_kana).filter(|b| !b.is_empty()) {
+                return format!(
+                    "{}-{}",
+                    capitalize(&romanize(base, style)),
+                    romanize(suffix_kana, style)
+                );
+            }
+        }
+    }
+    capitalize(&romanize(kana, style))
+}
+
+/// Romanizes a katakana (or hiragana) reading in lowercase. Characters other
+/// than kana are passed through unchanged.
+pub fn romanize(kana: &str, style: RomajiStyle) -> String {
+    let chars: Vec<char> = kana.chars().map(to_katakana).collect();
+    let mut syllables: Vec<String> = Vec::new();
+    let mut geminate = false;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        i += 1;
+        match c {
+            'ッ' => {
+                geminate = true;
+                continue;
+            }
+            'ー' => {
+                if let Some(last) = syllables.last_mut() {
+                    lengthen(last, style);
+                }
+                continue;
+            }
+            _ => {}
+        }
+
+        let Some(base) = syllable(c) else {
+            syllables.push(c.to_string());
+            geminate = false;
+            continue;
+        };
+        let mut romaji = base.to_string();
+        if let Some(combined) = chars.get(i).and_then(|small| combine(base, *small)) {
+            romaji = combined;
+            i += 1;
+        }
+
+        if std::mem::take(&mut geminate) {
+            if romaji.starts_with("ch") {
+                romaji.insert(0, 't');
+            } else if let Some(first) = romaji.chars().next().filter(|c| !is_vowel(*c)) {
+                romaji.insert(0, first);
+            }
+        }
+
+        // Long vowels: おう, おお and うう.
+        if let Some(last) = syllables.last_mut() {
+            let long = match romaji.as_str() {
+                "u" => last.ends_with(['o', 'u']),
+                "o" => last.ends_with('o'),
+                _ => false,
+            };
+            if long && c != 'ヲ' {
+                lengthen(last, style);
+                continue;
+            }
+        }
+        syllables.push(romaji);
+    }
+
+    let mut output = String::new();
+    for (i, romaji) in syllables.iter().enumerate() {
+        output.push_str(romaji);
+        // ン before a vowel or y is written n' to keep it readable: Shin'ei.
+        let before_vowel = syllables
+            .get(i + 1)
+            .and_then(|next| next.chars().next())
+            .is_some_and(|next| is_vowel(next) || next == 'y');
+        if romaji == "n" && before_vowel {
+            output.push('\'');
+        }
+    }
+    output
+}
+
+fn to_katakana(c: char) -> char {
+    match c {
+        'ぁ'..='ゖ' => char::from_u32(c as u32 + 0x60).unwrap_or(c),
+        _ => c,
+    }
+}
+
+fn is_vowel(c: char) -> bool {
+    matches!(c, 'a' | 'i' | 'u' | 'e' | 'o' | 'ā' | 'ī' | 'ū' | 'ē' | 'ō')
+}
+
+/// Marks the final vowel of `syllable` as long.
+fn lengthen(syllable: &mut String, style: RomajiStyle) {
+    if style == RomajiStyle::Hepburn {
+        return;
+    }
+    let macron = match syllable.pop() {
+        Some('a') => 'ā',
+        Some('i') => 'ī',
+        Some('u') => 'ū',
+        Some('e') => 'ē',
+        Some('o') => 'ō',
+        Some(other) => other,
+        None => return,
+    };
+    syllable.push(macron);
+}
+
+/// Combines a syllable with a following small kana: キャ → kya, ファ → fa.
+fn combine(base: &str, small: char) -> Option<String> {
+    let vowel = match small {
+        'ャ' => "ya",
+        'ュ' => "yu",
+        'ョ' => "yo",
+        'ァ' => "a",
+        'ィ' => "i",
+        'ゥ' => "u",
+        'ェ' => "e",
+        'ォ' => "o",
+        _ => return None,
+    };
+    let consonant = match base {
+        "u" => "w",
+        "vu" => "v",
+        _ => &base[..base.len() - 1],
+    };
+    let combined = match vowel.strip_prefix('y') {
+        Some(vowel) if matches!(consonant, "sh" | "ch" | "j") => format!("{}{}", consonant, vowel),
+        Some(_) if !base.ends_with('i') => return None,
+        _ => format!("{}{}", consonant, vowel),
+    };
+    Some(combined)
+}
+
+fn syllable(c: char) -> Option<&'static str> {
+    Some(match c {
+        'ア' | 'ァ' => "a",
+        'イ' | 'ィ' | 'ヰ' => "i",
+        'ウ' | 'ゥ' => "u",
+        'エ' | 'ェ' | 'ヱ' => "e",
+        'オ' | 'ォ' | 'ヲ' => "o",
+        'カ' => "ka",
+        'キ' => "ki",
+        'ク' => "ku",
+        'ケ' | 'ヶ' => "ke",
+        'コ' => "ko",
+        'ガ' => "ga",
+        'ギ' => "gi",
+        'グ' => "gu",
+        'ゲ' => "ge",
+        'ゴ' => "go",
+        'サ' => "sa",
+        'シ' => "shi",
+        'ス' => "su",
+        'セ' => "se",
+        'ソ' => "so",
+        'ザ' => "za",
+        'ジ' | 'ヂ' => "ji",
+        'ズ' | 'ヅ' => "zu",
+        'ゼ' => "ze",
+        'ゾ' => "zo",
+        'タ' => "ta",
+        'チ' => "chi",
+        'ツ' => "tsu",
+        'テ' => "te",
+        'ト' => "to",
+        'ダ' => "da",
+        'デ' => "de",
+        'ド' => "do",
+        'ナ' => "na",
+        'ニ' => "ni",
+        'ヌ' => "nu",
+        'ネ' => "ne",
+        'ノ' => "no",
+        'ハ' => "ha",
+        'ヒ' => "hi",
+        'フ' => "fu",
+        'ヘ' => "he",
+        'ホ' => "ho",
+        'バ' => "ba",
+        'ビ' => "bi",
+        'ブ' => "bu",
+        'ベ' => "be",
+        'ボ' => "bo",
+        'パ' => "pa",
+        'ピ' => "pi",
+        'プ' => "pu",
+        'ペ' => "pe",
+        'ポ' => "po",
+        'マ' => "ma",
+        'ミ' => "mi",
+        'ム' => "mu",
+        'メ' => "me",
+        'モ' => "mo",
+        'ヤ' | 'ャ' => "ya",
+        'ユ' | 'ュ' => "yu",
+        'ヨ' | 'ョ' => "yo",
+        'ラ' => "ra",
+        'リ' => "ri",
+        'ル' => "ru",
+        'レ' => "re",
+        'ロ' => "ro",
+        'ワ' | 'ヮ' => "wa",
+        'ン' => "n",
+        'ヴ' => "vu",
+        _ => return None,
+    })
+}
+
+pub fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::postal_code;
+
+    #[test]
+    fn test_romanize() {
+        use RomajiStyle::*;
+        assert_eq!(romanize("トウキョウ", Hepburn), "tokyo");
+        assert_eq!(romanize("トウキョウ", HepburnMacrons), "tōkyō");
+        assert_eq!(romanize("ジングウマエ", HepburnMacrons), "jingūmae");
+        assert_eq!(romanize("オオサカ", HepburnMacrons), "ōsaka");
+        assert_eq!(romanize("ホッカイドウ", Hepburn), "hokkaido");
+        assert_eq!(romanize("ハッチョウボリ", Hepburn), "hatchobori");
+        assert_eq!(romanize("シンエイ", Hepburn), "shin'ei");
+        assert_eq!(romanize("センター", HepburnMacrons), "sentā");
+        assert_eq!(romanize("ティーエフ", Hepburn), "tiefu");
+        assert_eq!(romanize("ちゅうおう", Hepburn), "chuo");
+    }
+
+    #[test]
+    fn test_romanize_name_splits_suffixes() {
+        let style = RomajiStyle::Hepburn;
+        assert_eq!(romanize_name("東京都", "トウキョウト", style), "Tokyo-to");
+        assert_eq!(romanize_name("北海道", "ホッカイドウ", style), "Hokkaido");
+        assert_eq!(romanize_name("渋谷区", "シブヤク", style), "Shibuya-ku");
+        assert_eq!(
+            romanize_name("西多摩郡奥多摩町", "ニシタマグンオクタママチ", style),
+            "Nishitama-gun Okutama-machi"
+        );
+        assert_eq!(chome_kana(1).as_deref(), Some("イッチョウメ"));
+        assert_eq!(chome_kana(20).as_deref(), Some("ニジュッチョウメ"));
+        assert_eq!(split_chome("神宮前一丁目"), ("神宮前", Some(1)));
+        assert_eq!(split_chome("銀座12丁目"), ("銀座", Some(12)));
+        assert_eq!(split_chome("丸の内"), ("丸の内", None));
+    }
+
+    #[test]
+    fn test_readings_from_postal_code_dataset() {
+        let index = postal_code::test_index();
+
+        let result = readings(
+            Some(&index),
+            Some("東京都"),
+            Some("渋谷区"),
+            Some("神宮前一丁目"),
+            RomajiStyle::Hepburn,
+        );
+        assert_eq!(result.prefecture_kana.as_deref(), Some("トウキョウト"));
+        assert_eq!(result.prefecture_romaji.as_deref(), Some("Tokyo-to"));
+        assert_eq!(result.city_romaji.as_deref(), Some("Shibuya-ku"));
+        assert_eq!(
+            result.town_kana.as_deref(),
+            Some("ジングウマエイッチョウメ")
+        );
+        assert_eq!(result.town_romaji.as_deref(), Some("Jingumae 1-chome"));
+
+        // A city missing from the dataset still gets its prefecture's reading.
+        let result = readings(
+            Some(&index),
+            Some("東京都"),
+            Some("八王子市"),
+            None,
+            RomajiStyle::HepburnMacrons,
+        );
+        assert_eq!(result.prefecture_romaji.as_deref(), Some("Tōkyō-to"));
+        assert_eq!(result.city_kana, None);
+    }
+}
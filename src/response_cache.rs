@@ -0,0 +1,135 @@
+//! Bounded LRU cache of parse results for repeated addresses.
+
+use crate::parser_pool::ParserConfig;
+use lru::LruCache;
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const DEFAULT_TTL_SECS: u64 = 300;
+
+// Cache metrics
+pub static HITS: AtomicU64 = AtomicU64::new(0);
+pub static MISSES: AtomicU64 = AtomicU64::new(0);
+
+/// Whether a response was served from the cache, reported as `X-Cache`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheStatus {
+    Hit,
+    Miss,
+}
+
+impl CacheStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Hit => "HIT",
+            Self::Miss => "MISS",
+        }
+    }
+}
+
+type CacheKey = (String, ParserConfig);
+
+pub struct ResponseCache<V> {
+    ttl: Duration,
+    entries: Mutex<LruCache<CacheKey, (Instant, V)>>,
+}
+
+impl<V: Clone> ResponseCache<V> {
+    pub fn new(max_entries: NonZeroUsize, ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Mutex::new(LruCache::new(max_entries)),
+        }
+    }
+
+    /// Builds the cache from `RESPONSE_CACHE_*` settings; `None` when disabled.
+    pub fn from_env() -> Option<Self> {
+        let max_entries = std::env::var("RESPONSE_CACHE_MAX_ENTRIES")
+            .ok()
+            .and_then(|value| value.parse::<usize>().ok())
+            .and_then(NonZeroUsize::new)?;
+
+        let ttl_secs = std::env::var("RESPONSE_CACHE_TTL_SECS")
+            .unwrap_or_else(|_| DEFAULT_TTL_SECS.to_string())
+            .parse::<u64>()
+            .unwrap_or(DEFAULT_TTL_SECS);
+
+        Some(Self::new(max_entries, Duration::from_secs(ttl_secs)))
+    }
+
+    /// Looks up a cached result for the normalized `address` and `config`.
+    pub fn get(&self, address: &str, config: ParserConfig) -> Option<V> {
+        let key = (address.to_string(), config);
+        let mut entries = self.entries.lock().unwrap();
+        let value = match entries.get(&key) {
+            Some((inserted_at, value)) if inserted_at.elapsed() < self.ttl => Some(value.clone()),
+            Some(_) => {
+                entries.pop(&key);
+                None
+            }
+            None => None,
+        };
+        match value {
+            Some(_) => HITS.fetch_add(1, Ordering::Relaxed),
+            None => MISSES.fetch_add(1, Ordering::Relaxed),
+        };
+        value
+    }
+
+    pub fn insert(&self, address: &str, config: ParserConfig, value: V) {
+        self.entries
+            .lock()
+            .unwrap()
+            .put((address.to_string(), config), (Instant::now(), value));
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser_pool::DataSource;
+
+    const CONFIG: ParserConfig = ParserConfig {
+        data_source: DataSource::Geolonia,
+        correct_incomplete_city_names: true,
+        verbose: false,
+    };
+
+    #[test]
+    fn test_cache_is_keyed_by_address_and_options() {
+        let cache = ResponseCache::new(NonZeroUsize::new(4).unwrap(), Duration::from_secs(60));
+        cache.insert("東京都渋谷区", CONFIG, 1);
+
+        assert_eq!(cache.get("東京都渋谷区", CONFIG), Some(1));
+        assert_eq!(cache.get("東京都港区", CONFIG), None);
+        let other = ParserConfig {
+            verbose: true,
+            ..CONFIG
+        };
+        assert_eq!(cache.get("東京都渋谷区", other), None);
+    }
+
+    #[test]
+    fn test_cache_expires_and_evicts_least_recently_used() {
+        let expired = ResponseCache::new(NonZeroUsize::new(4).unwrap(), Duration::ZERO);
+        expired.insert("a", CONFIG, 1);
+        assert_eq!(expired.get("a", CONFIG), None);
+        assert_eq!(expired.len(), 0);
+
+        let cache = ResponseCache::new(NonZeroUsize::new(2).unwrap(), Duration::from_secs(60));
+        cache.insert("a", CONFIG, 1);
+        cache.insert("b", CONFIG, 2);
+        assert_eq!(cache.get("a", CONFIG), Some(1));
+        cache.insert("c", CONFIG, 3);
+
+        assert_eq!(cache.get("b", CONFIG), None);
+        assert_eq!(cache.get("a", CONFIG), Some(1));
+        assert_eq!(cache.get("c", CONFIG), Some(3));
+    }
+}
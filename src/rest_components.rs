@@ -0,0 +1,308 @@
+//! Structured breakdown of the part of an address after the town name.
+//!
+//! The parser leaves block numbers and building details in a single `rest`
+//! string such as `1-1-1 神宮前ビル 305号室`. This splits it into 丁目, 番地,
+//! 号, building, floor and room, with numerals converted to Arabic digits.
+
+use crate::normalize::{is_kanji_numeral, kanji_to_number, normalize_characters};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RestComponents {
+    pub chome: Option<String>,
+    pub banchi: Option<String>,
+    pub go: Option<String>,
+    pub building: Option<String>,
+    pub floor: Option<String>,
+    pub room: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Slot {
+    Chome,
+    Banchi,
+    Go,
+}
+
+const SLOTS: [Slot; 3] = [Slot::Chome, Slot::Banchi, Slot::Go];
+
+/// Splits `rest`. When the resolved town already names its 丁目
+/// (`神宮前一丁目`), unlabelled numbers start at 番地 instead of 丁目.
+pub fn split_rest(rest: &str, town_has_chome: bool) -> RestComponents {
+    let rest = normalize_characters(rest);
+    let (numbers, remainder) = read_block_numbers(rest.trim_start());
+
+    let mut components = RestComponents::default();
+    let all_unlabelled = numbers.iter().all(|(_, slot)| slot.is_none());
+    let mut next_slot = if !town_has_chome && all_unlabelled && numbers.len() >= 3 {
+        0
+    } else {
+        1
+    };
+    let mut extra = Vec::new();
+    for (number, slot) in numbers {
+        let slot = match slot {
+            Some(slot) => slot,
+            None if next_slot < SLOTS.len() => SLOTS[next_slot],
+            None => {
+                extra.push(number);
+                continue;
+            }
+        };
+        next_slot = SLOTS.iter().position(|s| *s == slot).unwrap_or(0) + 1;
+        let field = match slot {
+            Slot::Chome => &mut components.chome,
+            Slot::Banchi => &mut components.banchi,
+            Slot::Go => &mut components.go,
+        };
+        *field = Some(number);
+    }
+
+    let (building, floor, room) = split_building(remainder);
+    components.building = building;
+    components.floor = floor;
+    // A single number past 号 (`1-2-3-405`) is a room number.
+    components.room = match (room, extra.len()) {
+        (Some(room), _) => Some(room),
+        (None, 1) => extra.pop(),
+        (None, _) => None,
+    };
+    components
+}
+
+/// Reads the leading run of block numbers such as `1-2-3` or `一丁目2番3号`.
+fn read_block_numbers(input: &str) -> (Vec<(String, Option<Slot>)>, &str) {
+    let mut numbers = Vec::new();
+    let mut rest = input;
+    while let Some((number, arabic, after)) = read_number(rest) {
+        if after.starts_with("号室") {
+            break;
+        }
+        let (slot, after) = if let Some(after) = after.strip_prefix("丁目") {
+            (Some(Slot::Chome), after)
+        } else if let Some(after) = after.strip_prefix("番地") {
+            (Some(Slot::Banchi), after)
+        } else if let Some(after) = after.strip_prefix('番') {
+            (Some(Slot::Banchi), after)
+        } else if let Some(after) = after.strip_prefix('号') {
+            (Some(Slot::Go), after)
+        } else {
+            (None, after)
+        };
+        // Kanji numerals only count with an explicit label; otherwise they are
+        // likely the start of a building name.
+        if slot.is_none() && !arabic {
+            break;
+        }
+
+        numbers.push((number, slot));
+        rest = after;
+        match rest.strip_prefix('-').or_else(|| rest.strip_prefix('の')) {
+            Some(after) => rest = after,
+            None if slot.is_some() => continue,
+            None => break,
+        }
+    }
+    (numbers, rest)
+}
+
+/// Reads a number at the start of `input`, returning it in Arabic digits and
+/// whether it was written in Arabic digits originally.
+fn read_number(input: &str) -> Option<(String, bool, &str)> {
+    let digits_end = input
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(input.len());
+    if digits_end > 0 {
+        return Some((input[..digits_end].to_string(), true, &input[digits_end..]));
+    }
+
+    let kanji_end = input
+        .char_indices()
+        .find(|(_, c)| !is_kanji_numeral(*c))
+        .map(|(index, _)| index)
+        .unwrap_or(input.len());
+    let number = kanji_to_number(&input[..kanji_end]).filter(|_| kanji_end > 0)?;
+    Some((number.to_string(), false, &input[kanji_end..]))
+}
+
+/// Splits the text after the block numbers into building, floor and room.
+fn split_building(remainder: &str) -> (Option<String>, Option<String>, Option<String>) {
+    let mut text = remainder.trim();
+
+    let mut room = None;
+    if let Some(before) = text
+        .strip_suffix("号室")
+        .or_else(|| text.strip_suffix('室'))
+    {
+        if let Some((before, number)) = split_trailing_number(before) {
+            room = Some(number);
+            text = before.trim_end();
+        }
+    } else if let Some((before, number)) = split_room_number(text) {
+        room = Some(number.to_string());
+        text = before.trim_end();
+    }
+
+    let mut floor = None;
+    if let Some(before) = text
+        .strip_suffix(['F', 'f'])
+        .or_else(|| text.strip_suffix('階'))
+    {
+        if let Some((before, number)) = split_trailing_number(before) {
+            let basement = before
+                .strip_suffix('B')
+                .or_else(|| before.strip_suffix("地下"));
+            floor = Some(match basement {
+                Some(_) => format!("B{}", number),
+                None => number,
+            });
+            text = basement.unwrap_or(before).trim_end();
+        }
+    }
+
+    let building = Some(text.trim_start_matches('-').trim())
+        .filter(|building| !building.is_empty())
+        .map(str::to_string);
+    (building, floor, room)
+}
+
+/// Splits off a bare room number: the whole text when it is only digits
+/// (`1-2-3 101`), digits after a space (`ABC Heights 201`), or digits right
+/// after a kana or kanji building name, where normalization has removed the
+/// space (`三田マンション201`). `第2` is taken as part of the name.
+fn split_room_number(text: &str) -> Option<(&str, &str)> {
+    let start = text
+        .char_indices()
+        .rev()
+        .take_while(|(_, c)| c.is_ascii_digit())
+        .last()
+        .map(|(index, _)| index)?;
+    let (before, number) = text.split_at(start);
+    let separated = match before.chars().next_back() {
+        None => true,
+        Some(c) if c.is_whitespace() => true,
+        Some('第') => false,
+        Some(c) => !c.is_ascii() && !is_kanji_numeral(c),
+    };
+    separated.then_some((before, number))
+}
+
+/// Splits a trailing run of Arabic or kanji digits off `input`.
+fn split_trailing_number(input: &str) -> Option<(&str, String)> {
+    let start = input
+        .char_indices()
+        .rev()
+        .take_while(|(_, c)| c.is_ascii_digit() || is_kanji_numeral(*c))
+        .last()
+        .map(|(index, _)| index)?;
+    let number = &input[start..];
+    let number = if number.chars().all(|c| c.is_ascii_digit()) {
+        number.to_string()
+    } else {
+        kanji_to_number(number)?.to_string()
+    };
+    Some((&input[..start], number))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn components(parts: [Option<&str>; 6]) -> RestComponents {
+        let [chome, banchi, go, building, floor, room] = parts.map(|p| p.map(str::to_string));
+        RestComponents {
+            chome,
+            banchi,
+            go,
+            building,
+            floor,
+            room,
+        }
+    }
+
+    #[test]
+    fn test_split_dashed_block_numbers() {
+        assert_eq!(
+            split_rest("1-1-1 神宮前ビル 305号室", false),
+            components([
+                Some("1"),
+                Some("1"),
+                Some("1"),
+                Some("神宮前ビル"),
+                None,
+                Some("305")
+            ])
+        );
+        assert_eq!(
+            split_rest("5-3 パークタワー 12F", true),
+            components([
+                None,
+                Some("5"),
+                Some("3"),
+                Some("パークタワー"),
+                Some("12"),
+                None
+            ])
+        );
+        assert_eq!(
+            split_rest("１２ー３４ー５６ー７０８", false),
+            components([Some("12"), Some("34"), Some("56"), None, None, Some("708")])
+        );
+    }
+
+    #[test]
+    fn test_split_labelled_block_numbers() {
+        assert_eq!(
+            split_rest("二丁目十番地三号 ABCハイツ地下1階", false),
+            components([
+                Some("2"),
+                Some("10"),
+                Some("3"),
+                Some("ABCハイツ"),
+                Some("B1"),
+                None
+            ])
+        );
+        assert_eq!(
+            split_rest("3番2号 三田マンション 201", true),
+            components([
+                None,
+                Some("3"),
+                Some("2"),
+                Some("三田マンション"),
+                None,
+                Some("201")
+            ])
+        );
+        assert_eq!(
+            split_rest("3番2号三田マンション201", true),
+            components([
+                None,
+                Some("3"),
+                Some("2"),
+                Some("三田マンション"),
+                None,
+                Some("201")
+            ])
+        );
+        assert_eq!(
+            split_rest("1-2-3 101", false),
+            components([Some("1"), Some("2"), Some("3"), None, None, Some("101")])
+        );
+        assert_eq!(
+            split_rest("1-2-3 サンハイツ第2", false),
+            components([
+                Some("1"),
+                Some("2"),
+                Some("3"),
+                Some("サンハイツ第2"),
+                None,
+                None
+            ])
+        );
+        assert_eq!(
+            split_rest("305号室", true),
+            components([None, None, None, None, None, Some("305")])
+        );
+    }
+}
@@ -0,0 +1,141 @@
+//! In-flight de-duplication of identical work.
+//!
+//! Concurrent callers that ask for the same key while a computation for it is
+//! still running await that computation instead of starting their own.
+
+use futures::future::{BoxFuture, FutureExt, Shared};
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::Hash;
+use std::sync::Mutex;
+
+pub struct SingleFlight<K, V> {
+    in_flight: Mutex<HashMap<K, Shared<BoxFuture<'static, V>>>>,
+}
+
+impl<K, V> SingleFlight<K, V>
+where
+    K: Clone + Eq + Hash,
+    V: Clone + Send + 'static,
+{
+    pub fn new() -> Self {
+        Self {
+            in_flight: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Runs the future built by `make` unless one is already in flight for
+    /// `key`, in which case its result is shared.
+    ///
+    /// Returns the result and whether it was coalesced onto another caller.
+    pub async fn run<F>(&self, key: K, make: impl FnOnce() -> F) -> (V, bool)
+    where
+        F: Future<Output = V> + Send + 'static,
+    {
+        let (future, leader) = {
+            let mut in_flight = self.in_flight.lock().unwrap();
+            match in_flight.get(&key) {
+                Some(future) => (future.clone(), None),
+                None => {
+                    let future = make().boxed().shared();
+                    in_flight.insert(key.clone(), future.clone());
+                    (
+                        future.clone(),
+                        Some(LeaderGuard {
+                            flight: self,
+                            key,
+                            future,
+                        }),
+                    )
+                }
+            }
+        };
+
+        let coalesced = leader.is_none();
+        let value = future.await;
+        // Dropping the guard (also on cancellation) lets the next caller start
+        // a fresh computation.
+        drop(leader);
+        (value, coalesced)
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.in_flight.lock().unwrap().len()
+    }
+}
+
+struct LeaderGuard<'a, K, V>
+where
+    K: Eq + Hash,
+{
+    flight: &'a SingleFlight<K, V>,
+    key: K,
+    future: Shared<BoxFuture<'static, V>>,
+}
+
+impl<K, V> Drop for LeaderGuard<'_, K, V>
+where
+    K: Eq + Hash,
+{
+    fn drop(&mut self) {
+        let mut in_flight = self.flight.in_flight.lock().unwrap();
+        // Only remove our own entry; a newer flight may have replaced it.
+        if in_flight
+            .get(&self.key)
+            .is_some_and(|future| Shared::ptr_eq(future, &self.future))
+        {
+            in_flight.remove(&self.key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_concurrent_callers_share_one_run() {
+        let flight = SingleFlight::<String, u32>::new();
+        let runs = Arc::new(AtomicUsize::new(0));
+
+        let call = || {
+            let runs = runs.clone();
+            flight.run("東京都".to_string(), move || async move {
+                runs.fetch_add(1, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                7
+            })
+        };
+
+        let (first, second, third) = tokio::join!(call(), call(), call());
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+        assert_eq!([first.0, second.0, third.0], [7, 7, 7]);
+        assert_eq!([first.1, second.1, third.1], [false, true, true]);
+        assert_eq!(flight.len(), 0);
+
+        let (value, coalesced) = call().await;
+        assert_eq!((value, coalesced), (7, false));
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_leader_releases_key() {
+        let flight = SingleFlight::<&str, u32>::new();
+
+        let slow = flight.run("key", || async {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            1
+        });
+        assert!(tokio::time::timeout(Duration::from_millis(10), slow)
+            .await
+            .is_err());
+        assert_eq!(flight.len(), 0);
+
+        let (value, coalesced) = flight.run("key", || async { 2 }).await;
+        assert_eq!((value, coalesced), (2, false));
+    }
+}
@@ -0,0 +1,150 @@
+//! Nearest-town lookup over the offline dataset.
+//!
+//! Town points are bucketed into a fixed grid of latitude/longitude cells.
+//! A query scans rings of cells around its own cell, stopping once no cell
+//! further out could hold anything closer than the best match so far.
+
+use crate::dataset::Dataset;
+use std::collections::HashMap;
+
+/// Grid cell size in degrees (roughly 2 km).
+const CELL_DEGREES: f64 = 0.02;
+/// Towns further away than this are not considered a match.
+const MAX_DISTANCE_METERS: f64 = 50_000.0;
+const EARTH_RADIUS_METERS: f64 = 6_371_008.8;
+const METERS_PER_DEGREE: f64 = 111_195.0;
+
+#[derive(Debug, Clone)]
+pub struct IndexedTown {
+    pub prefecture: String,
+    pub city: String,
+    pub town: String,
+    pub lat: f64,
+    pub lng: f64,
+}
+
+pub struct SpatialIndex {
+    towns: Vec<IndexedTown>,
+    cells: HashMap<(i32, i32), Vec<usize>>,
+}
+
+impl SpatialIndex {
+    /// Indexes every town in the dataset that has coordinates.
+    pub fn build(dataset: &Dataset) -> Self {
+        let mut index = Self {
+            towns: Vec::new(),
+            cells: HashMap::new(),
+        };
+        for prefecture in dataset.prefectures() {
+            for city in &prefecture.cities {
+                for town in &city.towns {
+                    let (Some(lat), Some(lng)) = (town.lat, town.lng) else {
+                        continue;
+                    };
+                    index
+                        .cells
+                        .entry(cell(lat, lng))
+                        .or_default()
+                        .push(index.towns.len());
+                    index.towns.push(IndexedTown {
+                        prefecture: prefecture.name.clone(),
+                        city: city.name.clone(),
+                        town: town.town.clone(),
+                        lat,
+                        lng,
+                    });
+                }
+            }
+        }
+        index
+    }
+
+    pub fn len(&self) -> usize {
+        self.towns.len()
+    }
+
+    /// Returns the town closest to the point and its distance in meters.
+    pub fn nearest(&self, lat: f64, lng: f64) -> Option<(&IndexedTown, f64)> {
+        let (row, column) = cell(lat, lng);
+        // Smallest distance covered by one ring of cells around the query.
+        let ring_meters = CELL_DEGREES * METERS_PER_DEGREE * lat.to_radians().cos().max(0.1);
+        let max_rings = (MAX_DISTANCE_METERS / ring_meters).ceil() as i32;
+
+        let mut best: Option<(usize, f64)> = None;
+        for ring in 0..=max_rings {
+            for (r, c) in ring_cells(row, column, ring) {
+                for &i in self.cells.get(&(r, c)).into_iter().flatten() {
+                    let town = &self.towns[i];
+                    let distance = haversine_meters(lat, lng, town.lat, town.lng);
+                    if best.is_none_or(|(_, best_distance)| distance < best_distance) {
+                        best = Some((i, distance));
+                    }
+                }
+            }
+            // Anything in the next ring is at least `ring * ring_meters` away.
+            if best.is_some_and(|(_, distance)| distance <= ring as f64 * ring_meters) {
+                break;
+            }
+        }
+
+        best.filter(|(_, distance)| *distance <= MAX_DISTANCE_METERS)
+            .map(|(i, distance)| (&self.towns[i], distance))
+    }
+}
+
+fn cell(lat: f64, lng: f64) -> (i32, i32) {
+    (
+        (lat / CELL_DEGREES).floor() as i32,
+        (lng / CELL_DEGREES).floor() as i32,
+    )
+}
+
+/// Cells on the square ring `ring` steps away from `(row, column)`.
+fn ring_cells(row: i32, column: i32, ring: i32) -> impl Iterator<Item = (i32, i32)> {
+    (-ring..=ring).flat_map(move |dr| {
+        (-ring..=ring)
+            .filter(move |dc| dr.abs() == ring || dc.abs() == ring)
+            .map(move |dc| (row + dr, column + dc))
+    })
+}
+
+pub fn haversine_meters(lat1: f64, lng1: f64, lat2: f64, lng2: f64) -> f64 {
+    let (phi1, phi2) = (lat1.to_radians(), lat2.to_radians());
+    let d_phi = (lat2 - lat1).to_radians();
+    let d_lambda = (lng2 - lng1).to_radians();
+    let a = (d_phi / 2.0).sin().powi(2) + phi1.cos() * phi2.cos() * (d_lambda / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_METERS * a.sqrt().asin()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dataset;
+
+    #[test]
+    fn test_nearest_town() {
+        let index = SpatialIndex::build(&dataset::test_dataset());
+        assert_eq!(index.len(), 4);
+
+        // Tokyo Station
+        let (town, distance) = index.nearest(35.681236, 139.767125).unwrap();
+        assert_eq!(
+            (town.city.as_str(), town.town.as_str()),
+            ("千代田区", "丸の内一丁目")
+        );
+        assert!(distance > 100.0 && distance < 200.0, "{}", distance);
+
+        // Shibuya Station
+        let (town, _) = index.nearest(35.658034, 139.701636).unwrap();
+        assert_eq!(town.town, "渋谷一丁目");
+
+        // Osaka is far outside the Tokyo fixture.
+        assert!(index.nearest(34.702485, 135.495951).is_none());
+    }
+
+    #[test]
+    fn test_haversine_meters() {
+        let distance = haversine_meters(35.681236, 139.767125, 34.702485, 135.495951);
+        assert!((distance - 403_000.0).abs() < 2_000.0, "{}", distance);
+    }
+}
@@ -0,0 +1,295 @@
+//! Type-ahead completions for partially typed addresses.
+//!
+//! A query is completed one level at a time: a prefecture name prefix yields
+//! prefectures, a full prefecture followed by a partial city yields that
+//! prefecture's cities, and a full city followed by a partial town yields its
+//! towns. City and town lists come through [`MasterDataClient`], so they are
+//! served from the offline dataset or the master data cache the parser fills.
+//!
+//! Queries written only in kana are also matched against the readings in the
+//! postal code dataset, when one is loaded.
+
+use crate::dataset;
+use crate::master_data::MasterDataClient;
+use crate::normalize::normalize_characters;
+use crate::postal_code::PostalCodeIndex;
+use japanese_address_parser::http::client::ApiClient;
+use serde::Serialize;
+use std::collections::HashSet;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SuggestionKind {
+    Prefecture,
+    City,
+    Town,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Suggestion {
+    pub kind: SuggestionKind,
+    /// The completed address text, e.g. `東京都渋谷区神宮前一丁目`.
+    pub label: String,
+    pub prefecture: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub city: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub town: Option<String>,
+}
+
+impl Suggestion {
+    fn new(kind: SuggestionKind, prefecture: &str, city: Option<&str>, town: Option<&str>) -> Self {
+        let label = [Some(prefecture), city, town]
+            .into_iter()
+            .flatten()
+            .collect();
+        Self {
+            kind,
+            label,
+            prefecture: prefecture.to_string(),
+            city: city.map(str::to_string),
+            town: town.map(str::to_string),
+        }
+    }
+}
+
+/// A place name with its katakana reading.
+struct Reading {
+    reading: String,
+    suggestion: Suggestion,
+}
+
+/// Place names of the postal code dataset, sorted by reading.
+pub struct ReadingIndex {
+    readings: Vec<Reading>,
+}
+
+impl ReadingIndex {
+    pub fn build(index: &PostalCodeIndex) -> Self {
+        let mut seen = HashSet::new();
+        let mut readings = Vec::new();
+        let mut add = |reading: &str, suggestion: Suggestion| {
+            if seen.insert((suggestion.kind, suggestion.label.clone())) {
+                readings.push(Reading {
+                    reading: reading.to_string(),
+                    suggestion,
+                });
+            }
+        };
+        for entry in index.entries() {
+            let (prefecture, city) = (entry.prefecture.as_str(), entry.city.as_str());
+            add(
+                &entry.prefecture_kana,
+                Suggestion::new(SuggestionKind::Prefecture, prefecture, None, None),
+            );
+            add(
+                &entry.city_kana,
+                Suggestion::new(SuggestionKind::City, prefecture, Some(city), None),
+            );
+            if let (Some(town), Some(kana)) = (&entry.town, &entry.town_kana) {
+                add(
+                    kana,
+                    Suggestion::new(SuggestionKind::Town, prefecture, Some(city), Some(town)),
+                );
+            }
+        }
+        readings.sort_by(|a, b| a.reading.cmp(&b.reading));
+        Self { readings }
+    }
+
+    fn find(&self, query: &str) -> Vec<Suggestion> {
+        let start = self
+            .readings
+            .partition_point(|r| r.reading.as_str() < query);
+        self.readings[start..]
+            .iter()
+            .take_while(|r| r.reading.starts_with(query))
+            .map(|r| r.suggestion.clone())
+            .collect()
+    }
+}
+
+/// Returns up to `limit` completions for `query`, broader levels first.
+/// Kana queries are also looked up in `readings` when it is given.
+pub async fn suggest(
+    client: &MasterDataClient,
+    readings: Option<&ReadingIndex>,
+    query: &str,
+    limit: usize,
+) -> Vec<Suggestion> {
+    let query: String = normalize_characters(query)
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect();
+    if query.is_empty() {
+        return Vec::new();
+    }
+
+    let mut suggestions = by_name(client, &query).await;
+    if let (Some(reading), Some(readings)) = (to_katakana(&query), readings) {
+        suggestions.extend(readings.find(&reading));
+    }
+
+    // Stable, so each level keeps the master data order among equal lengths.
+    suggestions.sort_by_key(|s| (s.kind, s.label.chars().count()));
+    let mut seen = HashSet::new();
+    suggestions.retain(|s| seen.insert(s.label.clone()));
+    suggestions.truncate(limit);
+    suggestions
+}
+
+async fn by_name(client: &MasterDataClient, query: &str) -> Vec<Suggestion> {
+    let prefectures = jisx0401::Prefecture::values();
+    let Some(prefecture) = prefectures.clone().find(|p| query.starts_with(p.name_ja())) else {
+        let mut suggestions: Vec<Suggestion> = prefectures
+            .filter(|p| p.name_ja().starts_with(query))
+            .map(|p| Suggestion::new(SuggestionKind::Prefecture, p.name_ja(), None, None))
+            .collect();
+        // Without a prefecture, cities can only be searched in the offline
+        // dataset; fetching every prefecture master per keystroke is too slow.
+        if let Some(dataset) = client.dataset() {
+            for prefecture in dataset.prefectures() {
+                for city in prefecture
+                    .cities
+                    .iter()
+                    .filter(|c| c.name.starts_with(query))
+                {
+                    suggestions.push(Suggestion::new(
+                        SuggestionKind::City,
+                        &prefecture.name,
+                        Some(&city.name),
+                        None,
+                    ));
+                }
+            }
+        }
+        return suggestions;
+    };
+
+    let prefecture = prefecture.name_ja();
+    let rest = &query[prefecture.len()..];
+    let Ok(master) = client
+        .fetch::<dataset::PrefectureMaster>(&dataset::prefecture_master_url(prefecture))
+        .await
+    else {
+        return Vec::new();
+    };
+
+    // Prefer the longest city name the query starts with (`市川市` over `市`).
+    let city = master
+        .cities
+        .iter()
+        .filter(|city| rest.starts_with(city.as_str()))
+        .max_by_key(|city| city.len());
+    let Some(city) = city else {
+        return master
+            .cities
+            .iter()
+            .filter(|city| city.starts_with(rest))
+            .map(|city| Suggestion::new(SuggestionKind::City, prefecture, Some(city), None))
+            .collect();
+    };
+
+    let rest = &rest[city.len()..];
+    let towns: Vec<dataset::Town> = client
+        .fetch(&dataset::city_master_url(prefecture, city))
+        .await
+        .unwrap_or_default();
+    towns
+        .iter()
+        .filter(|town| town.town.starts_with(rest))
+        .map(|town| {
+            Suggestion::new(
+                SuggestionKind::Town,
+                prefecture,
+                Some(city),
+                Some(&town.town),
+            )
+        })
+        .collect()
+}
+
+/// Converts a query written only in kana to katakana, or `None` if it
+/// contains anything else.
+fn to_katakana(query: &str) -> Option<String> {
+    query
+        .chars()
+        .map(|c| match c {
+            'ぁ'..='ゖ' => char::from_u32(c as u32 + 0x60),
+            'ァ'..='ヺ' | 'ー' => Some(c),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::postal_code;
+
+    fn labels(suggestions: &[Suggestion]) -> Vec<&str> {
+        suggestions.iter().map(|s| s.label.as_str()).collect()
+    }
+
+    fn client() -> MasterDataClient {
+        MasterDataClient::with_dataset(Some(dataset::test_dataset()))
+    }
+
+    #[tokio::test]
+    async fn test_suggest_completes_each_level() {
+        let client = client();
+        let suggest = |query, limit| suggest(&client, None, query, limit);
+
+        assert_eq!(labels(&suggest("東", 10).await), vec!["東京都"]);
+        assert_eq!(
+            labels(&suggest("東京都", 10).await),
+            vec!["東京都渋谷区", "東京都千代田区"]
+        );
+        assert_eq!(
+            labels(&suggest("東京都千", 10).await),
+            vec!["東京都千代田区"]
+        );
+
+        let towns = suggest("東京都渋谷区神宮", 10).await;
+        assert_eq!(
+            labels(&towns),
+            vec!["東京都渋谷区神宮前一丁目", "東京都渋谷区神宮前二丁目"]
+        );
+        assert_eq!(towns[0].kind, SuggestionKind::Town);
+        assert_eq!(towns[0].town.as_deref(), Some("神宮前一丁目"));
+
+        assert_eq!(suggest("東京都渋谷区", 1).await.len(), 1);
+        assert!(suggest("大阪府大阪市", 10).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_suggest_city_without_prefecture() {
+        let suggestions = suggest(&client(), None, "渋谷", 10).await;
+        assert_eq!(labels(&suggestions), vec!["東京都渋谷区"]);
+        assert_eq!(suggestions[0].kind, SuggestionKind::City);
+    }
+
+    #[tokio::test]
+    async fn test_suggest_by_kana_reading() {
+        let client = client();
+        let readings = ReadingIndex::build(&postal_code::test_index());
+        let suggest = |query| suggest(&client, Some(&readings), query, 10);
+
+        assert_eq!(
+            labels(&suggest("しぶや").await),
+            vec!["東京都渋谷区", "東京都渋谷区渋谷"]
+        );
+        assert_eq!(labels(&suggest("ﾄｳｷｮｳ").await), vec!["東京都"]);
+        assert_eq!(
+            labels(&suggest("ジングウ").await),
+            vec!["東京都渋谷区神宮前"]
+        );
+    }
+
+    #[test]
+    fn test_to_katakana() {
+        assert_eq!(to_katakana("しぶや").as_deref(), Some("シブヤ"));
+        assert_eq!(to_katakana("ジングウマエ").as_deref(), Some("ジングウマエ"));
+        assert_eq!(to_katakana("渋谷"), None);
+    }
+}
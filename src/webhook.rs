@@ -0,0 +1,352 @@
+//! Signed callbacks sent when a batch job finishes.
+//!
+//! The body is signed with HMAC-SHA256 using `WEBHOOK_SECRET`, over the
+//! timestamp and the body joined by a dot so a captured request cannot be
+//! replayed with a new timestamp:
+//!
+//! ```text
+//! x-webhook-timestamp: 1729071000
+//! x-webhook-signature: sha256=hex(hmac(secret, "1729071000.{body}"))
+//! ```
+//!
+//! A delivery succeeds on any 2xx response. Anything else is retried with
+//! exponential backoff, up to `WEBHOOK_MAX_ATTEMPTS` attempts in total.
+//!
+//! Callbacks are only sent to public addresses: a host that resolves to a
+//! loopback, private, link-local, shared (`100.64.0.0/10`) or unspecified
+//! address is refused unless it is listed in `WEBHOOK_ALLOWED_HOSTS`. The
+//! host is checked again before every attempt and the request is sent to the
+//! addresses that were checked, so a DNS answer changing in between cannot
+//! redirect it. Redirects are not followed.
+
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+
+const DEFAULT_MAX_ATTEMPTS: u32 = 5;
+const DEFAULT_RETRY_BASE_MS: u64 = 1000;
+const DEFAULT_TIMEOUT_SECS: u64 = 10;
+/// Longest wait between two attempts.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(3600);
+
+pub const SIGNATURE_HEADER: &str = "x-webhook-signature";
+pub const TIMESTAMP_HEADER: &str = "x-webhook-timestamp";
+
+#[derive(Debug, Clone)]
+pub struct WebhookConfig {
+    /// Callbacks are refused when no secret is configured.
+    pub secret: Option<String>,
+    pub max_attempts: u32,
+    /// Delay before the second attempt, doubled for each one after.
+    pub retry_base: Duration,
+    pub timeout: Duration,
+    /// Hosts and IP addresses callbacks may be sent to even though they are
+    /// not public.
+    pub allowed_hosts: Vec<String>,
+}
+
+impl WebhookConfig {
+    pub fn from_env() -> Self {
+        let parse = |name: &str, default: u64| {
+            std::env::var(name)
+                .ok()
+                .and_then(|value| value.parse::<u64>().ok())
+                .unwrap_or(default)
+        };
+        Self {
+            secret: std::env::var("WEBHOOK_SECRET")
+                .ok()
+                .filter(|secret| !secret.is_empty()),
+            max_attempts: (parse("WEBHOOK_MAX_ATTEMPTS", DEFAULT_MAX_ATTEMPTS.into()) as u32)
+                .max(1),
+            retry_base: Duration::from_millis(parse(
+                "WEBHOOK_RETRY_BASE_MS",
+                DEFAULT_RETRY_BASE_MS,
+            )),
+            timeout: Duration::from_secs(parse("WEBHOOK_TIMEOUT_SECS", DEFAULT_TIMEOUT_SECS)),
+            allowed_hosts: std::env::var("WEBHOOK_ALLOWED_HOSTS")
+                .unwrap_or_default()
+                .split(',')
+                .map(|host| host.trim().to_string())
+                .filter(|host| !host.is_empty())
+                .collect(),
+        }
+    }
+
+    /// Checks that `url` is an http or https URL whose host resolves only to
+    /// public addresses, or is allowed explicitly. Returns the addresses the
+    /// host resolved to.
+    pub async fn validate_url(&self, url: &str) -> Result<Vec<SocketAddr>, String> {
+        let parsed = reqwest::Url::parse(url)
+            .ok()
+            .filter(|url| matches!(url.scheme(), "http" | "https"))
+            .ok_or_else(|| {
+                format!(
+                    "Invalid 'callback_url' '{}' (expected an http or https URL)",
+                    url
+                )
+            })?;
+        let (Some(host), Some(port)) = (parsed.host_str(), parsed.port_or_known_default()) else {
+            return Err(format!("Invalid 'callback_url' '{}' (missing host)", url));
+        };
+        // IPv6 hosts are written in brackets.
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        let addresses: Vec<SocketAddr> = tokio::net::lookup_host((host, port))
+            .await
+            .map_err(|e| format!("Failed to resolve 'callback_url' host '{}': {}", host, e))?
+            .collect();
+        if self.is_allowed(host) {
+            return Ok(addresses);
+        }
+        for address in &addresses {
+            let ip = address.ip();
+            if !is_public(ip) && !self.is_allowed(&ip.to_string()) {
+                return Err(format!(
+                    "'callback_url' host '{}' resolves to {}, which is not a public address",
+                    host, ip
+                ));
+            }
+        }
+        Ok(addresses)
+    }
+
+    fn is_allowed(&self, host: &str) -> bool {
+        self.allowed_hosts
+            .iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(host))
+    }
+
+    /// How long to wait before attempt number `attempt` (1-based).
+    fn retry_delay(&self, attempt: u32) -> Duration {
+        if attempt <= 1 {
+            return Duration::ZERO;
+        }
+        let factor = 2u32.saturating_pow(attempt - 2);
+        self.retry_base.saturating_mul(factor).min(MAX_RETRY_DELAY)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CallbackStatus {
+    /// Not sent yet, or still being retried.
+    Pending,
+    Delivered,
+    /// Every attempt failed.
+    Failed,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeliveryAttempt {
+    /// 1-based attempt number.
+    pub attempt: u32,
+    pub sent_at: DateTime<Utc>,
+    /// Response status, when a response was received.
+    pub status_code: Option<u16>,
+    /// Why the attempt failed, if it did.
+    pub error: Option<String>,
+    pub duration_ms: u64,
+}
+
+impl DeliveryAttempt {
+    pub fn succeeded(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Returns the `x-webhook-signature` value for `body` sent at `timestamp`.
+pub fn sign(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(format!("{}.", timestamp).as_bytes());
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// Whether `ip` is outside the loopback, private, link-local, shared and
+/// unspecified ranges.
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [first, second, ..] = ip.octets();
+            // 100.64.0.0/10, used for carrier-grade NAT.
+            let shared = first == 100 && second & 0xc0 == 64;
+            // 0.0.0.0/8, which some systems route to the local host.
+            let this_network = first == 0;
+            !(ip.is_loopback() || ip.is_private() || ip.is_link_local() || shared || this_network)
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public(IpAddr::V4(ip)),
+            None => {
+                let segment = ip.segments()[0];
+                let unique_local = segment & 0xfe00 == 0xfc00;
+                let link_local = segment & 0xffc0 == 0xfe80;
+                !(ip.is_loopback() || ip.is_unspecified() || unique_local || link_local)
+            }
+        },
+    }
+}
+
+/// A client that connects to `url`'s host only at `addresses`.
+fn client(url: &str, addresses: &[SocketAddr]) -> reqwest::Result<reqwest::Client> {
+    // A redirect could lead to an address the URL check would refuse.
+    let mut builder = reqwest::Client::builder().redirect(reqwest::redirect::Policy::none());
+    if let Some(host) = reqwest::Url::parse(url)
+        .ok()
+        .and_then(|url| url.domain().map(str::to_string))
+    {
+        builder = builder.resolve_to_addrs(&host, addresses);
+    }
+    builder.build()
+}
+
+/// Makes delivery attempt number `attempt`, waiting out its backoff delay
+/// first.
+pub async fn attempt(
+    config: &WebhookConfig,
+    url: &str,
+    body: &[u8],
+    attempt: u32,
+) -> DeliveryAttempt {
+    tokio::time::sleep(config.retry_delay(attempt)).await;
+
+    let sent_at = Utc::now();
+    let start_time = Instant::now();
+    // The host may resolve differently from when the job was submitted.
+    let client = config.validate_url(url).await.and_then(|addresses| {
+        client(url, &addresses).map_err(|e| format!("Failed to build callback client: {}", e))
+    });
+    let client = match client {
+        Ok(client) => client,
+        Err(error) => {
+            return DeliveryAttempt {
+                attempt,
+                sent_at,
+                status_code: None,
+                error: Some(error),
+                duration_ms: start_time.elapsed().as_millis() as u64,
+            }
+        }
+    };
+    let timestamp = sent_at.timestamp();
+    let mut request = client
+        .post(url)
+        .timeout(config.timeout)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header(TIMESTAMP_HEADER, timestamp.to_string())
+        .body(body.to_vec());
+    if let Some(secret) = &config.secret {
+        request = request.header(SIGNATURE_HEADER, sign(secret, timestamp, body));
+    }
+    let (status_code, error) = match request.send().await {
+        Ok(response) if response.status().is_success() => (Some(response.status().as_u16()), None),
+        Ok(response) => (
+            Some(response.status().as_u16()),
+            Some(format!("Callback returned {}", response.status())),
+        ),
+        Err(e) => (None, Some(format!("Callback request failed: {}", e))),
+    };
+    DeliveryAttempt {
+        attempt,
+        sent_at,
+        status_code,
+        error,
+        duration_ms: start_time.elapsed().as_millis() as u64,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sign() {
+        // echo -n '1700000000.{"a":1}' | openssl dgst -sha256 -hmac secret
+        assert_eq!(
+            sign("secret", 1_700_000_000, br#"{"a":1}"#),
+            "sha256=49f24e537407743fa4a0242bb63b94b9a47ee99cbbe071ccd8a22550ae411686"
+        );
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        let config = WebhookConfig {
+            secret: None,
+            max_attempts: 5,
+            retry_base: Duration::from_secs(1),
+            timeout: Duration::from_secs(1),
+            allowed_hosts: Vec::new(),
+        };
+        let delays: Vec<u64> = (1..=5)
+            .map(|attempt| config.retry_delay(attempt).as_secs())
+            .collect();
+        assert_eq!(delays, vec![0, 1, 2, 4, 8]);
+    }
+
+    #[tokio::test]
+    async fn test_client_connects_to_checked_addresses() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let receiver = axum::Router::new().route("/hook", axum::routing::post(|| async { "ok" }));
+        tokio::spawn(async move { axum::serve(listener, receiver).await });
+
+        // `.invalid` never resolves, so only the given address can be reached.
+        let url = format!("http://callback.invalid:{}/hook", address.port());
+        let response = client(&url, &[address])
+            .unwrap()
+            .post(&url)
+            .send()
+            .await
+            .unwrap();
+        assert!(response.status().is_success());
+    }
+
+    #[tokio::test]
+    async fn test_validate_url() {
+        let mut config = WebhookConfig {
+            secret: None,
+            max_attempts: 1,
+            retry_base: Duration::ZERO,
+            timeout: Duration::from_secs(1),
+            allowed_hosts: Vec::new(),
+        };
+        assert!(config
+            .validate_url("https://93.184.215.14/hooks/jobs")
+            .await
+            .is_ok());
+        assert!(config.validate_url("ftp://example.com").await.is_err());
+        assert!(config.validate_url("not a url").await.is_err());
+
+        for url in [
+            "http://127.0.0.1:8080/hook",
+            "http://localhost/hook",
+            "http://10.0.0.1/hook",
+            "http://192.168.1.1/hook",
+            "http://169.254.169.254/latest/meta-data",
+            "http://0.0.0.0/hook",
+            "http://0.1.2.3/hook",
+            "http://100.64.0.1/hook",
+            "http://100.127.255.254/hook",
+            "http://[::1]/hook",
+            "http://[fd00::1]/hook",
+            "http://[fe80::1]/hook",
+            "http://[::ffff:127.0.0.1]/hook",
+        ] {
+            let error = config.validate_url(url).await.unwrap_err();
+            assert!(error.contains("not a public address"), "{}: {}", url, error);
+        }
+
+        // Just outside 100.64.0.0/10.
+        assert!(config.validate_url("http://100.128.0.1/hook").await.is_ok());
+
+        config.allowed_hosts = vec!["127.0.0.1".to_string()];
+        assert_eq!(
+            config.validate_url("http://127.0.0.1:8080/hook").await,
+            Ok(vec!["127.0.0.1:8080".parse().unwrap()])
+        );
+        assert!(config.validate_url("http://10.0.0.1/hook").await.is_err());
+    }
+}